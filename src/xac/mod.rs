@@ -1,4 +1,12 @@
 pub(crate) mod xac_enum;
+pub(crate) mod xac_error;
+pub(crate) mod xac_gltf;
+pub(crate) mod xac_obj;
 pub(crate) mod xac_parser;
+pub(crate) mod xac_pose;
+pub(crate) mod xac_skin;
 pub(crate) mod xac_struct;
+#[cfg(test)]
+pub(crate) mod xac_test_util;
 pub(crate) mod xac_util;
+pub(crate) mod xac_writer;
@@ -1,8 +1,12 @@
 #![allow(dead_code)]
+#![allow(clippy::enum_variant_names)]
 pub(crate) enum XacChunkType {
+    // Per-node chunk of files that predate the node hierarchy chunk.
+    XacNodeId = 0,
     XacMeshId = 1,
     XacSkinningId = 2,
     XacMaterialDefinitionId = 3,
+    XacMaterialLayerId = 4,
     XacShaderMaterialId = 5,
     XacMetadataId = 7,
     XacNodeHierarchyId = 11,
@@ -10,6 +14,12 @@ pub(crate) enum XacChunkType {
     XacMaterialTotalId = 13,
 }
 
+// Order in which a node's scale and rotation are applied, from the header.
+pub(crate) enum XacMultiplyOrder {
+    XacScaleRotTransId = 0,
+    XacRotScaleTransId = 1,
+}
+
 pub(crate) enum XacVerticesAttributeType {
     XacPositionId = 0,
     XacNormalId = 1,
@@ -34,3 +44,20 @@ pub(crate) enum XacPhonemeType {
     XacWId = 0x400,
     XacRERId = 0x800,
 }
+
+pub(crate) enum XacLayerType {
+    XacUnknownLayerId = 0,
+    XacAmbientLayerId = 1,
+    XacDiffuseLayerId = 2,
+    XacSpecularLayerId = 3,
+    XacOpacityLayerId = 4,
+    XacBumpLayerId = 5,
+    XacSelfIlluminationLayerId = 6,
+    XacShineLayerId = 7,
+    XacShineStrengthLayerId = 8,
+    XacFilterColorLayerId = 9,
+    XacReflectLayerId = 10,
+    XacRefractLayerId = 11,
+    XacEnvironmentLayerId = 12,
+    XacDisplacementLayerId = 13,
+}
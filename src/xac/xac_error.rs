@@ -0,0 +1,153 @@
+#![allow(dead_code)]
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum XacError {
+    Io {
+        chunk_type: Option<i32>,
+        offset: u64,
+        source: io::Error,
+    },
+    InvalidMagic {
+        chunk_type: Option<i32>,
+        offset: u64,
+        magic: [u8; 4],
+    },
+    UnsupportedVersion {
+        chunk_type: Option<i32>,
+        offset: u64,
+        major_version: u8,
+        minor_version: u8,
+    },
+    UnsupportedChunkVersion {
+        chunk_type: Option<i32>,
+        offset: u64,
+        version: i32,
+    },
+    TruncatedChunk {
+        chunk_type: Option<i32>,
+        offset: u64,
+    },
+    InconsistentCount {
+        chunk_type: Option<i32>,
+        offset: u64,
+        message: String,
+    },
+}
+
+impl XacError {
+    pub(crate) fn inconsistent(message: impl Into<String>) -> XacError {
+        XacError::InconsistentCount {
+            chunk_type: None,
+            offset: 0,
+            message: message.into(),
+        }
+    }
+
+    pub fn chunk_type(&self) -> Option<i32> {
+        match self {
+            XacError::Io { chunk_type, .. }
+            | XacError::InvalidMagic { chunk_type, .. }
+            | XacError::UnsupportedVersion { chunk_type, .. }
+            | XacError::UnsupportedChunkVersion { chunk_type, .. }
+            | XacError::TruncatedChunk { chunk_type, .. }
+            | XacError::InconsistentCount { chunk_type, .. } => *chunk_type,
+        }
+    }
+
+    pub fn offset(&self) -> u64 {
+        match self {
+            XacError::Io { offset, .. }
+            | XacError::InvalidMagic { offset, .. }
+            | XacError::UnsupportedVersion { offset, .. }
+            | XacError::UnsupportedChunkVersion { offset, .. }
+            | XacError::TruncatedChunk { offset, .. }
+            | XacError::InconsistentCount { offset, .. } => *offset,
+        }
+    }
+
+    // Errors raised inside a chunk reader do not know where they happened,
+    // the chunk loop fills in the chunk type and stream position afterwards.
+    pub(crate) fn locate(mut self, chunk: Option<i32>, position: u64) -> XacError {
+        match &mut self {
+            XacError::Io {
+                chunk_type, offset, ..
+            }
+            | XacError::InvalidMagic {
+                chunk_type, offset, ..
+            }
+            | XacError::UnsupportedVersion {
+                chunk_type, offset, ..
+            }
+            | XacError::UnsupportedChunkVersion {
+                chunk_type, offset, ..
+            }
+            | XacError::TruncatedChunk { chunk_type, offset }
+            | XacError::InconsistentCount {
+                chunk_type, offset, ..
+            } => {
+                *chunk_type = chunk;
+                *offset = position;
+            }
+        }
+        self
+    }
+}
+
+impl From<io::Error> for XacError {
+    fn from(source: io::Error) -> Self {
+        if source.kind() == io::ErrorKind::UnexpectedEof {
+            XacError::TruncatedChunk {
+                chunk_type: None,
+                offset: 0,
+            }
+        } else {
+            XacError::Io {
+                chunk_type: None,
+                offset: 0,
+                source,
+            }
+        }
+    }
+}
+
+impl fmt::Display for XacError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XacError::Io { source, .. } => write!(f, "I/O error: {}", source)?,
+            XacError::InvalidMagic { magic, .. } => write!(
+                f,
+                "Not an XAC file: invalid header magic {:?}",
+                String::from_utf8_lossy(magic)
+            )?,
+            XacError::UnsupportedVersion {
+                major_version,
+                minor_version,
+                ..
+            } => write!(
+                f,
+                "Unsupported .xac version: expected v1.x, file is {}.{}",
+                major_version, minor_version
+            )?,
+            XacError::UnsupportedChunkVersion { version, .. } => {
+                write!(f, "Unsupported chunk version {}", version)?
+            }
+            XacError::TruncatedChunk { .. } => write!(f, "Unexpected end of data")?,
+            XacError::InconsistentCount { message, .. } => write!(f, "{}", message)?,
+        }
+        match self.chunk_type() {
+            Some(chunk_type) => write!(f, " (chunk {} at byte {})", chunk_type, self.offset()),
+            None => write!(f, " (at byte {})", self.offset()),
+        }
+    }
+}
+
+impl std::error::Error for XacError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            XacError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,1364 @@
+#![allow(dead_code)]
+use crate::xac::xac_enum::XacLayerType::{
+    XacBumpLayerId, XacDiffuseLayerId, XacOpacityLayerId, XacSelfIlluminationLayerId,
+};
+use crate::xac::xac_enum::XacMultiplyOrder::XacScaleRotTransId;
+use crate::xac::xac_pose::xac_local_matrix;
+use crate::xac::xac_skin::xac_skin_weights;
+use crate::xac::xac_struct::{
+    XacActorFile, XacActorMaterial, XacActorNode, XacActorSubMesh, XacMatrix44, XacNodeTransform,
+    XacQuaternion, XacVec3d,
+};
+use crate::xac::xac_util::{
+    xac_matrix44_inverse, xac_matrix44_normal, xac_matrix44_to_array, xac_matrix44_transform_point,
+    xac_matrix44_transform_vector, xac_normalize_vec3d,
+};
+use crate::xsm::xsm_structs::{Xsm, XsmBinding};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const GLTF_ARRAY_BUFFER: u32 = 34962;
+const GLTF_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const GLTF_UNSIGNED_BYTE: u32 = 5121;
+const GLTF_UNSIGNED_SHORT: u32 = 5123;
+const GLTF_UNSIGNED_INT: u32 = 5125;
+const GLTF_FLOAT: u32 = 5126;
+
+// JSON part of a glTF asset and the content of its single binary buffer.
+pub(crate) struct XacGltfDocument {
+    pub(crate) root: Map<String, Value>,
+    pub(crate) buffer_views: Vec<Value>,
+    pub(crate) accessors: Vec<Value>,
+    pub(crate) bin: Vec<u8>,
+}
+
+// Skinned glTF meshes ignore the transform of their node. Vertices that
+// XacActorFile::skin attaches rigidly to the mesh node are stored relative
+// to that node, so they are moved into bind pose model space with the
+// node's bind world matrix and bound to the node's joint, which poses them
+// the same way.
+struct XacGltfRigidVertices {
+    bind_world: XacMatrix44,
+    normal_matrix: XacMatrix44,
+    rigid: Vec<bool>,
+}
+
+impl XacActorFile {
+    pub fn export_gltf(&self, path: &str) -> io::Result<()> {
+        self.export_gltf_with_motions(path, &[])
+    }
+
+    pub fn export_glb(&self, path: &str) -> io::Result<()> {
+        self.export_glb_with_motions(path, &[])
+    }
+
+    // Writes `path` as glTF JSON and the binary buffer next to it, using the
+    // same file stem with a .bin extension. Every motion becomes a glTF
+    // animation of the actor with linear translation, rotation and scale
+    // tracks. glTF has no scale rotation, so scale rotation keys are dropped
+    // and animated nodes are scaled along their own axes.
+    pub fn export_gltf_with_motions(&self, path: &str, motions: &[&Xsm]) -> io::Result<()> {
+        let gltf_path = Path::new(path);
+        let bin_path = gltf_path.with_extension("bin");
+        let bin_file_name = match bin_path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => "buffer.bin".to_string(),
+        };
+
+        let mut gltf_file = BufWriter::new(File::create(gltf_path)?);
+        let mut bin_file = BufWriter::new(File::create(&bin_path)?);
+        self.write_gltf(&mut gltf_file, &mut bin_file, &bin_file_name, motions)?;
+        gltf_file.flush()?;
+        bin_file.flush()
+    }
+
+    // Binary glTF version of export_gltf_with_motions, with the same motion
+    // export and dropped scale rotation keys.
+    pub fn export_glb_with_motions(&self, path: &str, motions: &[&Xsm]) -> io::Result<()> {
+        let mut glb_file = BufWriter::new(File::create(path)?);
+        self.write_glb(&mut glb_file, motions)?;
+        glb_file.flush()
+    }
+
+    // Writer versions of the exports above. Motions are exported the same
+    // way, dropping scale rotation keys.
+    pub fn write_gltf<W: Write, B: Write>(
+        &self,
+        gltf: &mut W,
+        bin: &mut B,
+        bin_file_name: &str,
+        motions: &[&Xsm],
+    ) -> io::Result<()> {
+        self.gltf_document(motions)
+            .write_gltf(gltf, bin, bin_file_name)
+    }
+
+    pub fn write_glb<W: Write>(&self, glb: &mut W, motions: &[&Xsm]) -> io::Result<()> {
+        self.gltf_document(motions).write_glb(glb)
+    }
+
+    // Vertices are written as stored in the file, without converting to the
+    // glTF axis convention. Nodes are glTF nodes with the same index, and
+    // every node with a visual mesh gets a mesh with one primitive per
+    // submesh. Collision meshes are not exported.
+    pub(crate) fn gltf_document(&self, motions: &[&Xsm]) -> XacGltfDocument {
+        let mut document = XacGltfDocument {
+            root: Map::new(),
+            buffer_views: Vec::new(),
+            accessors: Vec::new(),
+            bin: Vec::new(),
+        };
+        document.root.insert(
+            "asset".to_string(),
+            json!({"version": "2.0", "generator": "orsha-parser"}),
+        );
+
+        let skinned = self.nodes.iter().any(|node| {
+            node.visual_mesh
+                .sub_meshes
+                .iter()
+                .any(|sub_mesh| !sub_mesh.vertex_influences.is_empty())
+        });
+        let skin_id = if skinned {
+            Some(document.push_skin(self))
+        } else {
+            None
+        };
+
+        let bindings: Vec<XsmBinding> = motions.iter().map(|xsm| xsm.bind(self)).collect();
+        let mut nodes: Vec<Value> = Vec::new();
+        let mut meshes: Vec<Value> = Vec::new();
+        let mut scene_nodes: Vec<usize> = Vec::new();
+        for (node_id, node) in self.nodes.iter().enumerate() {
+            let animated = bindings
+                .iter()
+                .any(|binding| binding.node_submotions[node_id].is_some());
+            let mut gltf_node = gltf_node_transform(node, self.header.multiply_order, animated);
+            gltf_node.insert("name".to_string(), json!(node.name));
+            let children: Vec<usize> = self
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(_, child)| child.parent_node_id == node_id as i32)
+                .map(|(child_id, _)| child_id)
+                .collect();
+            if !children.is_empty() {
+                gltf_node.insert("children".to_string(), json!(children));
+            }
+            if node.parent_node_id < 0 || node.parent_node_id as usize >= self.nodes.len() {
+                scene_nodes.push(node_id);
+            }
+
+            if let Some(mesh) = document.push_mesh(self, node_id, node) {
+                gltf_node.insert("mesh".to_string(), json!(meshes.len()));
+                let node_skinned = node
+                    .visual_mesh
+                    .sub_meshes
+                    .iter()
+                    .any(|sub_mesh| !sub_mesh.vertex_influences.is_empty());
+                if let (true, Some(skin_id)) = (node_skinned, skin_id) {
+                    gltf_node.insert("skin".to_string(), json!(skin_id));
+                }
+                meshes.push(mesh);
+            }
+            nodes.push(Value::Object(gltf_node));
+        }
+
+        document.insert_array("nodes", nodes);
+        document.insert_array("meshes", meshes);
+        document.root.insert("scene".to_string(), json!(0));
+        document.root.insert(
+            "scenes".to_string(),
+            json!([{"name": self.actor_name, "nodes": scene_nodes}]),
+        );
+        document.push_materials(&self.materials);
+
+        let animations: Vec<Value> = motions
+            .iter()
+            .zip(bindings.iter())
+            .enumerate()
+            .map(|(motion_id, (xsm, binding))| document.push_animation(motion_id, xsm, binding))
+            .collect();
+        document.insert_array("animations", animations);
+        document
+    }
+}
+
+impl XacGltfDocument {
+    pub(crate) fn write_gltf<W: Write, B: Write>(
+        mut self,
+        gltf: &mut W,
+        bin: &mut B,
+        bin_file_name: &str,
+    ) -> io::Result<()> {
+        let bin_data = std::mem::take(&mut self.bin);
+        let root = self.finish(bin_data.len(), Some(bin_file_name));
+        serde_json::to_writer_pretty(gltf, &root)?;
+        bin.write_all(&bin_data)
+    }
+
+    // Binary glTF: a 12 byte header, the JSON chunk padded with spaces and
+    // the buffer chunk padded with zeros, both to 4 bytes.
+    pub(crate) fn write_glb<W: Write>(mut self, glb: &mut W) -> io::Result<()> {
+        let mut bin = std::mem::take(&mut self.bin);
+        let root = self.finish(bin.len(), None);
+        let mut json_bytes = serde_json::to_vec(&root)?;
+        while !json_bytes.len().is_multiple_of(4) {
+            json_bytes.push(b' ');
+        }
+        while !bin.len().is_multiple_of(4) {
+            bin.push(0);
+        }
+
+        let mut length = 12 + 8 + json_bytes.len();
+        if !bin.is_empty() {
+            length += 8 + bin.len();
+        }
+        glb.write_all(b"glTF")?;
+        glb.write_all(&2u32.to_le_bytes())?;
+        glb.write_all(&(length as u32).to_le_bytes())?;
+        glb.write_all(&(json_bytes.len() as u32).to_le_bytes())?;
+        glb.write_all(b"JSON")?;
+        glb.write_all(&json_bytes)?;
+        if !bin.is_empty() {
+            glb.write_all(&(bin.len() as u32).to_le_bytes())?;
+            glb.write_all(b"BIN\0")?;
+            glb.write_all(&bin)?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self, bin_length: usize, uri: Option<&str>) -> Value {
+        if bin_length > 0 {
+            let mut buffer = json!({ "byteLength": bin_length });
+            if let Some(uri) = uri {
+                buffer["uri"] = json!(gltf_uri(uri));
+            }
+            self.root.insert("buffers".to_string(), json!([buffer]));
+        }
+        let buffer_views = std::mem::take(&mut self.buffer_views);
+        let accessors = std::mem::take(&mut self.accessors);
+        self.insert_array("bufferViews", buffer_views);
+        self.insert_array("accessors", accessors);
+        Value::Object(self.root)
+    }
+
+    // glTF does not allow empty arrays, so those are left out.
+    pub(crate) fn insert_array(&mut self, name: &str, values: Vec<Value>) {
+        if !values.is_empty() {
+            self.root.insert(name.to_string(), Value::Array(values));
+        }
+    }
+
+    fn push_buffer_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        while !self.bin.len().is_multiple_of(4) {
+            self.bin.push(0);
+        }
+        let mut buffer_view = json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": bytes.len(),
+        });
+        if let Some(target) = target {
+            buffer_view["target"] = json!(target);
+        }
+        self.bin.extend_from_slice(bytes);
+        self.buffer_views.push(buffer_view);
+        self.buffer_views.len() - 1
+    }
+
+    fn push_accessor(
+        &mut self,
+        bytes: &[u8],
+        target: Option<u32>,
+        component_type: u32,
+        normalized: bool,
+        count: usize,
+        accessor_type: &str,
+    ) -> usize {
+        let buffer_view = self.push_buffer_view(bytes, target);
+        let mut accessor = json!({
+            "bufferView": buffer_view,
+            "componentType": component_type,
+            "count": count,
+            "type": accessor_type,
+        });
+        if normalized {
+            accessor["normalized"] = json!(true);
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    // Float accessors with N components. `bounds` adds the per component
+    // min and max that glTF requires for positions and animation input.
+    pub(crate) fn push_floats<const N: usize>(
+        &mut self,
+        values: &[[f32; N]],
+        target: Option<u32>,
+        bounds: bool,
+    ) -> usize {
+        let bytes: Vec<u8> = values
+            .iter()
+            .flatten()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let accessor_type = match N {
+            1 => "SCALAR",
+            2 => "VEC2",
+            3 => "VEC3",
+            4 => "VEC4",
+            _ => "MAT4",
+        };
+        let accessor = self.push_accessor(
+            &bytes,
+            target,
+            GLTF_FLOAT,
+            false,
+            values.len(),
+            accessor_type,
+        );
+        if bounds && !values.is_empty() {
+            let mut min = values[0];
+            let mut max = values[0];
+            for value in values.iter() {
+                for component in 0..N {
+                    min[component] = min[component].min(value[component]);
+                    max[component] = max[component].max(value[component]);
+                }
+            }
+            self.accessors[accessor]["min"] = json!(min.to_vec());
+            self.accessors[accessor]["max"] = json!(max.to_vec());
+        }
+        accessor
+    }
+
+    fn push_skin(&mut self, xac: &XacActorFile) -> usize {
+        let bind_world = xac.pose_matrices(&xac.bind_pose()).world;
+        // Column major column vector matrices have the same layout as our
+        // row major row vector matrices.
+        let inverse_bind_matrices: Vec<[f32; 16]> = bind_world
+            .iter()
+            .map(|matrix| {
+                let inverse = xac_matrix44_inverse(matrix).unwrap_or_else(|| matrix.clone());
+                let rows = xac_matrix44_to_array(&inverse);
+                let mut values = [0.0f32; 16];
+                for (index, value) in rows.iter().flatten().enumerate() {
+                    values[index] = *value;
+                }
+                values
+            })
+            .collect();
+        let accessor = self.push_floats(&inverse_bind_matrices, None, false);
+        let joints: Vec<usize> = (0..xac.nodes.len()).collect();
+        let skins = vec![json!({
+            "name": xac.actor_name,
+            "inverseBindMatrices": accessor,
+            "joints": joints,
+        })];
+        self.insert_array("skins", skins);
+        0
+    }
+
+    fn push_mesh(
+        &mut self,
+        xac: &XacActorFile,
+        node_id: usize,
+        node: &XacActorNode,
+    ) -> Option<Value> {
+        let morph_targets: Vec<(usize, usize)> = xac
+            .morph_targets
+            .iter()
+            .enumerate()
+            .filter_map(|(morph_target_id, morph_target)| {
+                morph_target
+                    .deformation
+                    .iter()
+                    .position(|deformation| deformation.node_id == node_id)
+                    .map(|deformation_id| (morph_target_id, deformation_id))
+            })
+            .collect();
+
+        let skinned = node
+            .visual_mesh
+            .sub_meshes
+            .iter()
+            .any(|sub_mesh| !sub_mesh.vertex_influences.is_empty());
+        let bind_world = if skinned {
+            Some(xac.pose_matrices(&xac.bind_pose()).world[node_id].clone())
+        } else {
+            None
+        };
+
+        let mut primitives: Vec<Value> = Vec::new();
+        let mut vertex_offset: usize = 0;
+        for sub_mesh in node.visual_mesh.sub_meshes.iter() {
+            let num_vertices = sub_mesh.vertex_positions.len();
+            if num_vertices > 0 {
+                let rigid = bind_world.as_ref().map(|bind_world| XacGltfRigidVertices {
+                    bind_world: bind_world.clone(),
+                    normal_matrix: xac_matrix44_normal(bind_world),
+                    rigid: (0..num_vertices)
+                        .map(|vertex| {
+                            sub_mesh
+                                .vertex_influences
+                                .get(vertex)
+                                .and_then(|influences| {
+                                    xac_skin_weights(influences, xac.nodes.len())
+                                })
+                                .is_none()
+                        })
+                        .collect(),
+                });
+                let targets: Vec<Value> = morph_targets
+                    .iter()
+                    .map(|(morph_target_id, deformation_id)| {
+                        self.push_morph_target(
+                            xac,
+                            sub_mesh,
+                            vertex_offset,
+                            *morph_target_id,
+                            *deformation_id,
+                            rigid.as_ref(),
+                        )
+                    })
+                    .collect();
+                let mut primitive = self.push_primitive(xac, node_id, sub_mesh, rigid.as_ref());
+                if sub_mesh.material_id >= 0
+                    && (sub_mesh.material_id as usize) < xac.materials.len()
+                {
+                    primitive["material"] = json!(sub_mesh.material_id);
+                }
+                if !targets.is_empty() {
+                    primitive["targets"] = Value::Array(targets);
+                }
+                primitives.push(primitive);
+            }
+            vertex_offset += num_vertices;
+        }
+        if primitives.is_empty() {
+            return None;
+        }
+
+        let mut mesh = json!({"name": node.name, "primitives": primitives});
+        if !morph_targets.is_empty() {
+            let names: Vec<&str> = morph_targets
+                .iter()
+                .map(|(morph_target_id, _)| xac.morph_targets[*morph_target_id].name.as_str())
+                .collect();
+            mesh["weights"] = json!(vec![0.0f32; morph_targets.len()]);
+            mesh["extras"] = json!({ "targetNames": names });
+        }
+        Some(mesh)
+    }
+
+    // `rigid` is set for every primitive of a skinned mesh, see
+    // XacGltfRigidVertices.
+    fn push_primitive(
+        &mut self,
+        xac: &XacActorFile,
+        node_id: usize,
+        sub_mesh: &XacActorSubMesh,
+        rigid: Option<&XacGltfRigidVertices>,
+    ) -> Value {
+        let num_vertices = sub_mesh.vertex_positions.len();
+        let mut attributes = Map::new();
+
+        let positions: Vec<[f32; 3]> = sub_mesh
+            .vertex_positions
+            .iter()
+            .enumerate()
+            .map(|(vertex, position)| match rigid {
+                Some(rigid) => rigid.point(vertex, position),
+                None => [position.x, position.y, position.z],
+            })
+            .collect();
+        let accessor = self.push_floats(&positions, Some(GLTF_ARRAY_BUFFER), true);
+        attributes.insert("POSITION".to_string(), json!(accessor));
+
+        if sub_mesh.vertex_normals.len() == num_vertices {
+            let normals: Vec<[f32; 3]> = sub_mesh
+                .vertex_normals
+                .iter()
+                .enumerate()
+                .map(|(vertex, normal)| match rigid {
+                    Some(rigid) => rigid.normal(vertex, normal, true),
+                    None => [normal.x, normal.y, normal.z],
+                })
+                .collect();
+            let accessor = self.push_floats(&normals, Some(GLTF_ARRAY_BUFFER), false);
+            attributes.insert("NORMAL".to_string(), json!(accessor));
+        }
+        if sub_mesh.vertex_tangents.len() == num_vertices {
+            let tangents: Vec<[f32; 4]> = sub_mesh
+                .vertex_tangents
+                .iter()
+                .enumerate()
+                .map(|(vertex, tangent)| {
+                    let direction = XacVec3d {
+                        x: tangent.x,
+                        y: tangent.y,
+                        z: tangent.z,
+                    };
+                    let [x, y, z] = match rigid {
+                        Some(rigid) => rigid.vector(vertex, &direction, true),
+                        None => [tangent.x, tangent.y, tangent.z],
+                    };
+                    [x, y, z, tangent.w]
+                })
+                .collect();
+            let accessor = self.push_floats(&tangents, Some(GLTF_ARRAY_BUFFER), false);
+            attributes.insert("TANGENT".to_string(), json!(accessor));
+        }
+
+        // XAC and glTF both put the texture origin at the top left.
+        let uv_sets = sub_mesh
+            .vertex_uvs
+            .iter()
+            .filter(|uvs| uvs.len() == num_vertices);
+        for (uv_set, uvs) in uv_sets.enumerate() {
+            let uvs: Vec<[f32; 2]> = uvs.iter().map(|uv| [uv.x, uv.y]).collect();
+            let accessor = self.push_floats(&uvs, Some(GLTF_ARRAY_BUFFER), false);
+            attributes.insert(format!("TEXCOORD_{}", uv_set), json!(accessor));
+        }
+
+        // 32-bit colour sets come first, followed by the 128-bit ones.
+        let mut color_set: usize = 0;
+        for colors in sub_mesh.vertex_colors_32.iter() {
+            if colors.len() != num_vertices {
+                continue;
+            }
+            let bytes: Vec<u8> = colors
+                .iter()
+                .flat_map(|color| [color.x, color.y, color.z, color.w])
+                .collect();
+            let accessor = self.push_accessor(
+                &bytes,
+                Some(GLTF_ARRAY_BUFFER),
+                GLTF_UNSIGNED_BYTE,
+                true,
+                num_vertices,
+                "VEC4",
+            );
+            attributes.insert(format!("COLOR_{}", color_set), json!(accessor));
+            color_set += 1;
+        }
+        for colors in sub_mesh.vertex_colors_128.iter() {
+            if colors.len() != num_vertices {
+                continue;
+            }
+            let colors: Vec<[f32; 4]> = colors
+                .iter()
+                .map(|color| [color.x, color.y, color.z, color.w])
+                .collect();
+            let accessor = self.push_floats(&colors, Some(GLTF_ARRAY_BUFFER), false);
+            attributes.insert(format!("COLOR_{}", color_set), json!(accessor));
+            color_set += 1;
+        }
+
+        if rigid.is_some() {
+            self.push_joints_and_weights(&mut attributes, xac, node_id, sub_mesh);
+        }
+
+        let mut primitive = json!({ "attributes": attributes, "mode": 4 });
+        if !sub_mesh.indices.is_empty() {
+            let bytes: Vec<u8> = sub_mesh
+                .indices
+                .iter()
+                .flat_map(|index| index.to_le_bytes())
+                .collect();
+            let accessor = self.push_accessor(
+                &bytes,
+                Some(GLTF_ELEMENT_ARRAY_BUFFER),
+                GLTF_UNSIGNED_INT,
+                false,
+                sub_mesh.indices.len(),
+                "SCALAR",
+            );
+            primitive["indices"] = json!(accessor);
+        }
+        primitive
+    }
+
+    // Influences are written four per JOINTS_n/WEIGHTS_n set with the
+    // weights XacActorFile::skin uses. Joint indices are node ids since the
+    // skin lists every node. A vertex without such weights is bound to the
+    // mesh node, see XacGltfRigidVertices.
+    fn push_joints_and_weights(
+        &mut self,
+        attributes: &mut Map<String, Value>,
+        xac: &XacActorFile,
+        node_id: usize,
+        sub_mesh: &XacActorSubMesh,
+    ) {
+        let num_vertices = sub_mesh.vertex_positions.len();
+        let vertex_weights: Vec<Vec<(usize, f32)>> = (0..num_vertices)
+            .map(|vertex| {
+                sub_mesh
+                    .vertex_influences
+                    .get(vertex)
+                    .and_then(|influences| xac_skin_weights(influences, xac.nodes.len()))
+                    .unwrap_or_else(|| vec![(node_id, 1.0)])
+            })
+            .collect();
+        let num_sets = vertex_weights
+            .iter()
+            .map(|weights| weights.len().div_ceil(4))
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        for set in 0..num_sets {
+            let mut joints: Vec<u8> = Vec::with_capacity(num_vertices * 8);
+            let mut weights: Vec<[f32; 4]> = Vec::with_capacity(num_vertices);
+            for vertex_weights in vertex_weights.iter() {
+                let mut set_joints = [0u16; 4];
+                let mut set_weights = [0.0f32; 4];
+                for (slot, (joint, weight)) in
+                    vertex_weights.iter().skip(set * 4).take(4).enumerate()
+                {
+                    set_joints[slot] = *joint as u16;
+                    set_weights[slot] = *weight;
+                }
+                joints.extend(set_joints.iter().flat_map(|joint| joint.to_le_bytes()));
+                weights.push(set_weights);
+            }
+            let accessor = self.push_accessor(
+                &joints,
+                Some(GLTF_ARRAY_BUFFER),
+                GLTF_UNSIGNED_SHORT,
+                false,
+                num_vertices,
+                "VEC4",
+            );
+            attributes.insert(format!("JOINTS_{}", set), json!(accessor));
+            let accessor = self.push_floats(&weights, Some(GLTF_ARRAY_BUFFER), false);
+            attributes.insert(format!("WEIGHTS_{}", set), json!(accessor));
+        }
+    }
+
+    // Deformation vertex indices refer to original vertices, which are shared
+    // by every submesh vertex split from them.
+    fn push_morph_target(
+        &mut self,
+        xac: &XacActorFile,
+        sub_mesh: &XacActorSubMesh,
+        vertex_offset: usize,
+        morph_target_id: usize,
+        deformation_id: usize,
+        rigid: Option<&XacGltfRigidVertices>,
+    ) -> Value {
+        let deformation = &xac.morph_targets[morph_target_id].deformation[deformation_id];
+        let mut deltas: HashMap<usize, usize> = HashMap::new();
+        for (delta, vertex_index) in deformation.vertex_indices.iter().enumerate() {
+            deltas.insert(*vertex_index as usize, delta);
+        }
+
+        let num_vertices = sub_mesh.vertex_positions.len();
+        let mut positions = vec![[0.0f32; 3]; num_vertices];
+        let mut normals = vec![[0.0f32; 3]; num_vertices];
+        let mut tangents = vec![[0.0f32; 3]; num_vertices];
+        for vertex in 0..num_vertices {
+            let original_vertex = match sub_mesh.influence_range_indices.get(vertex) {
+                Some(index) => *index as usize,
+                None => vertex_offset + vertex,
+            };
+            let delta = match deltas.get(&original_vertex) {
+                Some(delta) => *delta,
+                None => continue,
+            };
+            // Offsets of rigid vertices move with them, without normalizing.
+            if let Some(offset) = deformation.position_offsets.get(delta) {
+                positions[vertex] = match rigid {
+                    Some(rigid) => rigid.vector(vertex, offset, false),
+                    None => [offset.x, offset.y, offset.z],
+                };
+            }
+            if let Some(offset) = deformation.normal_offsets.get(delta) {
+                normals[vertex] = match rigid {
+                    Some(rigid) => rigid.normal(vertex, offset, false),
+                    None => [offset.x, offset.y, offset.z],
+                };
+            }
+            if let Some(offset) = deformation.tangent_offsets.get(delta) {
+                tangents[vertex] = match rigid {
+                    Some(rigid) => rigid.vector(vertex, offset, false),
+                    None => [offset.x, offset.y, offset.z],
+                };
+            }
+        }
+
+        let mut target = Map::new();
+        let accessor = self.push_floats(&positions, Some(GLTF_ARRAY_BUFFER), true);
+        target.insert("POSITION".to_string(), json!(accessor));
+        if sub_mesh.vertex_normals.len() == num_vertices {
+            let accessor = self.push_floats(&normals, Some(GLTF_ARRAY_BUFFER), false);
+            target.insert("NORMAL".to_string(), json!(accessor));
+        }
+        if sub_mesh.vertex_tangents.len() == num_vertices {
+            let accessor = self.push_floats(&tangents, Some(GLTF_ARRAY_BUFFER), false);
+            target.insert("TANGENT".to_string(), json!(accessor));
+        }
+        Value::Object(target)
+    }
+
+    // Tracks become LINEAR samplers, glTF interpolates rotations with slerp
+    // like the sampler does. A track without keys is written as a single key
+    // holding the submotion's pose value, so every clip fully defines the
+    // nodes it animates. Scale rotation has no glTF equivalent and is left
+    // out. Keys that do not advance in time are dropped since glTF requires
+    // strictly increasing input times.
+    fn push_animation(&mut self, motion_id: usize, xsm: &Xsm, binding: &XsmBinding) -> Value {
+        let mut samplers: Vec<Value> = Vec::new();
+        let mut channels: Vec<Value> = Vec::new();
+        for (node_id, submotion_id) in binding.node_submotions.iter().enumerate() {
+            let submotion =
+                match submotion_id.and_then(|id| xsm.bone_animation.skeletal_submotion.get(id)) {
+                    Some(submotion) => submotion.decode(),
+                    None => continue,
+                };
+
+            let (times, positions) = gltf_track(
+                &submotion.pos_key,
+                |key| key.time,
+                |key| [key.pos.x, key.pos.y, key.pos.z],
+                [
+                    submotion.pose_pos.x,
+                    submotion.pose_pos.y,
+                    submotion.pose_pos.z,
+                ],
+            );
+            samplers.push(self.push_animation_sampler(&times, &positions));
+            channels.push(gltf_channel(samplers.len() - 1, node_id, "translation"));
+
+            let (times, rotations) = gltf_track(
+                &submotion.rot_key,
+                |key| key.time,
+                |key| [key.rot.x, key.rot.y, key.rot.z, key.rot.w],
+                [
+                    submotion.pose_rot.x,
+                    submotion.pose_rot.y,
+                    submotion.pose_rot.z,
+                    submotion.pose_rot.w,
+                ],
+            );
+            samplers.push(self.push_animation_sampler(&times, &rotations));
+            channels.push(gltf_channel(samplers.len() - 1, node_id, "rotation"));
+
+            let (times, scales) = gltf_track(
+                &submotion.scale_key,
+                |key| key.time,
+                |key| [key.scale.x, key.scale.y, key.scale.z],
+                [
+                    submotion.pose_scale.x,
+                    submotion.pose_scale.y,
+                    submotion.pose_scale.z,
+                ],
+            );
+            samplers.push(self.push_animation_sampler(&times, &scales));
+            channels.push(gltf_channel(samplers.len() - 1, node_id, "scale"));
+        }
+
+        let name = if xsm.metadata.motion_name.is_empty() {
+            format!("motion_{}", motion_id)
+        } else {
+            xsm.metadata.motion_name.clone()
+        };
+        json!({ "name": name, "channels": channels, "samplers": samplers })
+    }
+
+    fn push_animation_sampler<const N: usize>(
+        &mut self,
+        times: &[[f32; 1]],
+        values: &[[f32; N]],
+    ) -> Value {
+        let input = self.push_floats(times, None, true);
+        let output = self.push_floats(values, None, false);
+        json!({ "input": input, "output": output, "interpolation": "LINEAR" })
+    }
+
+    // Diffuse, bump and self illumination layers become the base colour,
+    // normal and emissive textures. Textures reference the layer name as an
+    // image uri, so the images are expected next to the exported file.
+    fn push_materials(&mut self, materials: &[XacActorMaterial]) {
+        let mut images: Vec<Value> = Vec::new();
+        let mut image_ids: HashMap<&str, usize> = HashMap::new();
+        let mut gltf_materials: Vec<Value> = Vec::new();
+        for material in materials.iter() {
+            let mut texture = |map_type: u8| -> Option<usize> {
+                let layer = material
+                    .layers
+                    .iter()
+                    .find(|layer| layer.map_type == map_type && !layer.name.is_empty())?;
+                let next_id = image_ids.len();
+                let image_id = *image_ids.entry(layer.name.as_str()).or_insert(next_id);
+                if image_id == images.len() {
+                    images.push(json!({ "uri": gltf_uri(&layer.name) }));
+                }
+                Some(image_id)
+            };
+            let base_color_texture = texture(XacDiffuseLayerId as u8);
+            let normal_texture = texture(XacBumpLayerId as u8);
+            let emissive_texture = texture(XacSelfIlluminationLayerId as u8);
+            let has_opacity_layer = material
+                .layers
+                .iter()
+                .any(|layer| layer.map_type == XacOpacityLayerId as u8);
+
+            let mut pbr = json!({
+                "baseColorFactor": [
+                    material.diffuse_color.x.clamp(0.0, 1.0),
+                    material.diffuse_color.y.clamp(0.0, 1.0),
+                    material.diffuse_color.z.clamp(0.0, 1.0),
+                    material.opacity.clamp(0.0, 1.0),
+                ],
+                "metallicFactor": 0.0,
+                "roughnessFactor": 1.0,
+            });
+            if let Some(texture) = base_color_texture {
+                pbr["baseColorTexture"] = json!({ "index": texture });
+            }
+            let mut gltf_material = json!({
+                "name": material.name,
+                "pbrMetallicRoughness": pbr,
+                "doubleSided": material.double_sided,
+            });
+            if material.opacity < 1.0 || has_opacity_layer {
+                gltf_material["alphaMode"] = json!("BLEND");
+            }
+            if let Some(texture) = normal_texture {
+                gltf_material["normalTexture"] = json!({ "index": texture });
+            }
+            if let Some(texture) = emissive_texture {
+                gltf_material["emissiveTexture"] = json!({ "index": texture });
+                gltf_material["emissiveFactor"] = json!([1.0, 1.0, 1.0]);
+            } else {
+                gltf_material["emissiveFactor"] = json!([
+                    material.emissive_color.x.clamp(0.0, 1.0),
+                    material.emissive_color.y.clamp(0.0, 1.0),
+                    material.emissive_color.z.clamp(0.0, 1.0),
+                ]);
+            }
+            gltf_materials.push(gltf_material);
+        }
+
+        // Images and textures share their index since every image is used
+        // by exactly one texture.
+        let textures: Vec<Value> = (0..images.len())
+            .map(|image_id| json!({ "source": image_id }))
+            .collect();
+        self.insert_array("materials", gltf_materials);
+        self.insert_array("textures", textures);
+        self.insert_array("images", images);
+    }
+}
+
+impl XacGltfRigidVertices {
+    fn point(&self, vertex: usize, value: &XacVec3d) -> [f32; 3] {
+        if !self.rigid[vertex] {
+            return [value.x, value.y, value.z];
+        }
+        let point = xac_matrix44_transform_point(&self.bind_world, value);
+        [point.x, point.y, point.z]
+    }
+
+    fn vector(&self, vertex: usize, value: &XacVec3d, normalize: bool) -> [f32; 3] {
+        self.transform(vertex, &self.bind_world, value, normalize)
+    }
+
+    fn normal(&self, vertex: usize, value: &XacVec3d, normalize: bool) -> [f32; 3] {
+        self.transform(vertex, &self.normal_matrix, value, normalize)
+    }
+
+    fn transform(
+        &self,
+        vertex: usize,
+        matrix: &XacMatrix44,
+        value: &XacVec3d,
+        normalize: bool,
+    ) -> [f32; 3] {
+        if !self.rigid[vertex] {
+            return [value.x, value.y, value.z];
+        }
+        let mut vector = xac_matrix44_transform_vector(matrix, value);
+        if normalize {
+            vector = xac_normalize_vec3d(&vector);
+        }
+        [vector.x, vector.y, vector.z]
+    }
+}
+
+// glTF nodes only have translation, rotation and scale in that order, so
+// nodes whose scale depends on the scale rotation or the multiply order
+// are written as a matrix instead. Animated nodes must use TRS in glTF and
+// always do, dropping the scale rotation.
+fn gltf_node_transform(
+    node: &XacActorNode,
+    multiply_order: u8,
+    animated: bool,
+) -> Map<String, Value> {
+    let transform = XacNodeTransform {
+        position: node.position.clone(),
+        rotation: node.rotation.clone(),
+        scale: node.scale.clone(),
+        scale_rotation: node.scale_rotation.clone(),
+    };
+    let mut gltf_node = Map::new();
+    if animated || gltf_has_trs(&transform, multiply_order) {
+        gltf_node.insert(
+            "translation".to_string(),
+            json!([node.position.x, node.position.y, node.position.z]),
+        );
+        gltf_node.insert(
+            "rotation".to_string(),
+            json!(gltf_quaternion(&node.rotation)),
+        );
+        gltf_node.insert(
+            "scale".to_string(),
+            json!([node.scale.x, node.scale.y, node.scale.z]),
+        );
+    } else {
+        let rows = xac_matrix44_to_array(&xac_local_matrix(&transform, multiply_order));
+        let values: Vec<f32> = rows.iter().flatten().copied().collect();
+        gltf_node.insert("matrix".to_string(), json!(values));
+    }
+    gltf_node
+}
+
+pub(crate) fn gltf_has_trs(transform: &XacNodeTransform, multiply_order: u8) -> bool {
+    let scale = &transform.scale;
+    let uniform_scale = (scale.x - scale.y).abs() <= 1e-6 && (scale.x - scale.z).abs() <= 1e-6;
+    let scale_rotation = gltf_quaternion(&transform.scale_rotation);
+    let identity_scale_rotation = scale_rotation[3].abs() >= 1.0 - 1e-6;
+    uniform_scale || identity_scale_rotation && multiply_order == XacScaleRotTransId as u8
+}
+
+// Rotations are normalized as glTF requires unit quaternions.
+pub(crate) fn gltf_quaternion(value: &XacQuaternion) -> [f32; 4] {
+    let length =
+        (value.x * value.x + value.y * value.y + value.z * value.z + value.w * value.w).sqrt();
+    if length <= f32::EPSILON {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+    [
+        value.x / length,
+        value.y / length,
+        value.z / length,
+        value.w / length,
+    ]
+}
+
+fn gltf_channel(sampler: usize, node_id: usize, path: &str) -> Value {
+    json!({ "sampler": sampler, "target": { "node": node_id, "path": path } })
+}
+
+fn gltf_track<K, const N: usize>(
+    keys: &[K],
+    key_time: impl Fn(&K) -> f32,
+    key_value: impl Fn(&K) -> [f32; N],
+    pose_value: [f32; N],
+) -> (Vec<[f32; 1]>, Vec<[f32; N]>) {
+    let mut times: Vec<[f32; 1]> = Vec::with_capacity(keys.len());
+    let mut values: Vec<[f32; N]> = Vec::with_capacity(keys.len());
+    for key in keys.iter() {
+        let time = key_time(key);
+        if times.last().is_some_and(|last| time <= last[0]) {
+            continue;
+        }
+        times.push([time]);
+        values.push(key_value(key));
+    }
+    if times.is_empty() {
+        times.push([0.0]);
+        values.push(pose_value);
+    }
+    (times, values)
+}
+
+// Texture names can be Windows paths with spaces, which are not valid uris.
+fn gltf_uri(name: &str) -> String {
+    let mut uri = String::with_capacity(name.len());
+    for byte in name.replace('\\', "/").bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xac::xac_test_util::rigged_actor;
+    use crate::xac::xac_util::{xac_matrix44_from_array, xac_matrix44_multiply};
+    use crate::xsm::xsm_test_util::motion_file;
+    use byteorder::LittleEndian;
+
+    // Splits a GLB into its JSON and binary chunks, checking the layout.
+    fn glb_parts(glb: &[u8]) -> (Value, Vec<u8>) {
+        let word = |offset: usize| u32::from_le_bytes(glb[offset..offset + 4].try_into().unwrap());
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(word(4), 2);
+        assert_eq!(word(8) as usize, glb.len());
+        let json_length = word(12) as usize;
+        assert_eq!(&glb[16..20], b"JSON");
+        assert_eq!(json_length % 4, 0);
+        let root: Value = serde_json::from_slice(&glb[20..20 + json_length]).unwrap();
+        let bin_start = 20 + json_length;
+        let bin_length = word(bin_start) as usize;
+        assert_eq!(&glb[bin_start + 4..bin_start + 8], b"BIN\0");
+        assert_eq!(bin_start + 8 + bin_length, glb.len());
+        (root, glb[bin_start + 8..].to_vec())
+    }
+
+    // Accessor content as one Vec per element.
+    fn accessor_values(root: &Value, bin: &[u8], accessor: &Value) -> Vec<Vec<f32>> {
+        let accessor = &root["accessors"][accessor.as_u64().unwrap() as usize];
+        let buffer_view = &root["bufferViews"][accessor["bufferView"].as_u64().unwrap() as usize];
+        let offset = buffer_view["byteOffset"].as_u64().unwrap() as usize;
+        let num_components = match accessor["type"].as_str().unwrap() {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            "VEC4" => 4,
+            _ => 16,
+        };
+        let component_size = match accessor["componentType"].as_u64().unwrap() as u32 {
+            GLTF_FLOAT => 4,
+            GLTF_UNSIGNED_SHORT => 2,
+            component_type => panic!("unexpected component type {}", component_type),
+        };
+        let count = accessor["count"].as_u64().unwrap() as usize;
+        assert_eq!(
+            buffer_view["byteLength"].as_u64().unwrap() as usize,
+            count * num_components * component_size
+        );
+        (0..count)
+            .map(|element| {
+                (0..num_components)
+                    .map(|component| {
+                        let start =
+                            offset + (element * num_components + component) * component_size;
+                        if component_size == 4 {
+                            f32::from_le_bytes(bin[start..start + 4].try_into().unwrap())
+                        } else {
+                            u16::from_le_bytes(bin[start..start + 2].try_into().unwrap()) as f32
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn assert_values(actual: &[Vec<f32>], expected: &[&[f32]]) {
+        assert_eq!(
+            actual.len(),
+            expected.len(),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+        for (actual_value, expected_value) in actual.iter().zip(expected.iter()) {
+            assert_eq!(actual_value.len(), expected_value.len());
+            for (a, e) in actual_value.iter().zip(expected_value.iter()) {
+                assert!((a - e).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+            }
+        }
+    }
+
+    // Input times and output values of the channel animating `path` of
+    // `node`.
+    fn animation_track(
+        root: &Value,
+        bin: &[u8],
+        node: usize,
+        path: &str,
+    ) -> (Vec<Vec<f32>>, Vec<Vec<f32>>) {
+        let animation = &root["animations"][0];
+        let channel = animation["channels"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|channel| channel["target"] == json!({"node": node, "path": path}))
+            .unwrap();
+        let sampler = &animation["samplers"][channel["sampler"].as_u64().unwrap() as usize];
+        assert_eq!(sampler["interpolation"], "LINEAR");
+        let times = accessor_values(root, bin, &sampler["input"]);
+        let input = &root["accessors"][sampler["input"].as_u64().unwrap() as usize];
+        assert_eq!(input["min"], json!([times[0][0]]));
+        assert_eq!(input["max"], json!([times[times.len() - 1][0]]));
+        (times, accessor_values(root, bin, &sampler["output"]))
+    }
+
+    fn vec3d(values: &[f32]) -> XacVec3d {
+        XacVec3d {
+            x: values[0],
+            y: values[1],
+            z: values[2],
+        }
+    }
+
+    fn assert_close(actual: &XacVec3d, expected: &XacVec3d) {
+        let actual = [actual.x, actual.y, actual.z];
+        let expected = [expected.x, expected.y, expected.z];
+        for (actual_value, expected_value) in actual.iter().zip(expected.iter()) {
+            assert!(
+                (actual_value - expected_value).abs() < 1e-4,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    // The rigged actor with one vertex of the first submesh cleared and the
+    // influences of the second submesh's last vertex missing.
+    fn partly_rigid_actor() -> XacActorFile {
+        let mut actor = rigged_actor();
+        let sub_meshes = &mut actor.nodes[2].visual_mesh.sub_meshes;
+        sub_meshes[0].vertex_influences[2].clear();
+        sub_meshes[1].vertex_influences.truncate(1);
+        actor
+    }
+
+    // Skins the exported mesh the way a glTF viewer does, with the joint
+    // world matrices of `pose`, and compares it with XacActorFile::skin.
+    fn assert_skins_like_actor(actor: &XacActorFile, root: &Value, bin: &[u8]) {
+        let mut pose = actor.bind_pose();
+        pose[0].rotation = XacQuaternion {
+            x: 0.0,
+            y: 0.6,
+            z: 0.0,
+            w: 0.8,
+        };
+        pose[1].position.y = 3.0;
+        pose[2].scale.z = 1.5;
+        let expected = actor.skin(&pose);
+        let joint_world = actor.pose_matrices(&pose).world;
+
+        let node = &root["nodes"][2];
+        let skin = &root["skins"][node["skin"].as_u64().unwrap() as usize];
+        let joints: Vec<usize> = skin["joints"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|joint| joint.as_u64().unwrap() as usize)
+            .collect();
+        let inverse_bind_matrices = accessor_values(root, bin, &skin["inverseBindMatrices"]);
+        assert_eq!(inverse_bind_matrices.len(), joints.len());
+        let joint_matrices: Vec<XacMatrix44> = joints
+            .iter()
+            .zip(inverse_bind_matrices.iter())
+            .map(|(joint, values)| {
+                let mut rows = [[0.0f32; 4]; 4];
+                for (index, value) in values.iter().enumerate() {
+                    rows[index / 4][index % 4] = *value;
+                }
+                xac_matrix44_multiply(&xac_matrix44_from_array(&rows), &joint_world[*joint])
+            })
+            .collect();
+
+        let mesh = &root["meshes"][node["mesh"].as_u64().unwrap() as usize];
+        let primitives = mesh["primitives"].as_array().unwrap();
+        assert_eq!(primitives.len(), 2);
+        for (primitive, expected) in primitives.iter().zip(expected[0].visual_sub_meshes.iter()) {
+            let attributes = &primitive["attributes"];
+            let positions = accessor_values(root, bin, &attributes["POSITION"]);
+            let normals = accessor_values(root, bin, &attributes["NORMAL"]);
+            let vertex_joints = accessor_values(root, bin, &attributes["JOINTS_0"]);
+            let vertex_weights = accessor_values(root, bin, &attributes["WEIGHTS_0"]);
+            assert!(attributes.get("JOINTS_1").is_none());
+            assert_eq!(vertex_joints.len(), positions.len());
+            assert_eq!(vertex_weights.len(), positions.len());
+            for vertex in 0..positions.len() {
+                assert!((vertex_weights[vertex].iter().sum::<f32>() - 1.0).abs() < 1e-6);
+                let mut rows = [[0.0f32; 4]; 4];
+                for (joint, weight) in vertex_joints[vertex].iter().zip(&vertex_weights[vertex]) {
+                    let joint = *joint as usize;
+                    assert!(joint < joints.len());
+                    let matrix = xac_matrix44_to_array(&joint_matrices[joint]);
+                    for (row, joint_row) in rows.iter_mut().zip(matrix.iter()) {
+                        for (value, joint_value) in row.iter_mut().zip(joint_row.iter()) {
+                            *value += joint_value * weight;
+                        }
+                    }
+                }
+                let matrix = xac_matrix44_from_array(&rows);
+                assert_close(
+                    &xac_matrix44_transform_point(&matrix, &vec3d(&positions[vertex])),
+                    &expected.vertex_positions[vertex],
+                );
+                assert_close(
+                    &xac_normalize_vec3d(&xac_matrix44_transform_vector(
+                        &xac_matrix44_normal(&matrix),
+                        &vec3d(&normals[vertex]),
+                    )),
+                    &expected.vertex_normals[vertex],
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn binds_rigid_vertices_to_their_node() {
+        let actor = partly_rigid_actor();
+        let mut glb = Vec::new();
+        actor.write_glb(&mut glb, &[]).unwrap();
+        let (root, bin) = glb_parts(&glb);
+        let primitives = &root["meshes"][0]["primitives"];
+
+        // Rigid vertices use the mesh node as their only joint, weighted
+        // vertices keep their normalized weights.
+        let joints = accessor_values(&root, &bin, &primitives[0]["attributes"]["JOINTS_0"]);
+        let weights = accessor_values(&root, &bin, &primitives[0]["attributes"]["WEIGHTS_0"]);
+        assert_eq!(joints[1], [0.0, 1.0, 0.0, 0.0]);
+        assert_eq!(weights[1], [0.5, 0.5, 0.0, 0.0]);
+        assert_eq!(joints[2], [2.0, 0.0, 0.0, 0.0]);
+        assert_eq!(weights[2], [1.0, 0.0, 0.0, 0.0]);
+        let joints = accessor_values(&root, &bin, &primitives[1]["attributes"]["JOINTS_0"]);
+        assert_eq!(joints, [[2.0, 0.0, 0.0, 0.0], [2.0, 0.0, 0.0, 0.0]]);
+
+        // The rigid vertex (4, 8, 12) is stored in grand's space and moved to
+        // bind pose model space: scaled by 2, turned about z, moved by (-1, 0, 3).
+        let positions = accessor_values(&root, &bin, &primitives[1]["attributes"]["POSITION"]);
+        assert_close(&vec3d(&positions[1]), &vec3d(&[-17.0, 8.0, 27.0]));
+        assert_close(&vec3d(&positions[0]), &vec3d(&[3.0, 6.0, 9.0]));
+    }
+
+    #[test]
+    fn skins_exported_glb_like_the_actor() {
+        for actor in [rigged_actor(), partly_rigid_actor()] {
+            let mut glb = Vec::new();
+            actor.write_glb(&mut glb, &[]).unwrap();
+            let (root, bin) = glb_parts(&glb);
+            assert_eq!(
+                root["buffers"][0]["byteLength"].as_u64().unwrap() as usize,
+                bin.len()
+            );
+            assert_skins_like_actor(&actor, &root, &bin);
+        }
+    }
+
+    #[test]
+    fn skins_exported_gltf_like_the_actor() {
+        let actor = partly_rigid_actor();
+        let mut gltf = Vec::new();
+        let mut bin = Vec::new();
+        actor
+            .write_gltf(&mut gltf, &mut bin, "actor.bin", &[])
+            .unwrap();
+        let root: Value = serde_json::from_slice(&gltf).unwrap();
+        assert_eq!(root["buffers"][0]["uri"], "actor.bin");
+        assert_eq!(
+            root["buffers"][0]["byteLength"].as_u64().unwrap() as usize,
+            bin.len()
+        );
+        assert_skins_like_actor(&actor, &root, &bin);
+    }
+
+    #[test]
+    fn exports_motion_tracks_as_linear_samplers() {
+        let actor = rigged_actor();
+        let xsm = Xsm::from_bytes(&motion_file::<LittleEndian>(false)).unwrap();
+        let mut glb = Vec::new();
+        actor.write_glb(&mut glb, &[&xsm]).unwrap();
+        let (root, bin) = glb_parts(&glb);
+
+        let animation = &root["animations"][0];
+        assert_eq!(animation["name"], "walk");
+        let targets: Vec<(u64, &str)> = animation["channels"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|channel| {
+                (
+                    channel["target"]["node"].as_u64().unwrap(),
+                    channel["target"]["path"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                (0, "translation"),
+                (0, "rotation"),
+                (0, "scale"),
+                (1, "translation"),
+                (1, "rotation"),
+                (1, "scale"),
+            ]
+        );
+        assert_eq!(animation["samplers"].as_array().unwrap().len(), 6);
+        // Animated nodes are written with translation, rotation and scale.
+        assert!(root["nodes"][0].get("translation").is_some());
+        assert!(root["nodes"][1].get("translation").is_some());
+
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let (times, values) = animation_track(&root, &bin, 0, "translation");
+        assert_values(&times, &[&[0.0], &[1.0]]);
+        assert_values(&values, &[&[0.0, 0.0, 0.0], &[2.0, 0.0, 0.0]]);
+        let (times, values) = animation_track(&root, &bin, 0, "rotation");
+        assert_values(&times, &[&[0.0], &[1.0]]);
+        assert_values(&values, &[&[0.0, 0.0, 0.0, 1.0], &[0.0, 0.0, half, half]]);
+        let (times, values) = animation_track(&root, &bin, 0, "scale");
+        assert_values(&times, &[&[0.0], &[1.0]]);
+        assert_values(&values, &[&[1.0, 1.0, 1.0], &[3.0, 3.0, 3.0]]);
+
+        // Tracks without keys hold the pose value at time 0.
+        let (times, values) = animation_track(&root, &bin, 1, "translation");
+        assert_values(&times, &[&[0.0]]);
+        assert_values(&values, &[&[1.0, 2.0, 3.0]]);
+        let (_, values) = animation_track(&root, &bin, 1, "rotation");
+        assert_values(&values, &[&[half, 0.0, 0.0, half]]);
+        let (_, values) = animation_track(&root, &bin, 1, "scale");
+        assert_values(&values, &[&[1.0, 1.0, 1.0]]);
+    }
+
+    #[test]
+    fn drops_keys_that_do_not_advance_in_time() {
+        let actor = rigged_actor();
+        let mut xsm = Xsm::from_bytes(&motion_file::<LittleEndian>(false)).unwrap();
+        xsm.metadata.motion_name.clear();
+        let mut submotions = xsm.decoded_submotions();
+        let mut key = submotions[0].pos_key[1].clone();
+        key.pos.x = 5.0;
+        submotions[0].pos_key.push(key);
+        xsm.set_decoded_submotions(&submotions);
+
+        let mut gltf = Vec::new();
+        let mut bin = Vec::new();
+        actor
+            .write_gltf(&mut gltf, &mut bin, "actor.bin", &[&xsm])
+            .unwrap();
+        let root: Value = serde_json::from_slice(&gltf).unwrap();
+        assert_eq!(root["animations"][0]["name"], "motion_0");
+        let (times, values) = animation_track(&root, &bin, 0, "translation");
+        assert_values(&times, &[&[0.0], &[1.0]]);
+        assert_values(&values, &[&[0.0, 0.0, 0.0], &[2.0, 0.0, 0.0]]);
+    }
+
+    #[test]
+    fn references_the_material_of_each_submesh() {
+        let mut actor = rigged_actor();
+        let mut glb = Vec::new();
+        actor.write_glb(&mut glb, &[]).unwrap();
+        let (root, _) = glb_parts(&glb);
+        assert_eq!(root["materials"][1]["name"], "head");
+        let primitives = &root["meshes"][0]["primitives"];
+        assert_eq!(primitives[0]["material"], 0);
+        assert_eq!(primitives[1]["material"], 1);
+
+        // Submeshes without a known material use the glTF default.
+        actor.nodes[2].visual_mesh.sub_meshes[1].material_id = 2;
+        let mut glb = Vec::new();
+        actor.write_glb(&mut glb, &[]).unwrap();
+        let (root, _) = glb_parts(&glb);
+        assert!(root["meshes"][0]["primitives"][1].get("material").is_none());
+    }
+}
@@ -0,0 +1,207 @@
+#![allow(dead_code)]
+use crate::xac::xac_enum::XacLayerType::{
+    XacBumpLayerId, XacDiffuseLayerId, XacOpacityLayerId, XacSelfIlluminationLayerId,
+    XacShineLayerId, XacSpecularLayerId,
+};
+use crate::xac::xac_struct::{XacActorFile, XacActorMaterial, XacVec4d};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+impl XacActorFile {
+    // Writes `path` as a Wavefront OBJ and the material library next to it,
+    // using the same file stem with an .mtl extension.
+    pub fn export_obj(&self, path: &str) -> io::Result<()> {
+        let obj_path = Path::new(path);
+        let mtl_path = obj_path.with_extension("mtl");
+        let mtl_file_name = match mtl_path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => "materials.mtl".to_string(),
+        };
+
+        let mut obj_file = BufWriter::new(File::create(obj_path)?);
+        self.write_obj(&mut obj_file, &mtl_file_name)?;
+        obj_file.flush()?;
+
+        let mut mtl_file = BufWriter::new(File::create(&mtl_path)?);
+        self.write_mtl(&mut mtl_file)?;
+        mtl_file.flush()
+    }
+
+    // Only visual meshes are exported, vertices are written as stored in the
+    // file. UVs are flipped vertically since XAC uses a top-left origin.
+    pub fn write_obj<W: Write>(&self, obj: &mut W, mtl_file_name: &str) -> io::Result<()> {
+        writeln!(obj, "# {}", self.actor_name)?;
+        writeln!(obj, "mtllib {}", mtl_file_name)?;
+
+        let mut vertex_offset: usize = 1;
+        for node in self.nodes.iter() {
+            for (sub_mesh_index, sub_mesh) in node.visual_mesh.sub_meshes.iter().enumerate() {
+                writeln!(obj, "g {}_{}", obj_name(&node.name), sub_mesh_index)?;
+                let material = self.materials.get(sub_mesh.material_id as usize);
+                if let Some(material) = material {
+                    writeln!(
+                        obj,
+                        "usemtl {}",
+                        material_name(material, sub_mesh.material_id as usize)
+                    )?;
+                }
+
+                for position in sub_mesh.vertex_positions.iter() {
+                    writeln!(obj, "v {} {} {}", position.x, position.y, position.z)?;
+                }
+                // OBJ has a single texture coordinate channel.
+                let uvs = match sub_mesh.vertex_uvs.first() {
+                    Some(uvs) => uvs.as_slice(),
+                    None => &[],
+                };
+                for uv in uvs.iter() {
+                    writeln!(obj, "vt {} {}", uv.x, 1.0 - uv.y)?;
+                }
+                for normal in sub_mesh.vertex_normals.iter() {
+                    writeln!(obj, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+                }
+
+                let has_uvs = !uvs.is_empty();
+                let has_normals = !sub_mesh.vertex_normals.is_empty();
+                for face in sub_mesh.indices.chunks_exact(3) {
+                    write!(obj, "f")?;
+                    for index in face.iter() {
+                        let vertex = *index as usize + vertex_offset;
+                        match (has_uvs, has_normals) {
+                            (true, true) => write!(obj, " {}/{}/{}", vertex, vertex, vertex)?,
+                            (true, false) => write!(obj, " {}/{}", vertex, vertex)?,
+                            (false, true) => write!(obj, " {}//{}", vertex, vertex)?,
+                            (false, false) => write!(obj, " {}", vertex)?,
+                        }
+                    }
+                    writeln!(obj)?;
+                }
+                vertex_offset += sub_mesh.vertex_positions.len();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_mtl<W: Write>(&self, mtl: &mut W) -> io::Result<()> {
+        for (material_id, material) in self.materials.iter().enumerate() {
+            writeln!(mtl, "newmtl {}", material_name(material, material_id))?;
+            write_mtl_color(mtl, "Ka", &material.ambient_color)?;
+            write_mtl_color(mtl, "Kd", &material.diffuse_color)?;
+            write_mtl_color(mtl, "Ks", &material.specular_color)?;
+            write_mtl_color(mtl, "Ke", &material.emissive_color)?;
+            writeln!(mtl, "Ns {}", material.shine)?;
+            writeln!(mtl, "Ni {}", material.ior)?;
+            writeln!(mtl, "d {}", material.opacity)?;
+
+            for layer in material.layers.iter() {
+                let statement = match layer.map_type {
+                    map_type if map_type == XacDiffuseLayerId as u8 => "map_Kd",
+                    map_type if map_type == XacSpecularLayerId as u8 => "map_Ks",
+                    map_type if map_type == XacOpacityLayerId as u8 => "map_d",
+                    map_type if map_type == XacBumpLayerId as u8 => "map_bump",
+                    map_type if map_type == XacSelfIlluminationLayerId as u8 => "map_Ke",
+                    map_type if map_type == XacShineLayerId as u8 => "map_Ns",
+                    _ => continue,
+                };
+                if layer.name.is_empty() {
+                    continue;
+                }
+                writeln!(mtl, "{} {}", statement, layer.name)?;
+            }
+            writeln!(mtl)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_mtl_color<W: Write>(mtl: &mut W, statement: &str, color: &XacVec4d) -> io::Result<()> {
+    writeln!(mtl, "{} {} {} {}", statement, color.x, color.y, color.z)
+}
+
+fn material_name(material: &XacActorMaterial, material_id: usize) -> String {
+    if material.name.is_empty() {
+        format!("material_{}", material_id)
+    } else {
+        obj_name(&material.name)
+    }
+}
+
+// OBJ statements are whitespace separated, so names must not contain any.
+fn obj_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<&str>>().join("_")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::xac::xac_test_util::rigged_actor;
+
+    #[test]
+    fn writes_one_group_per_submesh() {
+        let mut obj = Vec::new();
+        rigged_actor().write_obj(&mut obj, "actor.mtl").unwrap();
+        // Faces use 1-based indices continuing over the groups, UVs are
+        // flipped from (v / 4, 1 - v / 4).
+        let expected = "# actor\n\
+            mtllib actor.mtl\n\
+            g grand_0\n\
+            usemtl body\n\
+            v 0 0 0\n\
+            v 1 2 3\n\
+            v 2 4 6\n\
+            vt 0 0\n\
+            vt 0.25 0.25\n\
+            vt 0.5 0.5\n\
+            vn 0 1 0\n\
+            vn 0 1 0\n\
+            vn 0 1 0\n\
+            f 1/1/1 2/2/2 3/3/3\n\
+            g grand_1\n\
+            usemtl head\n\
+            v 3 6 9\n\
+            v 4 8 12\n\
+            vt 0.75 0.75\n\
+            vt 1 1\n\
+            vn 0 1 0\n\
+            vn 0 1 0\n\
+            f 4/4/4 5/5/5 5/5/5\n";
+        assert_eq!(String::from_utf8(obj).unwrap(), expected);
+    }
+
+    #[test]
+    fn writes_faces_without_uvs_or_normals() {
+        let mut actor = rigged_actor();
+        let sub_mesh = &mut actor.nodes[2].visual_mesh.sub_meshes[1];
+        sub_mesh.vertex_uvs.clear();
+        // Unknown materials are not referenced.
+        sub_mesh.material_id = 7;
+        let mut obj = Vec::new();
+        actor.write_obj(&mut obj, "actor.mtl").unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        assert!(
+            obj.ends_with("g grand_1\nv 3 6 9\nv 4 8 12\nvn 0 1 0\nvn 0 1 0\nf 4//4 5//5 5//5\n")
+        );
+
+        actor.nodes[2].visual_mesh.sub_meshes[1]
+            .vertex_normals
+            .clear();
+        let mut obj = Vec::new();
+        actor.write_obj(&mut obj, "actor.mtl").unwrap();
+        assert!(String::from_utf8(obj).unwrap().ends_with("f 4 5 5\n"));
+    }
+
+    #[test]
+    fn writes_texture_layers_to_the_material_library() {
+        let mut mtl = Vec::new();
+        rigged_actor().write_mtl(&mut mtl).unwrap();
+        let mtl = String::from_utf8(mtl).unwrap();
+        let materials: Vec<&str> = mtl.split("\n\n").collect();
+        assert_eq!(materials.len(), 3);
+        assert!(materials[0].starts_with("newmtl body\nKa 0.1 0.1 0.1\n"));
+        assert!(materials[0].ends_with("d 1\nmap_Kd body_diffuse.dds"));
+        assert!(materials[1].starts_with("newmtl head\n"));
+        assert!(materials[1].ends_with("map_Kd head_diffuse.dds\nmap_bump head_normal.dds"));
+        assert_eq!(materials[2], "");
+    }
+}
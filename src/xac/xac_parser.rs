@@ -1,156 +1,267 @@
-#![allow(unused_variables)]
+#![allow(dead_code)]
 use crate::xac::xac_enum::XacChunkType::{
-    XacMaterialDefinitionId, XacMaterialTotalId, XacMeshId, XacMetadataId, XacMorphTargetId,
-    XacNodeHierarchyId, XacShaderMaterialId, XacSkinningId,
+    XacMaterialDefinitionId, XacMaterialLayerId, XacMaterialTotalId, XacMeshId, XacMetadataId,
+    XacMorphTargetId, XacNodeHierarchyId, XacNodeId, XacShaderMaterialId, XacSkinningId,
 };
 use crate::xac::xac_enum::XacVerticesAttributeType::{
     XacColor128Id, XacColor32Id, XacInfluenceRangeId, XacNormalId, XacPositionId, XacTangentId,
     XacUVCoordId,
 };
+use crate::xac::xac_error::XacError;
 use crate::xac::xac_struct::{
-    XacActorFile, XacActorMaterial, XacActorMaterialLayer, XacActorMesh, XacActorNode,
-    XacActorSubMesh, XacBoolProperties, XacChunkHeader, XacColor8, XacFloatProperties, XacHeader,
+    XacActorFile, XacActorMaterial, XacActorMaterialLayer, XacActorMesh, XacActorMorphTarget,
+    XacActorNode, XacActorNodeDeformation, XacActorSubMesh, XacActorVertexAttribute,
+    XacBoneInfluence, XacBoolProperties, XacChunkHeader, XacColor8, XacFloatProperties, XacHeader,
     XacInfluenceRange, XacIntProperties, XacMaterialDefinitionChunkv2Header,
     XacMaterialDefinitionChunkv2Layer, XacMaterialTotalsChunkv1, XacMatrix44, XacMeshChunkv1Header,
     XacMeshChunkv1SubMesh, XacMeshChunkv1VertexElement, XacMetaDataChunkv2Header,
-    XacNodeHierarchyChunkv1NodeHeader, XacQuaternion, XacShaderMaterial, XacStringProperties,
-    XacSubMeshInfluenceRange, XacVec2d, XacVec3d, XacVec4d,
+    XacMorphTargetChunk, XacMorphTargetsChunkv1Deformation, XacMorphTargetsChunkv1MorphTarget,
+    XacMorphTargetsChunkv1Transformation, XacNodeHierarchyChunkv1NodeHeader, XacQuaternion,
+    XacShaderMaterial, XacSkinningChunkv3Header, XacSkinningChunkv3Influence,
+    XacSkinningChunkv3InfluenceRange, XacStringProperties, XacSubMeshInfluenceRange,
+    XacUnknownChunk, XacVec2d, XacVec3d, XacVec4d,
 };
 use crate::xac::xac_util::{
-    xac_read_boolean, xac_read_color8, xac_read_matrix44, xac_read_quaternion, xac_read_string,
-    xac_read_vec2d, xac_read_vec3d, xac_read_vec4d,
+    xac_decompress_vec3d16, xac_decompress_vec3d8, xac_read_boolean, xac_read_color8,
+    xac_read_matrix44, xac_read_quaternion, xac_read_string, xac_read_vec2d, xac_read_vec3d,
+    xac_read_vec3d16, xac_read_vec3d8, xac_read_vec4d,
 };
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
 use std::fs::File;
-use std::io::{Read, Seek, SeekFrom};
-
-pub fn xacparse(path: &str) -> XacActorFile {
-    let mut xac_file = File::open(path).expect("Cannot open XAC file!");
-    let mut xac_actor = XacActorFile {
-        root_nodes: vec![],
-        materials: vec![],
-        morph_targets: vec![],
-        header: XacHeader {
-            magic: "".to_string(),
-            major_version: 0,
-            minor_version: 0,
-            big_endian: false,
-            multiply_order: 0,
-        },
-        metadata: XacMetaDataChunkv2Header {
-            reposition_mask: 0,
-            repositioning_node: 0,
-            exporter_major_version: 0,
-            exporter_minor_version: 0,
-            retarget_root_offset: 0.0,
-        },
-        actor_name: "".to_string(),
-        original_filename: "".to_string(),
-        source_app: "".to_string(),
-        export_date: "".to_string(),
-        shader_materials: vec![],
-        material_total: XacMaterialTotalsChunkv1 {
-            num_total_materials: 0,
-            num_standard_materials: 0,
-            num_fx_materials: 0,
-        },
-        num_root_nodes: 0,
-        num_nodes: 0,
-        nodes: vec![],
-        mesh_num_influence_range: Default::default(),
-        sub_mesh_influence_range_indices: Default::default(),
-        num_nodes_index: 0,
-    };
-    read_header(&mut xac_file, &mut xac_actor);
-    read_chunk(&mut xac_file, &mut xac_actor);
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
 
-    xac_actor
+pub fn xacparse(path: &str) -> Result<XacActorFile, XacError> {
+    let xac_file = File::open(path)?;
+    XacActorFile::from_reader(BufReader::new(xac_file))
 }
 
-fn read_header<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'a mut XacActorFile {
-    let mut magic = [0; 4];
-    file.read_exact(&mut magic).unwrap();
-    xac.header.magic = std::str::from_utf8(&magic).unwrap().to_string();
-    if xac.header.magic != "XAC " {
-        panic!("Not an XAC file: invalid header magic");
-    }
-    xac.header.major_version = file.read_u8().unwrap();
-    xac.header.minor_version = file.read_u8().unwrap();
-    if xac.header.major_version != 1 || xac.header.minor_version != 0 {
-        panic!(
-            "Unsupported .xac version: expected v1.0, file is {}.{}",
-            xac.header.major_version, xac.header.minor_version
-        );
+impl XacActorFile {
+    pub fn from_reader<R: Read + Seek>(mut reader: R) -> Result<XacActorFile, XacError> {
+        let mut xac_actor = XacActorFile {
+            root_nodes: vec![],
+            materials: vec![],
+            morph_targets: vec![],
+            morph_target_chunks: vec![],
+            header: XacHeader {
+                magic: "".to_string(),
+                major_version: 0,
+                minor_version: 0,
+                big_endian: false,
+                multiply_order: 0,
+            },
+            metadata: XacMetaDataChunkv2Header {
+                reposition_mask: 0,
+                repositioning_node: 0,
+                exporter_major_version: 0,
+                exporter_minor_version: 0,
+                retarget_root_offset: 0.0,
+            },
+            actor_name: "".to_string(),
+            original_filename: "".to_string(),
+            source_app: "".to_string(),
+            export_date: "".to_string(),
+            shader_materials: vec![],
+            material_total: XacMaterialTotalsChunkv1 {
+                num_total_materials: 0,
+                num_standard_materials: 0,
+                num_fx_materials: 0,
+            },
+            num_root_nodes: 0,
+            num_nodes: 0,
+            nodes: vec![],
+            mesh_num_influence_range: Default::default(),
+            sub_mesh_influence_range_indices: Default::default(),
+            num_nodes_index: 0,
+            unknown_chunks: vec![],
+        };
+        read_header(&mut reader, &mut xac_actor)?;
+        if xac_actor.header.big_endian {
+            read_chunk::<_, BigEndian>(&mut reader, &mut xac_actor)?;
+        } else {
+            read_chunk::<_, LittleEndian>(&mut reader, &mut xac_actor)?;
+        }
+
+        Ok(xac_actor)
     }
-    xac.header.big_endian = xac_read_boolean(file);
-    if xac.header.big_endian {
-        panic!("XAC file is encoded in big endian which is not supported by this importer");
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<XacActorFile, XacError> {
+        XacActorFile::from_reader(Cursor::new(bytes))
     }
-    xac.header.multiply_order = file.read_u8().unwrap();
-    xac
 }
 
-fn read_chunk<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'a mut XacActorFile {
-    while file.stream_position().unwrap() < file.metadata().unwrap().len() {
-        let chunk = XacChunkHeader {
-            type_id: file.read_i32::<LittleEndian>().unwrap(),
-            length: file.read_i32::<LittleEndian>().unwrap(),
-            version: file.read_i32::<LittleEndian>().unwrap(),
-        };
-        let position = file.stream_position().unwrap();
+fn read_header<R: Read>(file: &mut R, xac: &mut XacActorFile) -> Result<(), XacError> {
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    if &magic != b"XAC " {
+        return Err(XacError::InvalidMagic {
+            chunk_type: None,
+            offset: 0,
+            magic,
+        });
+    }
+    xac.header.magic = String::from_utf8_lossy(&magic).to_string();
+    xac.header.major_version = file.read_u8()?;
+    xac.header.minor_version = file.read_u8()?;
+    // Layout changes between exporter releases are versioned per chunk, the
+    // header minor version does not change how chunks are read.
+    if xac.header.major_version != 1 {
+        return Err(XacError::UnsupportedVersion {
+            chunk_type: None,
+            offset: 4,
+            major_version: xac.header.major_version,
+            minor_version: xac.header.minor_version,
+        });
+    }
+    xac.header.big_endian = xac_read_boolean(file)?;
+    xac.header.multiply_order = file.read_u8()?;
+    Ok(())
+}
 
-        if chunk.type_id == XacMeshId as i32 {
-            read_mesh(file, xac);
+fn read_chunk<R: Read + Seek, E: ByteOrder>(
+    file: &mut R,
+    xac: &mut XacActorFile,
+) -> Result<(), XacError> {
+    let start = file.stream_position()?;
+    let file_length = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(start))?;
+    while file.stream_position()? < file_length {
+        let chunk_start = file.stream_position()?;
+        let chunk = read_chunk_header::<_, E>(file)
+            .map_err(|err| XacError::from(err).locate(None, chunk_start))?;
+        let position = file.stream_position()?;
+        if chunk.length < 0 || position + chunk.length as u64 > file_length {
+            return Err(XacError::TruncatedChunk {
+                chunk_type: Some(chunk.type_id),
+                offset: chunk_start,
+            });
         }
 
-        if chunk.type_id == XacSkinningId as i32 {
-            //read_skinning(file, xac);
-        }
-        if chunk.type_id == XacMaterialDefinitionId as i32 {
-            read_material_definition(file, xac);
-        }
-        if chunk.type_id == XacShaderMaterialId as i32 {
-            read_shader_material(file, xac);
+        if let Some(versions) = supported_chunk_versions(chunk.type_id) {
+            if !versions.contains(&chunk.version) {
+                return Err(XacError::UnsupportedChunkVersion {
+                    chunk_type: Some(chunk.type_id),
+                    offset: chunk_start,
+                    version: chunk.version,
+                });
+            }
         }
 
-        if chunk.type_id == XacMetadataId as i32 {
-            read_metadata(file, xac);
-        }
-        if chunk.type_id == XacNodeHierarchyId as i32 {
-            read_node_hierarchy(file, xac);
-        }
-        if chunk.type_id == XacMorphTargetId as i32 {
-            //   read_morph_target(file, xac);
-        }
-        if chunk.type_id == XacMaterialTotalId as i32 {
-            read_material_total(file, xac);
+        let result = if chunk.type_id == XacMeshId as i32 {
+            read_mesh::<_, E>(file, xac)
+        } else if chunk.type_id == XacSkinningId as i32 {
+            read_skinning::<_, E>(file, xac, chunk.version)
+        } else if chunk.type_id == XacMaterialDefinitionId as i32 {
+            read_material_definition::<_, E>(file, xac, chunk.version)
+        } else if chunk.type_id == XacMaterialLayerId as i32 {
+            read_material_layer_chunk::<_, E>(file, xac)
+        } else if chunk.type_id == XacShaderMaterialId as i32 {
+            read_shader_material::<_, E>(file, xac)
+        } else if chunk.type_id == XacMetadataId as i32 {
+            read_metadata::<_, E>(file, xac, chunk.version)
+        } else if chunk.type_id == XacNodeHierarchyId as i32 {
+            read_node_hierarchy::<_, E>(file, xac)
+        } else if chunk.type_id == XacMorphTargetId as i32 {
+            read_morph_target::<_, E>(file, xac)
+        } else if chunk.type_id == XacMaterialTotalId as i32 {
+            read_material_total::<_, E>(file, xac)
+        } else {
+            read_unknown_chunk(file, xac, &chunk, chunk_start)
+        };
+        if let Err(err) = result {
+            let failed_at = file.stream_position().unwrap_or(position);
+            return Err(err.locate(Some(chunk.type_id), failed_at));
         }
 
-        file.seek(SeekFrom::Start(position + chunk.length as u64))
-            .unwrap();
+        file.seek(SeekFrom::Start(position + chunk.length as u64))?;
+    }
+
+    // Meshes and skinning are attached to the flat node list, so the tree
+    // is rebuilt once every chunk has been read.
+    xac.root_nodes = build_node_tree(&xac.nodes)?;
+    Ok(())
+}
+
+// Chunk layouts this parser knows, per chunk type. Chunk types that are not
+// listed here are skipped whatever their version.
+fn supported_chunk_versions(type_id: i32) -> Option<&'static [i32]> {
+    let single_version = [
+        XacMeshId as i32,
+        XacMaterialLayerId as i32,
+        XacShaderMaterialId as i32,
+        XacNodeHierarchyId as i32,
+        XacMorphTargetId as i32,
+        XacMaterialTotalId as i32,
+    ];
+    // The legacy node chunk is not read. Skipping it would leave the actor
+    // without nodes and fail later on the first mesh, so any version of it
+    // is rejected up front.
+    if type_id == XacNodeId as i32 {
+        Some(&[])
+    } else if type_id == XacSkinningId as i32 {
+        Some(&[1, 2, 3])
+    } else if type_id == XacMaterialDefinitionId as i32 || type_id == XacMetadataId as i32 {
+        Some(&[1, 2])
+    } else if single_version.contains(&type_id) {
+        Some(&[1])
+    } else {
+        None
     }
-    xac
 }
 
-fn read_metadata<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'a mut XacActorFile {
-    xac.metadata.reposition_mask = file.read_u32::<LittleEndian>().unwrap();
-    xac.metadata.repositioning_node = file.read_i32::<LittleEndian>().unwrap();
-    xac.metadata.exporter_major_version = file.read_u8().unwrap();
-    xac.metadata.exporter_minor_version = file.read_u8().unwrap();
-    file.read_u8().unwrap(); //Padding
-    file.read_u8().unwrap(); //Padding
-    xac.metadata.retarget_root_offset = file.read_f32::<LittleEndian>().unwrap();
-    xac.source_app = xac_read_string(file);
-    xac.original_filename = xac_read_string(file);
-    xac.export_date = xac_read_string(file);
-    xac.actor_name = xac_read_string(file);
-    xac
+fn read_chunk_header<R: Read, E: ByteOrder>(file: &mut R) -> std::io::Result<XacChunkHeader> {
+    Ok(XacChunkHeader {
+        type_id: file.read_i32::<E>()?,
+        length: file.read_i32::<E>()?,
+        version: file.read_i32::<E>()?,
+    })
+}
+
+fn read_unknown_chunk<R: Read>(
+    file: &mut R,
+    xac: &mut XacActorFile,
+    chunk: &XacChunkHeader,
+    chunk_start: u64,
+) -> Result<(), XacError> {
+    let mut data = vec![0; chunk.length as usize];
+    file.read_exact(&mut data)?;
+    xac.unknown_chunks.push(XacUnknownChunk {
+        type_id: chunk.type_id,
+        version: chunk.version,
+        offset: chunk_start,
+        data,
+    });
+    Ok(())
+}
+
+fn read_metadata<R: Read, E: ByteOrder>(
+    file: &mut R,
+    xac: &mut XacActorFile,
+    version: i32,
+) -> Result<(), XacError> {
+    xac.metadata.reposition_mask = file.read_u32::<E>()?;
+    xac.metadata.repositioning_node = file.read_i32::<E>()?;
+    xac.metadata.exporter_major_version = file.read_u8()?;
+    xac.metadata.exporter_minor_version = file.read_u8()?;
+    file.read_u8()?; //Padding
+    file.read_u8()?; //Padding
+    if version >= 2 {
+        // Version 1 has no retarget root offset.
+        xac.metadata.retarget_root_offset = file.read_f32::<E>()?;
+    }
+    xac.source_app = xac_read_string::<_, E>(file)?;
+    xac.original_filename = xac_read_string::<_, E>(file)?;
+    xac.export_date = xac_read_string::<_, E>(file)?;
+    xac.actor_name = xac_read_string::<_, E>(file)?;
+    Ok(())
 }
-fn read_node_hierarchy<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'a mut XacActorFile {
-    let num_nodes = file.read_i32::<LittleEndian>().unwrap();
-    let num_root_nodes = file.read_i32::<LittleEndian>().unwrap();
+fn read_node_hierarchy<R: Read, E: ByteOrder>(
+    file: &mut R,
+    xac: &mut XacActorFile,
+) -> Result<(), XacError> {
+    let num_nodes = file.read_i32::<E>()?;
+    let num_root_nodes = file.read_i32::<E>()?;
     if num_nodes <= 0 {
-        panic!("Invalid number of nodes");
+        return Err(XacError::inconsistent("Invalid number of nodes"));
     }
 
     xac.num_nodes = num_nodes;
@@ -180,9 +291,17 @@ fn read_node_hierarchy<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'a
                 y: 0.0,
                 z: 0.0,
             },
+            shear: XacVec3d {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            skeletal_lods: 0,
+            motion_lods: 0,
             parent_node_id: 0,
             num_children: 0,
             include_inbounds_calc: 0,
+            node_flags: 0,
             transform: XacMatrix44 {
                 axis_1: XacVec4d {
                     x: 0.0,
@@ -240,6 +359,15 @@ fn read_node_hierarchy<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'a
                 z: 0.0,
                 w: 0.0,
             },
+            shear: XacVec3d {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            skeletal_lods: 0,
+            motion_lods: 0,
+            include_inbounds_calc: 0,
+            node_flags: 0,
             transform: XacMatrix44 {
                 axis_1: XacVec4d {
                     x: 0.0,
@@ -266,30 +394,37 @@ fn read_node_hierarchy<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'a
                     w: 0.0,
                 },
             },
+            importance_factor: 0.0,
             parent: None,
             num_children: 0,
             children: vec![],
-            visual_mesh: XacActorMesh { sub_meshes: vec![] },
-            collision_mesh: XacActorMesh { sub_meshes: vec![] },
+            visual_mesh: XacActorMesh {
+                sub_meshes: vec![],
+                vertex_attributes: vec![],
+            },
+            collision_mesh: XacActorMesh {
+                sub_meshes: vec![],
+                vertex_attributes: vec![],
+            },
         };
 
-        node_info.rotation = xac_read_quaternion(file);
-        node_info.scale_rotation = xac_read_quaternion(file);
-        node_info.position = xac_read_vec3d(file);
-        node_info.scale = xac_read_vec3d(file);
-
-        file.read_i32::<LittleEndian>().unwrap(); //Padding
-        file.read_i32::<LittleEndian>().unwrap(); //Padding
-        file.read_i32::<LittleEndian>().unwrap(); //Padding
-        file.read_i32::<LittleEndian>().unwrap(); //Padding
-        file.read_i32::<LittleEndian>().unwrap(); //Padding
-
-        node_info.parent_node_id = file.read_i32::<LittleEndian>().unwrap();
-        node_info.num_children = file.read_i32::<LittleEndian>().unwrap();
-        node_info.include_inbounds_calc = file.read_i32::<LittleEndian>().unwrap();
-        node_info.transform = xac_read_matrix44(file);
-        node_info.importance_factor = file.read_f32::<LittleEndian>().unwrap();
-        let node_name = xac_read_string(file);
+        node_info.rotation = xac_read_quaternion::<_, E>(file)?;
+        node_info.scale_rotation = xac_read_quaternion::<_, E>(file)?;
+        node_info.position = xac_read_vec3d::<_, E>(file)?;
+        node_info.scale = xac_read_vec3d::<_, E>(file)?;
+
+        node_info.shear = xac_read_vec3d::<_, E>(file)?;
+        node_info.skeletal_lods = file.read_u32::<E>()?;
+        node_info.motion_lods = file.read_u32::<E>()?;
+        node_info.parent_node_id = file.read_i32::<E>()?;
+        node_info.num_children = file.read_i32::<E>()?;
+        node_info.include_inbounds_calc = file.read_u8()?;
+        node_info.node_flags = file.read_u8()?;
+        file.read_u8()?; //Padding
+        file.read_u8()?; //Padding
+        node_info.transform = xac_read_matrix44::<_, E>(file)?;
+        node_info.importance_factor = file.read_f32::<E>()?;
+        let node_name = xac_read_string::<_, E>(file)?;
 
         p_nodes.parent_node_id = node_info.parent_node_id;
         p_nodes.name = node_name;
@@ -297,53 +432,145 @@ fn read_node_hierarchy<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'a
         p_nodes.rotation = node_info.rotation;
         p_nodes.scale = node_info.scale;
         p_nodes.scale_rotation = node_info.scale_rotation;
+        p_nodes.shear = node_info.shear;
+        p_nodes.skeletal_lods = node_info.skeletal_lods;
+        p_nodes.motion_lods = node_info.motion_lods;
+        p_nodes.include_inbounds_calc = node_info.include_inbounds_calc;
+        p_nodes.node_flags = node_info.node_flags;
         p_nodes.transform = node_info.transform;
+        p_nodes.importance_factor = node_info.importance_factor;
         p_nodes.num_children = node_info.num_children;
-        p_nodes.node_id = xac.num_nodes_index.clone();
+        p_nodes.node_id = xac.num_nodes_index;
         xac.num_nodes_index = p_nodes.node_id + 1;
-        let num_root = xac.root_nodes.len().clone() as usize;
-        if node_info.parent_node_id.clone() == -1 {
+        if node_info.parent_node_id == -1 {
             p_nodes.parent = None;
-            xac.root_nodes.push(p_nodes.clone());
+        }
+
+        xac.nodes.push(p_nodes);
+    }
+
+    // The tree itself is built once every chunk has been read, here the
+    // hierarchy is only checked so errors point at the node chunk.
+    let (root_ids, _) = node_tree_ids(&xac.nodes)?;
+    if root_ids.len() != num_root_nodes as usize {
+        return Err(XacError::inconsistent(
+            "Root nodes size does not match number of nodes with parent ID -1",
+        ));
+    }
+    Ok(())
+}
+
+// Root ids and the children ids of every node, in file order.
+fn node_tree_ids(nodes: &[XacActorNode]) -> Result<(Vec<usize>, Vec<Vec<usize>>), XacError> {
+    let mut root_ids: Vec<usize> = Vec::new();
+    let mut children_ids: Vec<Vec<usize>> = vec![Vec::new(); nodes.len()];
+    for node in nodes.iter() {
+        if node.parent_node_id == -1 {
+            root_ids.push(node.node_id);
+        } else if node.parent_node_id < 0
+            || node.parent_node_id as usize >= nodes.len()
+            || node.parent_node_id as usize == node.node_id
+        {
+            return Err(XacError::inconsistent(format!(
+                "Node {} has invalid parent {}",
+                node.name, node.parent_node_id
+            )));
         } else {
-            xac.root_nodes[num_root - 1].children.push(p_nodes.clone());
+            children_ids[node.parent_node_id as usize].push(node.node_id);
         }
+    }
+
+    for node in nodes.iter() {
+        if children_ids[node.node_id].len() != node.num_children as usize {
+            return Err(XacError::inconsistent(format!(
+                "Node {} declares {} children but {} nodes refer to it",
+                node.name,
+                node.num_children,
+                children_ids[node.node_id].len()
+            )));
+        }
+    }
+
+    // Nodes that are part of a parent cycle can never be reached from a root.
+    if node_preorder(&root_ids, &children_ids).len() != nodes.len() {
+        return Err(XacError::inconsistent(
+            "Node hierarchy contains a parent cycle",
+        ));
+    }
+    Ok((root_ids, children_ids))
+}
 
-        xac.nodes.push(p_nodes.clone());
+fn node_preorder(root_ids: &[usize], children_ids: &[Vec<usize>]) -> Vec<usize> {
+    let mut order: Vec<usize> = Vec::with_capacity(children_ids.len());
+    let mut stack: Vec<usize> = root_ids.iter().rev().copied().collect();
+    while let Some(node_id) = stack.pop() {
+        order.push(node_id);
+        stack.extend(children_ids[node_id].iter().rev());
     }
+    order
+}
 
-    if xac.root_nodes.len() != num_root_nodes.clone() as usize {
-        panic!("Root nodes size does not match number of nodes with parent ID -1");
+// Built without recursion, children come after their parent in preorder so
+// walking it backwards finishes every subtree before its parent needs it.
+fn build_node_tree(nodes: &[XacActorNode]) -> Result<Vec<XacActorNode>, XacError> {
+    let (root_ids, children_ids) = node_tree_ids(nodes)?;
+    let mut subtrees: Vec<Option<XacActorNode>> = vec![None; nodes.len()];
+    for node_id in node_preorder(&root_ids, &children_ids).into_iter().rev() {
+        let mut node = nodes[node_id].clone();
+        node.children = children_ids[node_id]
+            .iter()
+            .filter_map(|child_id| subtrees[*child_id].take())
+            .collect();
+        subtrees[node_id] = Some(node);
     }
-    xac
+    Ok(root_ids
+        .iter()
+        .filter_map(|root_id| subtrees[*root_id].take())
+        .collect())
 }
 
-fn read_material_total<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'a mut XacActorFile {
+// The default drop would recurse once per tree level as well.
+impl Drop for XacActorNode {
+    fn drop(&mut self) {
+        let mut descendants = std::mem::take(&mut self.children);
+        while let Some(mut node) = descendants.pop() {
+            descendants.append(&mut node.children);
+        }
+    }
+}
+
+fn read_material_total<R: Read, E: ByteOrder>(
+    file: &mut R,
+    xac: &mut XacActorFile,
+) -> Result<(), XacError> {
     let mut totals = XacMaterialTotalsChunkv1 {
         num_total_materials: 0,
         num_standard_materials: 0,
         num_fx_materials: 0,
     };
-    totals.num_total_materials = file.read_i32::<LittleEndian>().unwrap();
-    totals.num_standard_materials = file.read_i32::<LittleEndian>().unwrap();
-    totals.num_fx_materials = file.read_i32::<LittleEndian>().unwrap();
-    if totals.num_standard_materials.clone() <= 0 {
-        panic!("Invalid number of standard materials");
+    totals.num_total_materials = file.read_i32::<E>()?;
+    totals.num_standard_materials = file.read_i32::<E>()?;
+    totals.num_fx_materials = file.read_i32::<E>()?;
+    if totals.num_standard_materials <= 0 {
+        return Err(XacError::inconsistent(
+            "Invalid number of standard materials",
+        ));
     }
 
-    if totals.num_total_materials.clone()
-        != totals.num_standard_materials.clone() + totals.num_fx_materials.clone()
-    {
-        panic!("Incorrect numTotalMaterials (must be sum of standard materials and fx materials");
+    if totals.num_total_materials != totals.num_standard_materials + totals.num_fx_materials {
+        return Err(XacError::inconsistent(
+            "Incorrect numTotalMaterials (must be sum of standard materials and fx materials",
+        ));
     }
     xac.material_total = totals;
-    xac
+    Ok(())
 }
 
-fn read_material_definition<'a>(
-    file: &'a mut File,
-    xac: &'a mut XacActorFile,
-) -> &'a mut XacActorFile {
+fn read_material_definition<R: Read, E: ByteOrder>(
+    file: &mut R,
+    xac: &mut XacActorFile,
+    version: i32,
+) -> Result<(), XacError> {
     let mut material_info = XacMaterialDefinitionChunkv2Header {
         ambient_color: XacVec4d {
             x: 0.0,
@@ -375,21 +602,27 @@ fn read_material_definition<'a>(
         ior: 0.0,
         double_sided: false,
         wireframe: false,
+        transparency_type: 0,
         num_layers: 0,
     };
-    material_info.ambient_color = xac_read_vec4d(file);
-    material_info.diffuse_color = xac_read_vec4d(file);
-    material_info.specular_color = xac_read_vec4d(file);
-    material_info.emissive_color = xac_read_vec4d(file);
-    material_info.shine = file.read_f32::<LittleEndian>().unwrap();
-    material_info.shine_strength = file.read_f32::<LittleEndian>().unwrap();
-    material_info.opacity = file.read_f32::<LittleEndian>().unwrap();
-    material_info.ior = file.read_f32::<LittleEndian>().unwrap();
-    material_info.double_sided = xac_read_boolean(file);
-    material_info.wireframe = xac_read_boolean(file);
-    file.read_u8().unwrap(); //Padding
-    material_info.num_layers = file.read_u8().unwrap();
-    let material_name = xac_read_string(file);
+    material_info.ambient_color = xac_read_vec4d::<_, E>(file)?;
+    material_info.diffuse_color = xac_read_vec4d::<_, E>(file)?;
+    material_info.specular_color = xac_read_vec4d::<_, E>(file)?;
+    material_info.emissive_color = xac_read_vec4d::<_, E>(file)?;
+    material_info.shine = file.read_f32::<E>()?;
+    material_info.shine_strength = file.read_f32::<E>()?;
+    material_info.opacity = file.read_f32::<E>()?;
+    material_info.ior = file.read_f32::<E>()?;
+    material_info.double_sided = xac_read_boolean(file)?;
+    material_info.wireframe = xac_read_boolean(file)?;
+    material_info.transparency_type = file.read_u8()?;
+    if version >= 2 {
+        material_info.num_layers = file.read_u8()?;
+    } else {
+        // Version 1 materials store their layers in separate layer chunks.
+        file.read_u8()?; //Padding
+    }
+    let material_name = xac_read_string::<_, E>(file)?;
 
     let mut material = XacActorMaterial {
         name: "".to_string(),
@@ -424,6 +657,7 @@ fn read_material_definition<'a>(
         ior: 0.0,
         double_sided: false,
         wireframe: false,
+        transparency_type: 0,
         layers: vec![],
     };
 
@@ -438,73 +672,85 @@ fn read_material_definition<'a>(
     material.ior = material_info.ior;
     material.double_sided = material_info.double_sided;
     material.wireframe = material_info.wireframe;
+    material.transparency_type = material_info.transparency_type;
 
     for _i in 0..material_info.num_layers {
-        let mut layer_info = XacMaterialDefinitionChunkv2Layer {
-            amount: 0.0,
-            v_offset: 0.0,
-            u_offset: 0.0,
-            u_tiling: 0.0,
-            v_tiling: 0.0,
-            rotation: 0.0,
-            material_id: 0,
-            map_type: 0,
-        };
-
-        layer_info.amount = file.read_f32::<LittleEndian>().unwrap();
-        layer_info.u_offset = file.read_f32::<LittleEndian>().unwrap();
-        layer_info.v_offset = file.read_f32::<LittleEndian>().unwrap();
-        layer_info.u_tiling = file.read_f32::<LittleEndian>().unwrap();
-        layer_info.v_tiling = file.read_f32::<LittleEndian>().unwrap();
-        layer_info.rotation = file.read_f32::<LittleEndian>().unwrap();
-        layer_info.material_id = file.read_i16::<LittleEndian>().unwrap();
-        layer_info.map_type = file.read_u8().unwrap();
-        file.read_u8().unwrap(); //Padding
-
-        let texture = xac_read_string(file);
-
-        let mut layer = XacActorMaterialLayer {
-            name: "".to_string(),
-            amount: 0.0,
-            v_offset: 0.0,
-            u_offset: 0.0,
-            v_tiling: 0.0,
-            u_tiling: 0.0,
-            rotation: 0.0,
-            map_type: 0,
-        };
-
-        layer.name = texture;
-        layer.amount = layer_info.amount;
-        layer.u_offset = layer_info.u_offset;
-        layer.v_offset = layer_info.v_offset;
-        layer.u_tiling = layer_info.u_tiling;
-        layer.v_tiling = layer_info.v_tiling;
-        layer.rotation = layer_info.rotation;
-        layer.map_type = layer_info.map_type;
-
+        let (_, layer) = read_material_layer::<_, E>(file)?;
         material.layers.push(layer);
     }
     xac.materials.push(material);
-    xac
+    Ok(())
 }
-fn calculate_faces(indices: &Vec<i32>) -> Vec<[i32; 4]> {
-    let mut faces: Vec<[i32; 4]> = Vec::new();
 
-    for i in (0..indices.len()).step_by(4) {
-        let face_indices = &indices[i..(i + 4)];
-        let face: [i32; 4] = [
-            face_indices[0].clone() - 1,
-            face_indices[1].clone() - 1,
-            face_indices[2].clone() - 1,
-            face_indices[3].clone() - 1,
-        ];
-        faces.push(face);
+fn read_material_layer_chunk<R: Read, E: ByteOrder>(
+    file: &mut R,
+    xac: &mut XacActorFile,
+) -> Result<(), XacError> {
+    let (material_id, layer) = read_material_layer::<_, E>(file)?;
+    match xac.materials.get_mut(material_id as usize) {
+        Some(material) if material_id >= 0 => material.layers.push(layer),
+        _ => {
+            return Err(XacError::inconsistent(format!(
+                "Material layer refers to unknown material {}",
+                material_id
+            )))
+        }
     }
+    Ok(())
+}
+
+// Layers have the same layout inline in a version 2 material and in a
+// standalone layer chunk, which also uses the material id.
+fn read_material_layer<R: Read, E: ByteOrder>(
+    file: &mut R,
+) -> Result<(i16, XacActorMaterialLayer), XacError> {
+    let mut layer_info = XacMaterialDefinitionChunkv2Layer {
+        amount: 0.0,
+        v_offset: 0.0,
+        u_offset: 0.0,
+        u_tiling: 0.0,
+        v_tiling: 0.0,
+        rotation: 0.0,
+        material_id: 0,
+        map_type: 0,
+    };
+
+    layer_info.amount = file.read_f32::<E>()?;
+    layer_info.u_offset = file.read_f32::<E>()?;
+    layer_info.v_offset = file.read_f32::<E>()?;
+    layer_info.u_tiling = file.read_f32::<E>()?;
+    layer_info.v_tiling = file.read_f32::<E>()?;
+    layer_info.rotation = file.read_f32::<E>()?;
+    layer_info.material_id = file.read_i16::<E>()?;
+    layer_info.map_type = file.read_u8()?;
+    file.read_u8()?; //Padding
+
+    let texture = xac_read_string::<_, E>(file)?;
+
+    let mut layer = XacActorMaterialLayer {
+        name: "".to_string(),
+        amount: 0.0,
+        v_offset: 0.0,
+        u_offset: 0.0,
+        v_tiling: 0.0,
+        u_tiling: 0.0,
+        rotation: 0.0,
+        map_type: 0,
+    };
+
+    layer.name = texture;
+    layer.amount = layer_info.amount;
+    layer.u_offset = layer_info.u_offset;
+    layer.v_offset = layer_info.v_offset;
+    layer.u_tiling = layer_info.u_tiling;
+    layer.v_tiling = layer_info.v_tiling;
+    layer.rotation = layer_info.rotation;
+    layer.map_type = layer_info.map_type;
 
-    faces
+    Ok((layer_info.material_id, layer))
 }
-fn read_mesh<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'a mut XacActorFile {
+
+fn read_mesh<R: Read, E: ByteOrder>(file: &mut R, xac: &mut XacActorFile) -> Result<(), XacError> {
     let mut mesh_info = XacMeshChunkv1Header {
         node_id: 0,
         num_influence_ranges: 0,
@@ -514,371 +760,598 @@ fn read_mesh<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'a mut XacAc
         num_vertex_element: 0,
         is_collision_mesh: false,
     };
-    mesh_info.node_id = file.read_i32::<LittleEndian>().unwrap();
-    mesh_info.num_influence_ranges = file.read_i32::<LittleEndian>().unwrap();
-    mesh_info.num_vertices = file.read_i32::<LittleEndian>().unwrap();
-    mesh_info.num_indices = file.read_i32::<LittleEndian>().unwrap();
-    mesh_info.num_sub_meshes = file.read_i32::<LittleEndian>().unwrap();
-    mesh_info.num_vertex_element = file.read_i32::<LittleEndian>().unwrap();
-    mesh_info.is_collision_mesh = xac_read_boolean(file);
-    file.read_u8().unwrap(); //Padding
-    file.read_u8().unwrap(); //Padding
-    file.read_u8().unwrap(); //Padding
-
-    for p_node in xac.root_nodes.iter_mut() {
-        if p_node.node_id == mesh_info.node_id.clone() as usize {
-            let mut p_mesh = XacActorMesh { sub_meshes: vec![] };
-
-            let mut positions: Vec<XacVec3d> = Vec::new();
-            let mut normals: Vec<XacVec3d> = Vec::new();
-            let mut tangents: Vec<XacVec4d> = Vec::new();
-            let mut bi_tangents: Vec<XacVec4d> = Vec::new();
-            let mut uv_set: Vec<Vec<XacVec2d>> = Vec::new();
-            let mut influence_range_indices: Vec<i32> = Vec::new();
-            let mut colors_128: Vec<Vec<XacVec3d>> = Vec::new();
-            let mut colors_32: Vec<Vec<XacColor8>> = Vec::new();
-
-            for _ in 0..mesh_info.num_vertex_element {
-                let mut vertices_attribute = XacMeshChunkv1VertexElement {
-                    usage: 0,
-                    element_size: 0,
-                    keep_originals: false,
-                    is_scale_factor: false,
-                };
-
-                vertices_attribute.usage = file.read_u32::<LittleEndian>().unwrap();
-                vertices_attribute.element_size = file.read_i32::<LittleEndian>().unwrap();
-                vertices_attribute.keep_originals = xac_read_boolean(file);
-                vertices_attribute.is_scale_factor = xac_read_boolean(file);
-                file.read_u8().unwrap(); //Padding
-                file.read_u8().unwrap(); //Padding
-
-                if vertices_attribute.usage == XacPositionId as u32 {
-                    for _ in 0..mesh_info.num_vertices {
-                        positions.push(xac_read_vec3d(file))
-                    }
-                }
-                if vertices_attribute.usage == XacNormalId as u32 {
-                    for _ in 0..mesh_info.num_vertices {
-                        normals.push(xac_read_vec3d(file))
-                    }
-                }
-                if vertices_attribute.usage == XacTangentId as u32 {
-                    if tangents.is_empty() {
-                        for _ in 0..mesh_info.num_vertices {
-                            tangents.push(xac_read_vec4d(file));
-                        }
-                    } else if bi_tangents.is_empty() {
-                        for _ in 0..mesh_info.num_vertices {
-                            bi_tangents.push(xac_read_vec4d(file));
-                        }
-                    }
-                }
-                if vertices_attribute.usage == XacUVCoordId as u32 {
-                    let mut temp_uv_set: Vec<XacVec2d> = Vec::new();
-                    for _ in 0..mesh_info.num_vertices {
-                        temp_uv_set.push(xac_read_vec2d(file));
-                    }
-                    uv_set.push(temp_uv_set);
-                }
-                if vertices_attribute.usage == XacColor32Id as u32 {
-                    let mut temp_colors_32: Vec<XacColor8> = Vec::new();
-                    for _ in 0..mesh_info.num_vertices {
-                        temp_colors_32.push(xac_read_color8(file));
-                    }
-                    colors_32.push(temp_colors_32);
-                }
-                if vertices_attribute.usage == XacInfluenceRangeId as u32 {
-                    for _ in 0..mesh_info.num_vertices {
-                        influence_range_indices.push(file.read_i32::<LittleEndian>().unwrap());
-                    }
-                }
-                if vertices_attribute.usage == XacColor128Id as u32 {
-                    let mut temp_colors_128: Vec<XacVec3d> = Vec::new();
-                    for _ in 0..mesh_info.num_vertices {
-                        temp_colors_128.push(xac_read_vec3d(file));
-                    }
-                    colors_128.push(temp_colors_128);
-                }
-            }
+    mesh_info.node_id = file.read_i32::<E>()?;
+    mesh_info.num_influence_ranges = file.read_i32::<E>()?;
+    mesh_info.num_vertices = file.read_i32::<E>()?;
+    mesh_info.num_indices = file.read_i32::<E>()?;
+    mesh_info.num_sub_meshes = file.read_i32::<E>()?;
+    mesh_info.num_vertex_element = file.read_i32::<E>()?;
+    mesh_info.is_collision_mesh = xac_read_boolean(file)?;
+    file.read_u8()?; //Padding
+    file.read_u8()?; //Padding
+    file.read_u8()?; //Padding
+
+    if mesh_info.node_id < 0 || mesh_info.node_id as usize >= xac.nodes.len() {
+        return Err(XacError::inconsistent(format!(
+            "Mesh refers to unknown node {}",
+            mesh_info.node_id
+        )));
+    }
+    if mesh_info.num_vertices < 0 {
+        return Err(XacError::inconsistent("Invalid number of mesh vertices"));
+    }
+    let num_nodes = xac.nodes.len();
+    let p_node = &mut xac.nodes[mesh_info.node_id as usize];
+    let mut p_mesh = XacActorMesh {
+        sub_meshes: vec![],
+        vertex_attributes: vec![],
+    };
 
-            if !mesh_info.is_collision_mesh.clone() {
-                for position in positions.clone() {
-                    println!("v {} {} {}", position.x, position.y, position.z);
-                }
+    let mut positions: Vec<XacVec3d> = Vec::new();
+    let mut normals: Vec<XacVec3d> = Vec::new();
+    let mut tangents: Vec<XacVec4d> = Vec::new();
+    let mut bi_tangents: Vec<XacVec4d> = Vec::new();
+    let mut uv_set: Vec<Vec<XacVec2d>> = Vec::new();
+    let mut influence_range_indices: Vec<i32> = Vec::new();
+    let mut colors_128: Vec<Vec<XacVec4d>> = Vec::new();
+    let mut colors_32: Vec<Vec<XacColor8>> = Vec::new();
+
+    for _ in 0..mesh_info.num_vertex_element {
+        let mut vertices_attribute = XacMeshChunkv1VertexElement {
+            usage: 0,
+            element_size: 0,
+            keep_originals: false,
+            is_scale_factor: false,
+        };
 
-                for uv_set in uv_set.clone() {
-                    for uv in uv_set.clone() {
-                        println!("vt {} {}", uv.x, uv.y);
-                    }
-                }
+        vertices_attribute.usage = file.read_u32::<E>()?;
+        vertices_attribute.element_size = file.read_i32::<E>()?;
+        vertices_attribute.keep_originals = xac_read_boolean(file)?;
+        vertices_attribute.is_scale_factor = xac_read_boolean(file)?;
+        file.read_u8()?; //Padding
+        file.read_u8()?; //Padding
+
+        if vertices_attribute.element_size < 0 {
+            return Err(XacError::inconsistent(format!(
+                "Invalid element size {} for vertex attribute {}",
+                vertices_attribute.element_size, vertices_attribute.usage
+            )));
+        }
+        let element_size = vertices_attribute.element_size as usize;
+        let data = read_vertex_attribute_data(file, element_size, mesh_info.num_vertices as usize)?;
+
+        let usage = vertices_attribute.usage;
+        // Size of the decoded value, elements wider than that keep their raw bytes
+        // so the writer can put the extra bytes back.
+        let mut decoded_size: Option<usize> = None;
+        if usage == XacPositionId as u32 {
+            positions = read_vertex_elements(&data, element_size, 12, xac_read_vec3d::<_, E>)?;
+            decoded_size = Some(12);
+        } else if usage == XacNormalId as u32 {
+            normals = read_vertex_elements(&data, element_size, 12, xac_read_vec3d::<_, E>)?;
+            decoded_size = Some(12);
+        } else if usage == XacTangentId as u32 && tangents.is_empty() {
+            tangents = read_vertex_elements(&data, element_size, 16, xac_read_vec4d::<_, E>)?;
+            decoded_size = Some(16);
+        } else if usage == XacTangentId as u32 && bi_tangents.is_empty() {
+            bi_tangents = read_vertex_elements(&data, element_size, 16, xac_read_vec4d::<_, E>)?;
+            decoded_size = Some(16);
+        } else if usage == XacUVCoordId as u32 {
+            uv_set.push(read_vertex_elements(
+                &data,
+                element_size,
+                8,
+                xac_read_vec2d::<_, E>,
+            )?);
+            decoded_size = Some(8);
+        } else if usage == XacColor32Id as u32 {
+            colors_32.push(read_vertex_elements(
+                &data,
+                element_size,
+                4,
+                xac_read_color8,
+            )?);
+            decoded_size = Some(4);
+        } else if usage == XacInfluenceRangeId as u32 {
+            influence_range_indices =
+                read_vertex_elements(&data, element_size, 4, |element| element.read_i32::<E>())?;
+            decoded_size = Some(4);
+        } else if usage == XacColor128Id as u32 {
+            colors_128.push(read_vertex_elements(
+                &data,
+                element_size,
+                16,
+                xac_read_vec4d::<_, E>,
+            )?);
+            decoded_size = Some(16);
+        }
 
-                for normal in normals.clone() {
-                    println!("v {} {} {}", normal.x, normal.y, normal.z);
-                }
-                let faces = calculate_faces(&influence_range_indices.clone());
-                for face in faces {
-                    println!("f {} {} {} {} ", face[0], face[1], face[2], face[3],);
-                }
-            }
+        p_mesh.vertex_attributes.push(XacActorVertexAttribute {
+            usage: vertices_attribute.usage,
+            element_size: vertices_attribute.element_size,
+            keep_originals: vertices_attribute.keep_originals,
+            is_scale_factor: vertices_attribute.is_scale_factor,
+            data: match decoded_size {
+                Some(size) if element_size <= size => vec![],
+                _ => data,
+            },
+        });
+    }
 
-            let mut vertex_offset: usize = 0;
-            let mut index_offset: usize = 0;
-            for _ in 0..mesh_info.num_sub_meshes {
-                let mut submeshes = XacMeshChunkv1SubMesh {
-                    num_indices: 0,
-                    num_vertices: 0,
-                    material_id: 0,
-                    num_bones: 0,
-                };
-
-                let mut submesh = XacActorSubMesh {
-                    name: "".to_string(),
-                    material_id: 0,
-                    vertex_positions: vec![],
-                    vertex_normals: vec![],
-                    vertex_tangents: vec![],
-                    vertex_bi_tangents: vec![],
-                    vertex_uvs: vec![],
-                    vertex_colors_32: vec![],
-                    vertex_colors_128: vec![],
-                    vertex_influences: vec![],
-                    indices: vec![],
-                    influence_range_indices: vec![],
-                };
-                submeshes.num_indices = file.read_i32::<LittleEndian>().unwrap();
-                submeshes.num_vertices = file.read_i32::<LittleEndian>().unwrap();
-                submeshes.material_id = file.read_i32::<LittleEndian>().unwrap();
-                submeshes.num_bones = file.read_i32::<LittleEndian>().unwrap();
-                submesh.name = p_node.name.clone();
-
-                if !positions.is_empty() {
-                    let temp = &positions;
-                    submesh.vertex_positions = temp[vertex_offset
-                        ..(submeshes.num_vertices.clone() as usize
-                            + vertex_offset.clone() as usize)]
-                        .to_vec();
-                }
-                if !normals.is_empty() {
-                    let temp = &normals;
-                    submesh.vertex_normals = temp[vertex_offset
-                        ..(submeshes.num_vertices.clone() as usize
-                            + vertex_offset.clone() as usize)]
-                        .to_vec();
-                }
-                if !tangents.is_empty() {
-                    let temp = &tangents;
-                    submesh.vertex_tangents = temp[vertex_offset
-                        ..(submeshes.num_vertices.clone() as usize
-                            + vertex_offset.clone() as usize)]
-                        .to_vec();
-                }
-                if !bi_tangents.is_empty() {
-                    let temp_bi_tangents = &bi_tangents;
-                    submesh.vertex_bi_tangents = temp_bi_tangents[vertex_offset
-                        ..(submeshes.num_vertices.clone() as usize
-                            + vertex_offset.clone() as usize)]
-                        .to_vec();
-                }
+    let mut vertex_offset: usize = 0;
+    for _ in 0..mesh_info.num_sub_meshes {
+        let mut submeshes = XacMeshChunkv1SubMesh {
+            num_indices: 0,
+            num_vertices: 0,
+            material_id: 0,
+            num_bones: 0,
+        };
 
-                if !influence_range_indices.is_empty() {
-                    xac.sub_mesh_influence_range_indices
-                        .push(XacSubMeshInfluenceRange {
-                            node_id: mesh_info.node_id.clone() as usize,
-                            indices: influence_range_indices[vertex_offset
-                                ..(submeshes.num_vertices.clone() as usize
-                                    + vertex_offset.clone() as usize)]
-                                .to_vec(),
-                        });
-                    submesh.influence_range_indices = influence_range_indices[vertex_offset
-                        ..(submeshes.num_vertices.clone() as usize
-                            + vertex_offset.clone() as usize)]
-                        .to_vec();
-                }
+        let mut submesh = XacActorSubMesh {
+            name: "".to_string(),
+            material_id: 0,
+            vertex_positions: vec![],
+            vertex_normals: vec![],
+            vertex_tangents: vec![],
+            vertex_bi_tangents: vec![],
+            vertex_uvs: vec![],
+            vertex_colors_32: vec![],
+            vertex_colors_128: vec![],
+            vertex_influences: vec![],
+            bones: vec![],
+            indices: vec![],
+            influence_range_indices: vec![],
+        };
+        submeshes.num_indices = file.read_i32::<E>()?;
+        submeshes.num_vertices = file.read_i32::<E>()?;
+        submeshes.material_id = file.read_i32::<E>()?;
+        submeshes.num_bones = file.read_i32::<E>()?;
+        submesh.name = p_node.name.clone();
+        submesh.material_id = submeshes.material_id;
+
+        if submeshes.num_vertices < 0
+            || vertex_offset + submeshes.num_vertices as usize > mesh_info.num_vertices as usize
+        {
+            return Err(XacError::inconsistent(
+                "Submesh vertices exceed the number of mesh vertices",
+            ));
+        }
+        let vertex_range = vertex_offset..(vertex_offset + submeshes.num_vertices as usize);
 
-                for i in 0..colors_32.len() {
-                    let temp = &colors_32;
-                    submesh.vertex_colors_32 = temp[i][vertex_offset
-                        ..(submeshes.num_vertices.clone() as usize
-                            + vertex_offset.clone() as usize)]
-                        .to_vec();
-                }
-                for i in 0..colors_128.len() {
-                    let temp = &colors_128;
-                    submesh.vertex_colors_128 = temp[i][vertex_offset
-                        ..(submeshes.num_vertices.clone() as usize
-                            + vertex_offset.clone() as usize)]
-                        .to_vec();
-                }
-                for i in 0..uv_set.len() {
-                    let temp = &uv_set;
-                    submesh.vertex_uvs = temp[i][vertex_offset
-                        ..(submeshes.num_vertices.clone() as usize
-                            + vertex_offset.clone() as usize)]
-                        .to_vec();
-                }
+        if !positions.is_empty() {
+            submesh.vertex_positions = positions[vertex_range.clone()].to_vec();
+        }
+        if !normals.is_empty() {
+            submesh.vertex_normals = normals[vertex_range.clone()].to_vec();
+        }
+        if !tangents.is_empty() {
+            submesh.vertex_tangents = tangents[vertex_range.clone()].to_vec();
+        }
+        if !bi_tangents.is_empty() {
+            submesh.vertex_bi_tangents = bi_tangents[vertex_range.clone()].to_vec();
+        }
 
-                for _ in 0..submeshes.num_indices {
-                    submesh
-                        .influence_range_indices
-                        .push(file.read_i32::<LittleEndian>().unwrap());
-                }
+        if !influence_range_indices.is_empty() {
+            xac.sub_mesh_influence_range_indices
+                .push(XacSubMeshInfluenceRange {
+                    node_id: mesh_info.node_id as usize,
+                    indices: influence_range_indices[vertex_range.clone()].to_vec(),
+                });
+            submesh.influence_range_indices =
+                influence_range_indices[vertex_range.clone()].to_vec();
+        }
 
-                for _ in 0..(4 * &submeshes.num_bones) {
-                    file.read_u8().unwrap();
-                }
-                vertex_offset = vertex_offset + submeshes.num_vertices.clone() as usize;
-                index_offset = index_offset + submeshes.num_indices.clone() as usize;
-                p_mesh.sub_meshes.push(submesh.clone());
-
-                if !mesh_info.is_collision_mesh.clone() {
-                    if !p_node.visual_mesh.sub_meshes.is_empty().clone() {
-                        panic!("Node already has a visual mesh");
-                    }
-                    p_node.visual_mesh = p_mesh.clone();
-                } else {
-                    if !p_node.collision_mesh.sub_meshes.is_empty().clone() {
-                        panic!("Node already has a collision mesh");
-                    }
-
-                    p_node.collision_mesh = p_mesh.clone();
-                }
+        for colors in colors_32.iter() {
+            submesh
+                .vertex_colors_32
+                .push(colors[vertex_range.clone()].to_vec());
+        }
+        for colors in colors_128.iter() {
+            submesh
+                .vertex_colors_128
+                .push(colors[vertex_range.clone()].to_vec());
+        }
+        for uvs in uv_set.iter() {
+            submesh.vertex_uvs.push(uvs[vertex_range.clone()].to_vec());
+        }
+
+        if submeshes.num_indices < 0 || submeshes.num_indices % 3 != 0 {
+            return Err(XacError::inconsistent(
+                "Submesh index count is not a multiple of three",
+            ));
+        }
+        // Indices are stored relative to the first vertex of the submesh.
+        for _ in 0..submeshes.num_indices {
+            let index = file.read_u32::<E>()?;
+            if index as usize >= submeshes.num_vertices as usize {
+                return Err(XacError::inconsistent(
+                    "Submesh index refers to a vertex outside the submesh",
+                ));
             }
+            submesh.indices.push(index);
+        }
 
-            xac.mesh_num_influence_range.push(XacInfluenceRange {
-                node_id: p_node.node_id.clone(),
-                num: mesh_info.num_influence_ranges.clone(),
-            });
+        for _ in 0..submeshes.num_bones {
+            let bone_node_id = file.read_u32::<E>()? as usize;
+            if bone_node_id >= num_nodes {
+                return Err(XacError::inconsistent(format!(
+                    "Submesh bone refers to unknown node {}",
+                    bone_node_id
+                )));
+            }
+            submesh.bones.push(bone_node_id);
         }
+        vertex_offset += submeshes.num_vertices as usize;
+        p_mesh.sub_meshes.push(submesh);
     }
 
-    xac
+    if !mesh_info.is_collision_mesh {
+        if !p_node.visual_mesh.sub_meshes.is_empty() {
+            return Err(XacError::inconsistent("Node already has a visual mesh"));
+        }
+        p_node.visual_mesh = p_mesh;
+    } else {
+        if !p_node.collision_mesh.sub_meshes.is_empty() {
+            return Err(XacError::inconsistent("Node already has a collision mesh"));
+        }
+
+        p_node.collision_mesh = p_mesh;
+    }
+
+    xac.mesh_num_influence_range.push(XacInfluenceRange {
+        node_id: p_node.node_id,
+        num: mesh_info.num_influence_ranges,
+        is_collision_mesh: mesh_info.is_collision_mesh,
+    });
+
+    Ok(())
 }
 
-/*
-fn read_skinning<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'a mut XacActorFile {
+// Reads one vertex attribute block without allocating more than the stream
+// actually holds, so a corrupt vertex count fails as a truncated chunk.
+fn read_vertex_attribute_data<R: Read>(
+    file: &mut R,
+    element_size: usize,
+    num_vertices: usize,
+) -> Result<Vec<u8>, XacError> {
+    let length = element_size
+        .checked_mul(num_vertices)
+        .ok_or_else(|| XacError::inconsistent("Vertex attribute size overflows"))?;
+    let mut data = Vec::new();
+    file.by_ref().take(length as u64).read_to_end(&mut data)?;
+    if data.len() != length {
+        return Err(XacError::TruncatedChunk {
+            chunk_type: None,
+            offset: 0,
+        });
+    }
+    Ok(data)
+}
+
+// Decodes one value from the start of every `element_size` bytes, elements
+// wider than the decoded type carry trailing data that is skipped.
+fn read_vertex_elements<'a, T>(
+    data: &'a [u8],
+    element_size: usize,
+    minimum_size: usize,
+    read: impl Fn(&mut &'a [u8]) -> std::io::Result<T>,
+) -> Result<Vec<T>, XacError> {
+    if element_size < minimum_size {
+        return Err(XacError::inconsistent(format!(
+            "Vertex element size {} is smaller than {} bytes",
+            element_size, minimum_size
+        )));
+    }
+    let mut elements = Vec::with_capacity(data.len() / element_size);
+    for mut element in data.chunks_exact(element_size) {
+        elements.push(read(&mut element)?);
+    }
+    Ok(elements)
+}
+fn read_skinning<R: Read, E: ByteOrder>(
+    file: &mut R,
+    xac: &mut XacActorFile,
+    version: i32,
+) -> Result<(), XacError> {
     let mut influences_header = XacSkinningChunkv3Header {
         node_id: 0,
         num_local_bones: 0,
         num_influences: 0,
         is_for_collision_mesh: false,
     };
-    influences_header.node_id = file.read_i32::<LittleEndian>().unwrap();
-    influences_header.num_local_bones = file.read_i32::<LittleEndian>().unwrap();
-    influences_header.num_influences = file.read_i32::<LittleEndian>().unwrap();
-    influences_header.is_for_collision_mesh = xac_read_boolean(file);
-    file.read_u8().unwrap(); //Padding
-    file.read_u8().unwrap(); //Padding
-    file.read_u8().unwrap(); //Padding
-
-    let mut influences = XacSkinningChunkv3Influence {
+    influences_header.node_id = file.read_i32::<E>()?;
+    if version >= 2 {
+        influences_header.num_local_bones = file.read_i32::<E>()?;
+    }
+    if version >= 3 {
+        influences_header.num_influences = file.read_i32::<E>()?;
+    }
+    influences_header.is_for_collision_mesh = xac_read_boolean(file)?;
+    file.read_u8()?; //Padding
+    file.read_u8()?; //Padding
+    file.read_u8()?; //Padding
+
+    // The mesh chunk tells how many original vertices (and therefore
+    // influence ranges) the skinned mesh has.
+    let num_influence_ranges = match xac.mesh_num_influence_range.iter().find(|range| {
+        range.node_id == influences_header.node_id as usize
+            && range.is_collision_mesh == influences_header.is_for_collision_mesh
+    }) {
+        Some(range) => range.num,
+        None => {
+            return Err(XacError::inconsistent(format!(
+                "Skinning refers to node {} which has no mesh",
+                influences_header.node_id
+            )))
+        }
+    };
+
+    let mut influences: Vec<XacSkinningChunkv3Influence> = Vec::new();
+    let mut influence_ranges: Vec<XacSkinningChunkv3InfluenceRange> = Vec::new();
+    if version >= 3 {
+        for _ in 0..influences_header.num_influences {
+            influences.push(read_skinning_influence::<_, E>(file)?);
+        }
+
+        for _ in 0..num_influence_ranges {
+            let mut influence_range = XacSkinningChunkv3InfluenceRange {
+                first_influence_index: 0,
+                num_influences: 0,
+            };
+            influence_range.first_influence_index = file.read_i32::<E>()?;
+            influence_range.num_influences = file.read_i32::<E>()?;
+            if influence_range.first_influence_index < 0
+                || influence_range.num_influences < 0
+                || influence_range.first_influence_index as usize
+                    + influence_range.num_influences as usize
+                    > influences.len()
+            {
+                return Err(XacError::inconsistent(
+                    "Influence range exceeds the number of influences",
+                ));
+            }
+            influence_ranges.push(influence_range);
+        }
+    } else {
+        // Older versions store the influences of each original vertex inline,
+        // preceded by their count. They are flattened into the version 3
+        // influence table and ranges.
+        for _ in 0..num_influence_ranges {
+            let num_vertex_influences = file.read_u8()?;
+            influence_ranges.push(XacSkinningChunkv3InfluenceRange {
+                first_influence_index: influences.len() as i32,
+                num_influences: num_vertex_influences as i32,
+            });
+            for _ in 0..num_vertex_influences {
+                influences.push(read_skinning_influence::<_, E>(file)?);
+            }
+        }
+        influences_header.num_influences = influences.len() as i32;
+    }
+
+    let node = &mut xac.nodes[influences_header.node_id as usize];
+    let mesh = if influences_header.is_for_collision_mesh {
+        &mut node.collision_mesh
+    } else {
+        &mut node.visual_mesh
+    };
+    resolve_vertex_influences(mesh, &influences, &influence_ranges)?;
+
+    Ok(())
+}
+
+fn read_skinning_influence<R: Read, E: ByteOrder>(
+    file: &mut R,
+) -> Result<XacSkinningChunkv3Influence, XacError> {
+    let mut influence = XacSkinningChunkv3Influence {
         weight: 0.0,
         node_id: 0,
     };
-    influences.node_id = file.read_i16::<LittleEndian>().unwrap();
-    influences.weight = file.read_f32::<LittleEndian>().unwrap();
+    influence.weight = file.read_f32::<E>()?;
+    influence.node_id = file.read_i16::<E>()?;
+    file.read_u8()?; //Padding
+    file.read_u8()?; //Padding
+    Ok(influence)
+}
 
-    let mut influence_ranges = XacSkinningChunkv3InfluenceRange {
-        first_influence_index: 0,
-        num_influences: 0,
-    };
+fn resolve_vertex_influences(
+    mesh: &mut XacActorMesh,
+    influences: &[XacSkinningChunkv3Influence],
+    influence_ranges: &[XacSkinningChunkv3InfluenceRange],
+) -> Result<(), XacError> {
+    let mut vertex_offset: usize = 0;
+    for sub_mesh in mesh.sub_meshes.iter_mut() {
+        if !sub_mesh.vertex_influences.is_empty() {
+            return Err(XacError::inconsistent(
+                "Duplicate vertex influences for submesh",
+            ));
+        }
 
-    influence_ranges.first_influence_index = file.read_i32::<LittleEndian>().unwrap();
-    influence_ranges.num_influences = file.read_i32::<LittleEndian>().unwrap();
-
-    for mut root_node in xac.root_nodes.iter_mut() {
-        if root_node.node_id.clone() == influences_header.node_id.clone() as usize {
-            if influences_header.is_for_collision_mesh.clone() {
-                for mut collision_mesh in root_node.collision_mesh.sub_meshes.iter_mut() {
-                    if !collision_mesh.vertex_influences.is_empty() {
-                        panic!("Duplicate vertex influences for submesh");
-                    }
-
-                    if collision_mesh.influence_range_indices.is_empty() {}
-                    for sub_mesh_influence_range_index in
-                        xac.sub_mesh_influence_range_indices.clone()
-                    {}
-
-                    for i in 0..collision_mesh.influence_range_indices.len() {
-                        for j in influence_ranges.first_influence_index
-                            ..(influence_ranges.first_influence_index.clone()
-                                + influence_ranges.num_influences.clone())
-                        {
-                            let mut influence_weight = XacBoneInfluence {
-                                node_id: 0,
-                                weight: 0.0,
-                            };
-                            influence_weight.node_id = influences.node_id.clone() as usize;
-                            influence_weight.weight = influences.weight.clone();
-                            collision_mesh.vertex_influences.push(influence_weight);
-                        }
-                    }
-                }
-            } else {
-                for mut visual_mesh in root_node.visual_mesh.sub_meshes.iter_mut() {
-                    if !visual_mesh.vertex_influences.is_empty() {
-                        panic!("Duplicate vertex influences for submesh");
-                    }
-
-                    if visual_mesh.influence_range_indices.is_empty() {}
-                    for sub_mesh_influence_range_index in
-                        xac.sub_mesh_influence_range_indices.clone()
-                    {}
-
-                    /*
-                                       for i in 0..visual_mesh.influence_range_indices.len() {
-                                           for j in influence_ranges.first_influence_index
-                                               ..(influence_ranges.first_influence_index.clone()
-                                                   + influence_ranges.num_influences.clone())
-                                           {
-                                               let mut influence_weight = XacBoneInfluence {
-                                                   node_id: 0,
-                                                   weight: 0.0,
-                                               };
-                                               influence_weight.node_id = influences.node_id.clone() as usize;
-                                               influence_weight.weight = influences.weight.clone();
-                                               visual_mesh.vertex_influences.push(influence_weight);
-                                           }
-                                       }
-
-                    */
+        for vertex in 0..sub_mesh.vertex_positions.len() {
+            // Without original vertex numbers every vertex is its own original.
+            let original_vertex = match sub_mesh.influence_range_indices.get(vertex) {
+                Some(index) => *index as usize,
+                None => vertex_offset + vertex,
+            };
+            let influence_range = match influence_ranges.get(original_vertex) {
+                Some(range) => range,
+                None => {
+                    return Err(XacError::inconsistent(
+                        "Vertex refers to a missing influence range",
+                    ))
                 }
             };
+
+            let first = influence_range.first_influence_index as usize;
+            let last = first + influence_range.num_influences as usize;
+            let mut vertex_influences: Vec<XacBoneInfluence> = Vec::new();
+            for influence in influences[first..last].iter() {
+                vertex_influences.push(XacBoneInfluence {
+                    node_id: influence.node_id as u16 as usize,
+                    weight: influence.weight,
+                });
+            }
+            sub_mesh.vertex_influences.push(vertex_influences);
         }
+        vertex_offset += sub_mesh.vertex_positions.len();
     }
-
-    xac
+    Ok(())
 }
 
-
-fn read_morph_target<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'a mut XacActorFile {
-    let num_morph_targets = file.read_i32::<LittleEndian>().unwrap();
-    let morph_target_lod_idx = file.read_i32::<LittleEndian>().unwrap();
-    let mut morph_target = XacMorphTargetsChunkv1MorphTarget {
-        range_min: 0.0,
-        range_max: 0.0,
-        lod_level: 0,
-        num_deformations: 0,
-        num_transformations: 0,
-        phoneme_set_bitmask: 0,
+fn read_morph_target<R: Read, E: ByteOrder>(
+    file: &mut R,
+    xac: &mut XacActorFile,
+) -> Result<(), XacError> {
+    let num_morph_targets = file.read_i32::<E>()?;
+    let morph_target_lod_idx = file.read_i32::<E>()?;
+    if num_morph_targets < 0 {
+        return Err(XacError::inconsistent("Invalid number of morph targets"));
+    }
+    let mut morph_target_chunk = XacMorphTargetChunk {
+        lod_level: morph_target_lod_idx,
+        morph_target_ids: vec![],
     };
-    morph_target.range_min = file.read_f32::<LittleEndian>().unwrap();
-    morph_target.range_max = file.read_f32::<LittleEndian>().unwrap();
-    morph_target.lod_level = file.read_i32::<LittleEndian>().unwrap();
-    morph_target.num_deformations = file.read_i32::<LittleEndian>().unwrap();
-    morph_target.num_transformations = file.read_i32::<LittleEndian>().unwrap();
-    morph_target.phoneme_set_bitmask = file.read_i32::<LittleEndian>().unwrap();
-    xac
-}
 
- */
+    for _ in 0..num_morph_targets {
+        let mut morph_target = XacMorphTargetsChunkv1MorphTarget {
+            range_min: 0.0,
+            range_max: 0.0,
+            lod_level: 0,
+            num_deformations: 0,
+            num_transformations: 0,
+            phoneme_set_bitmask: 0,
+        };
+        morph_target.range_min = file.read_f32::<E>()?;
+        morph_target.range_max = file.read_f32::<E>()?;
+        morph_target.lod_level = file.read_i32::<E>()?;
+        morph_target.num_deformations = file.read_i32::<E>()?;
+        morph_target.num_transformations = file.read_i32::<E>()?;
+        morph_target.phoneme_set_bitmask = file.read_i32::<E>()?;
+        let morph_target_name = xac_read_string::<_, E>(file)?;
+
+        let mut p_morph_target = XacActorMorphTarget {
+            name: morph_target_name,
+            range_min: morph_target.range_min,
+            range_max: morph_target.range_max,
+            lod_level: morph_target.lod_level,
+            phoneme_set_bitmask: morph_target.phoneme_set_bitmask,
+            deformation: vec![],
+            transformations: vec![],
+        };
+
+        for _ in 0..morph_target.num_deformations {
+            let mut deformation_info = XacMorphTargetsChunkv1Deformation {
+                node_id: 0,
+                min_value: 0.0,
+                max_value: 0.0,
+                num_vertices: 0,
+            };
+            deformation_info.node_id = file.read_i32::<E>()?;
+            deformation_info.min_value = file.read_f32::<E>()?;
+            deformation_info.max_value = file.read_f32::<E>()?;
+            deformation_info.num_vertices = file.read_i32::<E>()?;
+            if deformation_info.node_id < 0 || deformation_info.node_id >= xac.num_nodes {
+                return Err(XacError::inconsistent(format!(
+                    "Morph target {} deforms unknown node {}",
+                    p_morph_target.name, deformation_info.node_id
+                )));
+            }
+
+            let mut deformation = XacActorNodeDeformation {
+                node_id: deformation_info.node_id as usize,
+                min_value: deformation_info.min_value,
+                max_value: deformation_info.max_value,
+                vertex_indices: vec![],
+                position_offsets: vec![],
+                normal_offsets: vec![],
+                tangent_offsets: vec![],
+                compressed_position_offsets: vec![],
+                compressed_normal_offsets: vec![],
+                compressed_tangent_offsets: vec![],
+            };
+
+            for _ in 0..deformation_info.num_vertices {
+                let position = xac_read_vec3d16::<_, E>(file)?;
+                deformation.position_offsets.push(xac_decompress_vec3d16(
+                    &position,
+                    deformation_info.min_value,
+                    deformation_info.max_value,
+                ));
+                deformation.compressed_position_offsets.push(position);
+            }
+            for _ in 0..deformation_info.num_vertices {
+                let normal = xac_read_vec3d8(file)?;
+                deformation
+                    .normal_offsets
+                    .push(xac_decompress_vec3d8(&normal, -2.0, 2.0));
+                deformation.compressed_normal_offsets.push(normal);
+            }
+            for _ in 0..deformation_info.num_vertices {
+                let tangent = xac_read_vec3d8(file)?;
+                deformation
+                    .tangent_offsets
+                    .push(xac_decompress_vec3d8(&tangent, -2.0, 2.0));
+                deformation.compressed_tangent_offsets.push(tangent);
+            }
+            for _ in 0..deformation_info.num_vertices {
+                deformation.vertex_indices.push(file.read_i32::<E>()?);
+            }
+            p_morph_target.deformation.push(deformation);
+        }
 
-fn read_shader_material<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'a mut XacActorFile {
+        for _ in 0..morph_target.num_transformations {
+            let mut transformation = XacMorphTargetsChunkv1Transformation {
+                node_id: 0,
+                rotation: XacQuaternion {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                    w: 0.0,
+                },
+                scale_rotation: XacQuaternion {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                    w: 0.0,
+                },
+                position: XacVec3d {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+                scale: XacVec3d {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+            };
+            transformation.node_id = file.read_i32::<E>()?;
+            transformation.rotation = xac_read_quaternion::<_, E>(file)?;
+            transformation.scale_rotation = xac_read_quaternion::<_, E>(file)?;
+            transformation.position = xac_read_vec3d::<_, E>(file)?;
+            transformation.scale = xac_read_vec3d::<_, E>(file)?;
+            p_morph_target.transformations.push(transformation);
+        }
+
+        morph_target_chunk
+            .morph_target_ids
+            .push(xac.morph_targets.len());
+        xac.morph_targets.push(p_morph_target);
+    }
+    xac.morph_target_chunks.push(morph_target_chunk);
+    Ok(())
+}
+
+fn read_shader_material<R: Read, E: ByteOrder>(
+    file: &mut R,
+    xac: &mut XacActorFile,
+) -> Result<(), XacError> {
     let mut shader_material = XacShaderMaterial {
         num_int: 0,
         num_float: 0,
         num_bool: 0,
         num_string: 0,
+        padding: 0,
         flag: 0,
         name_material: "".to_string(),
         name_shader: "".to_string(),
@@ -886,61 +1359,410 @@ fn read_shader_material<'a>(file: &'a mut File, xac: &'a mut XacActorFile) -> &'
         float_property: vec![],
         bool_property: vec![],
         string_property: vec![],
+        skipped_data: vec![],
     };
 
-    shader_material.num_int = file.read_i32::<LittleEndian>().unwrap();
-    shader_material.num_float = file.read_i32::<LittleEndian>().unwrap();
-    file.read_i32::<LittleEndian>().unwrap(); //Padding
-    shader_material.num_bool = file.read_i32::<LittleEndian>().unwrap();
-    shader_material.flag = file.read_i32::<LittleEndian>().unwrap();
-    shader_material.num_string = file.read_i32::<LittleEndian>().unwrap();
-    shader_material.name_material = xac_read_string(file);
-    shader_material.name_shader = xac_read_string(file);
+    shader_material.num_int = file.read_i32::<E>()?;
+    shader_material.num_float = file.read_i32::<E>()?;
+    shader_material.padding = file.read_i32::<E>()?;
+    shader_material.num_bool = file.read_i32::<E>()?;
+    shader_material.flag = file.read_i32::<E>()?;
+    shader_material.num_string = file.read_i32::<E>()?;
+    shader_material.name_material = xac_read_string::<_, E>(file)?;
+    shader_material.name_shader = xac_read_string::<_, E>(file)?;
 
-    for i in 0..shader_material.num_int {
+    for _ in 0..shader_material.num_int {
         let mut property = XacIntProperties {
             name_properties: "".to_string(),
             value: 0,
         };
-        property.name_properties = xac_read_string(file);
-        property.value = file.read_i32::<LittleEndian>().unwrap();
+        property.name_properties = xac_read_string::<_, E>(file)?;
+        property.value = file.read_i32::<E>()?;
         shader_material.int_property.push(property);
     }
 
-    for i in 0..shader_material.num_float {
+    for _ in 0..shader_material.num_float {
         let mut property = XacFloatProperties {
             name_properties: "".to_string(),
             value: 0.0,
         };
-        property.name_properties = xac_read_string(file);
-        property.value = file.read_f32::<LittleEndian>().unwrap();
+        property.name_properties = xac_read_string::<_, E>(file)?;
+        property.value = file.read_f32::<E>()?;
         shader_material.float_property.push(property);
     }
 
-    for i in 0..shader_material.num_bool {
+    for _ in 0..shader_material.num_bool {
         let mut property = XacBoolProperties {
             name_properties: "".to_string(),
             value: 0,
         };
 
-        property.name_properties = xac_read_string(file);
-        property.value = file.read_u8().unwrap();
+        property.name_properties = xac_read_string::<_, E>(file)?;
+        property.value = file.read_u8()?;
         shader_material.bool_property.push(property);
     }
-    let skip = file.read_i32::<LittleEndian>().unwrap();
+    let skip = file.read_i32::<E>()?;
     for _ in 0..skip {
-        file.read_u8().unwrap();
+        shader_material.skipped_data.push(file.read_u8()?);
     }
-    for i in 0..shader_material.num_string {
+    for _ in 0..shader_material.num_string {
         let mut property = XacStringProperties {
             name_properties: "".to_string(),
             value: "".to_string(),
         };
-        property.name_properties = xac_read_string(file);
-        property.value = xac_read_string(file);
+        property.name_properties = xac_read_string::<_, E>(file)?;
+        property.value = xac_read_string::<_, E>(file)?;
         shader_material.string_property.push(property);
     }
 
     xac.shader_materials.push(shader_material);
-    xac
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xac::xac_test_util::{actor_file, chunk, chunk_offsets, floats, string};
+    use byteorder::WriteBytesExt;
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut file = actor_file::<LittleEndian>(false);
+        file[..4].copy_from_slice(b"XSM ");
+        match XacActorFile::from_bytes(&file) {
+            Err(XacError::InvalidMagic {
+                offset: 0, magic, ..
+            }) => assert_eq!(&magic, b"XSM "),
+            other => panic!("expected invalid magic, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_major_version() {
+        let mut file = actor_file::<LittleEndian>(false);
+        file[4] = 2;
+        match XacActorFile::from_bytes(&file) {
+            Err(XacError::UnsupportedVersion {
+                chunk_type: None,
+                offset: 4,
+                major_version: 2,
+                minor_version: 0,
+            }) => {}
+            other => panic!("expected unsupported version, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn reports_truncated_chunk_at_its_start() {
+        let mut file = actor_file::<LittleEndian>(false);
+        let chunk_start = file.len();
+        chunk::<LittleEndian>(&mut file, 13, 1, &[0; 12]);
+        file.truncate(file.len() - 4);
+        match XacActorFile::from_bytes(&file) {
+            Err(XacError::TruncatedChunk {
+                chunk_type: Some(13),
+                offset,
+            }) => assert_eq!(offset, chunk_start as u64),
+            other => panic!("expected truncated chunk, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn reports_unsupported_chunk_version_at_its_start() {
+        let mut file = b"XAC ".to_vec();
+        file.extend_from_slice(&[1, 0, 0, 0]);
+        chunk::<LittleEndian>(&mut file, 11, 9, &[0; 8]);
+        match XacActorFile::from_bytes(&file) {
+            Err(XacError::UnsupportedChunkVersion {
+                chunk_type: Some(11),
+                offset: 8,
+                version: 9,
+            }) => {}
+            other => panic!(
+                "expected unsupported chunk version, got {:?}",
+                other.map(|_| ())
+            ),
+        }
+    }
+
+    #[test]
+    fn reports_inconsistent_count_inside_its_chunk() {
+        let mut file = b"XAC ".to_vec();
+        file.extend_from_slice(&[1, 0, 0, 0]);
+        let mut nodes = Vec::new();
+        nodes.extend_from_slice(&0i32.to_le_bytes());
+        nodes.extend_from_slice(&0i32.to_le_bytes());
+        chunk::<LittleEndian>(&mut file, 11, 1, &nodes);
+        match XacActorFile::from_bytes(&file) {
+            Err(err @ XacError::InconsistentCount { .. }) => {
+                assert_eq!(err.chunk_type(), Some(11));
+                assert_eq!(err.offset(), 28);
+            }
+            other => panic!("expected inconsistent count, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn reports_truncated_data_inside_its_chunk() {
+        let mut file = b"XAC ".to_vec();
+        file.extend_from_slice(&[1, 0, 0, 0]);
+        chunk::<LittleEndian>(&mut file, 13, 1, &[0; 6]);
+        match XacActorFile::from_bytes(&file) {
+            Err(err @ XacError::TruncatedChunk { .. }) => {
+                assert_eq!(err.chunk_type(), Some(13));
+                assert_eq!(err.offset(), file.len() as u64);
+            }
+            other => panic!("expected truncated chunk, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn keeps_morph_target_chunk_lod() {
+        let mut file = b"XAC ".to_vec();
+        file.extend_from_slice(&[1, 0, 0, 0]);
+        for (chunk_lod, morph_target_lod, name) in [(2, 0, "blink"), (3, 1, "smile")] {
+            let mut morph_targets = Vec::new();
+            for value in [1i32, chunk_lod] {
+                morph_targets.extend_from_slice(&value.to_le_bytes());
+            }
+            for value in [0.0f32, 1.0] {
+                morph_targets.extend_from_slice(&value.to_le_bytes());
+            }
+            for value in [morph_target_lod, 0, 0, 0, name.len() as i32] {
+                morph_targets.extend_from_slice(&value.to_le_bytes());
+            }
+            morph_targets.extend_from_slice(name.as_bytes());
+            chunk::<LittleEndian>(&mut file, 12, 1, &morph_targets);
+        }
+
+        let xac = XacActorFile::from_bytes(&file).unwrap();
+        let chunks: Vec<(i32, Vec<usize>)> = xac
+            .morph_target_chunks
+            .iter()
+            .map(|chunk| (chunk.lod_level, chunk.morph_target_ids.clone()))
+            .collect();
+        assert_eq!(chunks, vec![(2, vec![0]), (3, vec![1])]);
+        assert_eq!(xac.morph_targets[0].lod_level, 0);
+        assert_eq!(xac.morph_targets[1].lod_level, 1);
+    }
+
+    fn node_chain_file(num_nodes: i32) -> Vec<u8> {
+        let mut file = b"XAC ".to_vec();
+        file.extend_from_slice(&[1, 0, 0, 0]);
+        let mut nodes = Vec::new();
+        nodes.extend_from_slice(&num_nodes.to_le_bytes());
+        nodes.extend_from_slice(&1i32.to_le_bytes());
+        for node_id in 0..num_nodes {
+            nodes.extend_from_slice(&[0; 76]);
+            nodes.extend_from_slice(&(node_id - 1).to_le_bytes());
+            nodes.extend_from_slice(&((node_id + 1 < num_nodes) as i32).to_le_bytes());
+            nodes.extend_from_slice(&[0; 72]);
+            nodes.extend_from_slice(&0i32.to_le_bytes());
+        }
+        chunk::<LittleEndian>(&mut file, 11, 1, &nodes);
+        file
+    }
+
+    #[test]
+    fn builds_deep_node_tree_without_recursion() {
+        let num_nodes = 100_000;
+        let xac = XacActorFile::from_bytes(&node_chain_file(num_nodes)).unwrap();
+        assert_eq!(xac.nodes.len(), num_nodes as usize);
+        assert_eq!(xac.root_nodes.len(), 1);
+        let mut depth = 1;
+        let mut node = &xac.root_nodes[0];
+        while let Some(child) = node.children.first() {
+            assert_eq!(child.node_id, depth);
+            depth += 1;
+            node = child;
+        }
+        assert_eq!(depth, num_nodes as usize);
+    }
+
+    #[test]
+    fn builds_node_tree_in_file_order() {
+        let xac = XacActorFile::from_bytes(&actor_file::<LittleEndian>(false)).unwrap();
+        assert_eq!(xac.root_nodes.len(), 1);
+        let root = &xac.root_nodes[0];
+        assert_eq!(root.name, "root");
+        assert_eq!(root.children[0].name, "child");
+        assert_eq!(root.children[0].children[0].name, "grand");
+        // Meshes are attached after the hierarchy chunk, the tree still has them.
+        assert!(!root.children[0].children[0]
+            .visual_mesh
+            .sub_meshes
+            .is_empty());
+    }
+
+    #[test]
+    fn rejects_parent_cycle() {
+        let mut file = node_chain_file(3);
+        // Node 0 becomes the child of node 2, leaving no root.
+        let node_offset = |node_id: usize| 8 + 12 + 8 + node_id * 160;
+        file[24..28].copy_from_slice(&0i32.to_le_bytes());
+        file[node_offset(0) + 76..node_offset(0) + 80].copy_from_slice(&2i32.to_le_bytes());
+        file[node_offset(2) + 80..node_offset(2) + 84].copy_from_slice(&1i32.to_le_bytes());
+        match XacActorFile::from_bytes(&file) {
+            Err(err @ XacError::InconsistentCount { .. }) => {
+                assert_eq!(err.chunk_type(), Some(11));
+                assert!(err.to_string().contains("cycle"));
+            }
+            other => panic!("expected parent cycle, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn accepts_other_minor_versions() {
+        let mut file = actor_file::<LittleEndian>(false);
+        file[5] = 3;
+        let xac = XacActorFile::from_bytes(&file).unwrap();
+        assert_eq!(xac.header.minor_version, 3);
+        assert_eq!(xac.nodes.len(), 3);
+    }
+
+    #[test]
+    fn rejects_legacy_node_chunk() {
+        let mut file = b"XAC ".to_vec();
+        file.extend_from_slice(&[1, 0, 0, 0]);
+        chunk::<LittleEndian>(&mut file, 0, 1, &[0; 16]);
+        file.extend_from_slice(&actor_file::<LittleEndian>(false)[8..]);
+        match XacActorFile::from_bytes(&file) {
+            Err(XacError::UnsupportedChunkVersion {
+                chunk_type: Some(0),
+                offset: 8,
+                version: 1,
+            }) => {}
+            other => panic!("expected unsupported chunk, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    // The fixture with its first chunk of `type_id` replaced by `chunks`.
+    fn replace_chunk(type_id: i32, chunks: &[u8]) -> Vec<u8> {
+        let file = actor_file::<LittleEndian>(false);
+        let offsets = chunk_offsets::<LittleEndian>(&file);
+        let index = offsets.iter().position(|(id, _)| *id == type_id).unwrap();
+        let start = offsets[index].1;
+        let end = offsets.get(index + 1).map_or(file.len(), |(_, end)| *end);
+        let mut replaced = file[..start].to_vec();
+        replaced.extend_from_slice(chunks);
+        replaced.extend_from_slice(&file[end..]);
+        replaced
+    }
+
+    fn parse_fixture() -> XacActorFile {
+        XacActorFile::from_bytes(&actor_file::<LittleEndian>(false)).unwrap()
+    }
+
+    #[test]
+    fn reads_version_1_metadata() {
+        let mut metadata = Vec::new();
+        metadata.write_u32::<LittleEndian>(3).unwrap();
+        metadata.write_i32::<LittleEndian>(1).unwrap();
+        metadata.extend_from_slice(&[2, 5, 0, 0]);
+        for text in ["app", "orig.max", "date", "actor"] {
+            string::<LittleEndian>(&mut metadata, text);
+        }
+        let mut chunks = Vec::new();
+        chunk::<LittleEndian>(&mut chunks, 7, 1, &metadata);
+
+        let xac = XacActorFile::from_bytes(&replace_chunk(7, &chunks)).unwrap();
+        assert_eq!(xac.metadata.reposition_mask, 3);
+        assert_eq!(xac.metadata.repositioning_node, 1);
+        assert_eq!(xac.metadata.exporter_major_version, 2);
+        assert_eq!(xac.metadata.exporter_minor_version, 5);
+        assert_eq!(xac.metadata.retarget_root_offset, 0.0);
+        assert_eq!(xac.source_app, "app");
+        assert_eq!(xac.original_filename, "orig.max");
+        assert_eq!(xac.export_date, "date");
+        assert_eq!(xac.actor_name, "actor");
+    }
+
+    #[test]
+    fn reads_version_1_material_with_layer_chunk() {
+        let mut material = Vec::new();
+        floats::<LittleEndian>(&mut material, &[0.1; 16]);
+        floats::<LittleEndian>(&mut material, &[25.0, 1.0, 1.0, 1.5]);
+        material.extend_from_slice(&[1, 0, 2, 0]);
+        string::<LittleEndian>(&mut material, "body");
+        let mut layer = Vec::new();
+        floats::<LittleEndian>(&mut layer, &[1.0, 0.0, 0.0, 1.0, 1.0, 0.0]);
+        layer.write_i16::<LittleEndian>(0).unwrap();
+        layer.extend_from_slice(&[2, 0]);
+        string::<LittleEndian>(&mut layer, "body_diffuse.dds");
+        let mut chunks = Vec::new();
+        chunk::<LittleEndian>(&mut chunks, 3, 1, &material);
+        chunk::<LittleEndian>(&mut chunks, 4, 1, &layer);
+
+        let xac = XacActorFile::from_bytes(&replace_chunk(3, &chunks)).unwrap();
+        let material = &xac.materials[0];
+        assert_eq!(material.name, "body");
+        assert_eq!(material.shine, 25.0);
+        assert_eq!(material.ior, 1.5);
+        assert!(material.double_sided);
+        assert_eq!(material.transparency_type, 2);
+        assert_eq!(material.layers.len(), 1);
+        assert_eq!(material.layers[0].name, "body_diffuse.dds");
+        assert_eq!(material.layers[0].map_type, 2);
+        // Same material as the inline layer of version 2.
+        assert_eq!(
+            serde_json::to_value(material).unwrap(),
+            serde_json::to_value(&parse_fixture().materials[0]).unwrap()
+        );
+    }
+
+    // Influences of the fixture's four original vertices, stored inline
+    // after their count, with `num_local_bones` from version 2 on.
+    fn inline_skinning(version: i32) -> Vec<u8> {
+        let mut skinning = Vec::new();
+        skinning.write_i32::<LittleEndian>(2).unwrap();
+        if version >= 2 {
+            skinning.write_i32::<LittleEndian>(3).unwrap();
+        }
+        skinning.extend_from_slice(&[0, 0, 0, 0]);
+        let vertices: [&[(f32, i16)]; 4] =
+            [&[(1.0, 0)], &[(0.5, 0), (0.5, 1)], &[(1.0, 1)], &[(1.0, 2)]];
+        for influences in vertices {
+            skinning.write_u8(influences.len() as u8).unwrap();
+            for (weight, node_id) in influences {
+                skinning.write_f32::<LittleEndian>(*weight).unwrap();
+                skinning.write_i16::<LittleEndian>(*node_id).unwrap();
+                skinning.extend_from_slice(&[0, 0]);
+            }
+        }
+        let mut chunks = Vec::new();
+        chunk::<LittleEndian>(&mut chunks, 2, version, &skinning);
+        chunks
+    }
+
+    fn node_influences(xac: &XacActorFile) -> Vec<Vec<Vec<(usize, f32)>>> {
+        xac.nodes[2]
+            .visual_mesh
+            .sub_meshes
+            .iter()
+            .map(|sub_mesh| {
+                sub_mesh
+                    .vertex_influences
+                    .iter()
+                    .map(|influences| {
+                        influences
+                            .iter()
+                            .map(|influence| (influence.node_id, influence.weight))
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_inline_influences_of_skinning_versions_1_and_2() {
+        let expected = node_influences(&parse_fixture());
+        assert_eq!(expected[0][1], vec![(0, 0.5), (1, 0.5)]);
+        assert_eq!(expected[1][1], vec![(2, 1.0)]);
+        for version in [1, 2] {
+            let xac =
+                XacActorFile::from_bytes(&replace_chunk(2, &inline_skinning(version))).unwrap();
+            assert_eq!(node_influences(&xac), expected, "version {}", version);
+        }
+    }
 }
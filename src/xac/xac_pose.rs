@@ -0,0 +1,286 @@
+#![allow(dead_code)]
+use crate::xac::xac_enum::XacMultiplyOrder::XacRotScaleTransId;
+use crate::xac::xac_struct::{
+    XacActorFile, XacMatrix44, XacNodeTransform, XacPoseMatrices, XacQuaternion, XacVec3d,
+};
+use crate::xac::xac_util::{
+    xac_matrix44_from_quaternion, xac_matrix44_from_scale, xac_matrix44_from_translation,
+    xac_matrix44_identity, xac_matrix44_multiply,
+};
+use crate::xsm::xsm_sampler::{xsm_sample_submotion, XsmSampler};
+use crate::xsm::xsm_structs::{XsmBinding, XsmLocalTransform};
+
+impl XacActorFile {
+    pub fn bind_pose(&self) -> Vec<XacNodeTransform> {
+        self.nodes
+            .iter()
+            .map(|node| XacNodeTransform {
+                position: node.position.clone(),
+                rotation: node.rotation.clone(),
+                scale: node.scale.clone(),
+                scale_rotation: node.scale_rotation.clone(),
+            })
+            .collect()
+    }
+
+    // Pose of the actor at `time` in a motion. Nodes the motion does not
+    // animate keep their bind pose.
+    pub fn motion_pose(
+        &self,
+        sampler: &XsmSampler,
+        binding: &XsmBinding,
+        time: f32,
+    ) -> Vec<XacNodeTransform> {
+        let mut pose = self.bind_pose();
+        for (transform, submotion_id) in pose.iter_mut().zip(binding.node_submotions.iter()) {
+            let submotion = match submotion_id.and_then(|id| sampler.submotions.get(id)) {
+                Some(submotion) => submotion,
+                None => continue,
+            };
+            *transform = xac_node_transform_from_xsm(&xsm_sample_submotion(submotion, time));
+        }
+        pose
+    }
+
+    // `pose` holds one transform per node; missing entries use the bind pose.
+    pub fn local_matrices(&self, pose: &[XacNodeTransform]) -> Vec<XacMatrix44> {
+        let bind_pose = self.bind_pose();
+        bind_pose
+            .iter()
+            .enumerate()
+            .map(|(node_id, bind_transform)| {
+                let transform = pose.get(node_id).unwrap_or(bind_transform);
+                xac_local_matrix(transform, self.header.multiply_order)
+            })
+            .collect()
+    }
+
+    // World matrices are the local matrix followed by the parent's world
+    // matrix. Parents do not have to come before their children, and a
+    // parent id that is out of range or part of a cycle counts as a root.
+    pub fn world_matrices(&self, local: &[XacMatrix44]) -> Vec<XacMatrix44> {
+        // A node is done once its world matrix is set, and in the chain
+        // while it waits for its parents.
+        let mut world: Vec<Option<XacMatrix44>> = vec![None; local.len()];
+        let mut in_chain: Vec<bool> = vec![false; local.len()];
+        for node_id in 0..local.len() {
+            if world[node_id].is_some() {
+                continue;
+            }
+            let mut chain: Vec<usize> = vec![node_id];
+            in_chain[node_id] = true;
+            let mut parent_world = xac_matrix44_identity();
+            while let Some(parent_id) = self.parent_id(chain[chain.len() - 1]) {
+                if parent_id >= local.len() || in_chain[parent_id] {
+                    break;
+                }
+                if let Some(matrix) = &world[parent_id] {
+                    parent_world = matrix.clone();
+                    break;
+                }
+                chain.push(parent_id);
+                in_chain[parent_id] = true;
+            }
+            for chain_node_id in chain.into_iter().rev() {
+                in_chain[chain_node_id] = false;
+                parent_world = xac_matrix44_multiply(&local[chain_node_id], &parent_world);
+                world[chain_node_id] = Some(parent_world.clone());
+            }
+        }
+        world.into_iter().flatten().collect()
+    }
+
+    pub fn pose_matrices(&self, pose: &[XacNodeTransform]) -> XacPoseMatrices {
+        let local = self.local_matrices(pose);
+        let world = self.world_matrices(&local);
+        XacPoseMatrices { local, world }
+    }
+
+    fn parent_id(&self, node_id: usize) -> Option<usize> {
+        let parent_node_id = self.nodes.get(node_id)?.parent_node_id;
+        if parent_node_id < 0 {
+            None
+        } else {
+            Some(parent_node_id as usize)
+        }
+    }
+}
+
+// The scale is applied along the axes of `scale_rotation`. Shear is not
+// applied.
+pub(crate) fn xac_local_matrix(transform: &XacNodeTransform, multiply_order: u8) -> XacMatrix44 {
+    let scale_rotation = xac_matrix44_from_quaternion(&transform.scale_rotation);
+    let inverse_scale_rotation = xac_matrix44_from_quaternion(&XacQuaternion {
+        x: -transform.scale_rotation.x,
+        y: -transform.scale_rotation.y,
+        z: -transform.scale_rotation.z,
+        w: transform.scale_rotation.w,
+    });
+    let scale = xac_matrix44_multiply(
+        &xac_matrix44_multiply(
+            &inverse_scale_rotation,
+            &xac_matrix44_from_scale(&transform.scale),
+        ),
+        &scale_rotation,
+    );
+    let rotation = xac_matrix44_from_quaternion(&transform.rotation);
+    let translation = xac_matrix44_from_translation(&transform.position);
+
+    let scale_and_rotation = if multiply_order == XacRotScaleTransId as u8 {
+        xac_matrix44_multiply(&rotation, &scale)
+    } else {
+        xac_matrix44_multiply(&scale, &rotation)
+    };
+    xac_matrix44_multiply(&scale_and_rotation, &translation)
+}
+
+pub(crate) fn xac_node_transform_from_xsm(transform: &XsmLocalTransform) -> XacNodeTransform {
+    XacNodeTransform {
+        position: XacVec3d {
+            x: transform.pos.x,
+            y: transform.pos.y,
+            z: transform.pos.z,
+        },
+        rotation: XacQuaternion {
+            x: transform.rot.x,
+            y: transform.rot.y,
+            z: transform.rot.z,
+            w: transform.rot.w,
+        },
+        scale: XacVec3d {
+            x: transform.scale.x,
+            y: transform.scale.y,
+            z: transform.scale.z,
+        },
+        scale_rotation: XacQuaternion {
+            x: transform.scale_rot.x,
+            y: transform.scale_rot.y,
+            z: transform.scale_rot.z,
+            w: transform.scale_rot.w,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xac::xac_test_util::rigged_actor;
+    use crate::xac::xac_util::xac_matrix44_transform_point;
+    use crate::xsm::xsm_structs::Xsm;
+    use crate::xsm::xsm_test_util::motion_file;
+    use byteorder::LittleEndian;
+
+    fn vec3d(x: f32, y: f32, z: f32) -> XacVec3d {
+        XacVec3d { x, y, z }
+    }
+
+    fn quaternion(x: f32, y: f32, z: f32, w: f32) -> XacQuaternion {
+        XacQuaternion { x, y, z, w }
+    }
+
+    fn assert_point(matrix: &XacMatrix44, point: [f32; 3], expected: [f32; 3]) {
+        let actual = xac_matrix44_transform_point(matrix, &vec3d(point[0], point[1], point[2]));
+        let actual = [actual.x, actual.y, actual.z];
+        for (actual_value, expected_value) in actual.iter().zip(expected.iter()) {
+            assert!(
+                (actual_value - expected_value).abs() < 1e-5,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    fn transform(scale: XacVec3d, rotation: XacQuaternion) -> XacNodeTransform {
+        XacNodeTransform {
+            position: vec3d(0.0, 0.0, 10.0),
+            rotation,
+            scale,
+            scale_rotation: quaternion(0.0, 0.0, 0.0, 1.0),
+        }
+    }
+
+    #[test]
+    fn applies_multiply_order() {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let node = transform(vec3d(2.0, 1.0, 1.0), quaternion(0.0, 0.0, half, half));
+        // Scale, then rotate, then translate.
+        let matrix = xac_local_matrix(&node, 0);
+        assert_point(&matrix, [1.0, 0.0, 0.0], [0.0, 2.0, 10.0]);
+        assert_point(&matrix, [0.0, 1.0, 0.0], [-1.0, 0.0, 10.0]);
+        // Rotate, then scale, then translate.
+        let matrix = xac_local_matrix(&node, XacRotScaleTransId as u8);
+        assert_point(&matrix, [1.0, 0.0, 0.0], [0.0, 1.0, 10.0]);
+        assert_point(&matrix, [0.0, 1.0, 0.0], [-2.0, 0.0, 10.0]);
+    }
+
+    #[test]
+    fn scales_along_scale_rotation_axes() {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let mut node = transform(vec3d(2.0, 1.0, 1.0), quaternion(0.0, 0.0, 0.0, 1.0));
+        node.scale_rotation = quaternion(0.0, 0.0, half, half);
+        let matrix = xac_local_matrix(&node, 0);
+        assert_point(&matrix, [1.0, 0.0, 0.0], [1.0, 0.0, 10.0]);
+        assert_point(&matrix, [0.0, 1.0, 0.0], [0.0, 2.0, 10.0]);
+    }
+
+    #[test]
+    fn computes_world_matrices_of_bind_pose() {
+        let actor = rigged_actor();
+        let matrices = actor.pose_matrices(&actor.bind_pose());
+        assert_eq!(matrices.local.len(), 3);
+        assert_eq!(matrices.world.len(), 3);
+        assert_point(&matrices.world[0], [0.0, 0.0, 0.0], [1.0, 0.0, 0.0]);
+        assert_point(&matrices.world[0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]);
+        assert_point(&matrices.world[1], [0.0, 0.0, 0.0], [-1.0, 0.0, 0.0]);
+        assert_point(&matrices.world[2], [0.0, 0.0, 0.0], [-1.0, 0.0, 3.0]);
+        assert_point(&matrices.world[2], [1.0, 0.0, 0.0], [-1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn uses_bind_pose_for_missing_pose_entries() {
+        let actor = rigged_actor();
+        let mut pose = actor.bind_pose();
+        pose.truncate(1);
+        pose[0].position = vec3d(0.0, 0.0, 0.0);
+        let world = actor.world_matrices(&actor.local_matrices(&pose));
+        assert_point(&world[1], [0.0, 0.0, 0.0], [-2.0, 0.0, 0.0]);
+        assert_point(&world[2], [0.0, 0.0, 0.0], [-2.0, 0.0, 3.0]);
+    }
+
+    #[test]
+    fn treats_cycles_and_bad_parents_as_roots() {
+        let mut actor = rigged_actor();
+        actor.nodes[0].parent_node_id = 1;
+        let local = actor.local_matrices(&actor.bind_pose());
+        let world = actor.world_matrices(&local);
+        assert_eq!(world.len(), 3);
+        // Node 1 closes the cycle and is used as its root.
+        assert_point(&world[1], [0.0, 0.0, 0.0], [0.0, 2.0, 0.0]);
+        assert_point(&world[0], [0.0, 0.0, 0.0], [1.0, 2.0, 0.0]);
+        assert_point(&world[2], [0.0, 0.0, 0.0], [0.0, 2.0, 3.0]);
+
+        actor.nodes[0].parent_node_id = 7;
+        let world = actor.world_matrices(&local);
+        assert_point(&world[0], [0.0, 0.0, 0.0], [1.0, 0.0, 0.0]);
+        assert_point(&world[2], [0.0, 0.0, 0.0], [-1.0, 0.0, 3.0]);
+    }
+
+    #[test]
+    fn poses_bound_nodes_from_the_motion() {
+        let actor = rigged_actor();
+        let xsm = Xsm::from_bytes(&motion_file::<LittleEndian>(false)).unwrap();
+        let sampler = xsm.sampler();
+        let binding = xsm.bind(&actor);
+        let pose = actor.motion_pose(&sampler, &binding, 1.0);
+        assert_eq!(pose[0].scale.x, 3.0);
+        assert_eq!(pose[1].position.z, 3.0);
+        // "grand" is not in the motion and keeps its bind pose.
+        assert_eq!(pose[2].scale.x, 2.0);
+
+        let world = actor.pose_matrices(&pose).world;
+        // Root: scaled by 3, turned 90 degrees about z, moved to (2, 0, 0).
+        assert_point(&world[0], [1.0, 0.0, 0.0], [2.0, 3.0, 0.0]);
+        assert_point(&world[1], [0.0, 0.0, 0.0], [-4.0, 3.0, 9.0]);
+    }
+}
@@ -0,0 +1,304 @@
+#![allow(dead_code)]
+use crate::xac::xac_struct::{
+    XacActorFile, XacActorSubMesh, XacBoneInfluence, XacMatrix44, XacNodeTransform, XacPosedMesh,
+    XacPosedSubMesh, XacVec3d, XacVec4d,
+};
+use crate::xac::xac_util::{
+    xac_matrix44_from_array, xac_matrix44_inverse, xac_matrix44_multiply, xac_matrix44_normal,
+    xac_matrix44_to_array, xac_matrix44_transform_point, xac_matrix44_transform_vector,
+    xac_normalize_vec3d,
+};
+use crate::xsm::xsm_sampler::XsmSampler;
+use crate::xsm::xsm_structs::XsmBinding;
+
+impl XacActorFile {
+    pub fn skin_bind_pose(&self) -> Vec<XacPosedMesh> {
+        self.skin(&self.bind_pose())
+    }
+
+    // The sampler and binding are built once per motion by the caller, see
+    // Xsm::sampler and Xsm::bind.
+    pub fn skin_motion(
+        &self,
+        sampler: &XsmSampler,
+        binding: &XsmBinding,
+        time: f32,
+    ) -> Vec<XacPosedMesh> {
+        self.skin(&self.motion_pose(sampler, binding, time))
+    }
+
+    // Linear blend skinning on the CPU. Skinned vertices are stored in bind
+    // pose model space and are moved by the weighted bone matrices relative
+    // to the bind pose. Vertices without influences are rigidly attached to
+    // the node that owns the mesh. Only nodes with a mesh are returned.
+    pub fn skin(&self, pose: &[XacNodeTransform]) -> Vec<XacPosedMesh> {
+        let bind_world = self.pose_matrices(&self.bind_pose()).world;
+        let pose_world = self.pose_matrices(pose).world;
+        let skin_matrices: Vec<XacMatrix44> = bind_world
+            .iter()
+            .zip(pose_world.iter())
+            .map(|(bind, posed)| match xac_matrix44_inverse(bind) {
+                Some(inverse_bind) => xac_matrix44_multiply(&inverse_bind, posed),
+                None => posed.clone(),
+            })
+            .collect();
+
+        let mut posed_meshes: Vec<XacPosedMesh> = Vec::new();
+        for (node_id, node) in self.nodes.iter().enumerate() {
+            if node.visual_mesh.sub_meshes.is_empty() && node.collision_mesh.sub_meshes.is_empty() {
+                continue;
+            }
+            let node_world = &pose_world[node_id];
+            posed_meshes.push(XacPosedMesh {
+                node_id,
+                visual_sub_meshes: node
+                    .visual_mesh
+                    .sub_meshes
+                    .iter()
+                    .map(|sub_mesh| skin_sub_mesh(sub_mesh, &skin_matrices, node_world))
+                    .collect(),
+                collision_sub_meshes: node
+                    .collision_mesh
+                    .sub_meshes
+                    .iter()
+                    .map(|sub_mesh| skin_sub_mesh(sub_mesh, &skin_matrices, node_world))
+                    .collect(),
+            });
+        }
+        posed_meshes
+    }
+}
+
+fn skin_sub_mesh(
+    sub_mesh: &XacActorSubMesh,
+    skin_matrices: &[XacMatrix44],
+    node_world: &XacMatrix44,
+) -> XacPosedSubMesh {
+    let mut posed = XacPosedSubMesh {
+        vertex_positions: Vec::with_capacity(sub_mesh.vertex_positions.len()),
+        vertex_normals: Vec::with_capacity(sub_mesh.vertex_normals.len()),
+        vertex_tangents: Vec::with_capacity(sub_mesh.vertex_tangents.len()),
+    };
+    for (vertex, position) in sub_mesh.vertex_positions.iter().enumerate() {
+        let influences = sub_mesh.vertex_influences.get(vertex);
+        let matrix = influences
+            .and_then(|influences| blend_skin_matrices(influences, skin_matrices))
+            .unwrap_or_else(|| node_world.clone());
+        posed
+            .vertex_positions
+            .push(xac_matrix44_transform_point(&matrix, position));
+
+        if let Some(normal) = sub_mesh.vertex_normals.get(vertex) {
+            let normal_matrix = xac_matrix44_normal(&matrix);
+            posed
+                .vertex_normals
+                .push(xac_normalize_vec3d(&xac_matrix44_transform_vector(
+                    &normal_matrix,
+                    normal,
+                )));
+        }
+        // The tangent's w holds the bitangent sign and is kept as is.
+        if let Some(tangent) = sub_mesh.vertex_tangents.get(vertex) {
+            let direction = xac_normalize_vec3d(&xac_matrix44_transform_vector(
+                &matrix,
+                &XacVec3d {
+                    x: tangent.x,
+                    y: tangent.y,
+                    z: tangent.z,
+                },
+            ));
+            posed.vertex_tangents.push(XacVec4d {
+                x: direction.x,
+                y: direction.y,
+                z: direction.z,
+                w: tangent.w,
+            });
+        }
+    }
+    posed
+}
+
+// Normalized weights of the influences that reference an existing node, or
+// `None` when no such influence has any weight. Such vertices follow the
+// node that owns the mesh.
+pub(crate) fn xac_skin_weights(
+    influences: &[XacBoneInfluence],
+    num_nodes: usize,
+) -> Option<Vec<(usize, f32)>> {
+    let valid: Vec<(usize, f32)> = influences
+        .iter()
+        .filter(|influence| influence.node_id < num_nodes)
+        .map(|influence| (influence.node_id, influence.weight))
+        .collect();
+    let total_weight: f32 = valid.iter().map(|(_, weight)| weight).sum();
+    if total_weight <= f32::EPSILON {
+        return None;
+    }
+    Some(
+        valid
+            .into_iter()
+            .map(|(node_id, weight)| (node_id, weight / total_weight))
+            .collect(),
+    )
+}
+
+fn blend_skin_matrices(
+    influences: &[XacBoneInfluence],
+    skin_matrices: &[XacMatrix44],
+) -> Option<XacMatrix44> {
+    let mut rows = [[0.0f32; 4]; 4];
+    for (node_id, weight) in xac_skin_weights(influences, skin_matrices.len())? {
+        let skin_matrix = xac_matrix44_to_array(&skin_matrices[node_id]);
+        for (row, skin_row) in rows.iter_mut().zip(skin_matrix.iter()) {
+            for (value, skin_value) in row.iter_mut().zip(skin_row.iter()) {
+                *value += skin_value * weight;
+            }
+        }
+    }
+    Some(xac_matrix44_from_array(&rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xac::xac_test_util::rigged_actor;
+    use crate::xsm::xsm_structs::Xsm;
+    use crate::xsm::xsm_test_util::motion_file;
+    use byteorder::LittleEndian;
+
+    fn components(values: &[XacVec3d]) -> Vec<[f32; 3]> {
+        values
+            .iter()
+            .map(|value| [value.x, value.y, value.z])
+            .collect()
+    }
+
+    fn assert_points(actual: &[XacVec3d], expected: &[[f32; 3]]) {
+        let actual = components(actual);
+        assert_eq!(actual.len(), expected.len());
+        for (actual_point, expected_point) in actual.iter().zip(expected.iter()) {
+            for (actual_value, expected_value) in actual_point.iter().zip(expected_point.iter()) {
+                assert!(
+                    (actual_value - expected_value).abs() < 1e-4,
+                    "{:?} != {:?}",
+                    actual,
+                    expected
+                );
+            }
+        }
+    }
+
+    // Positions of the fixture mesh in submesh order, split per submesh.
+    fn bind_positions(offset: [f32; 3]) -> [Vec<[f32; 3]>; 2] {
+        let position = |v: f32| [v + offset[0], 2.0 * v + offset[1], 3.0 * v + offset[2]];
+        [
+            vec![position(0.0), position(1.0), position(2.0)],
+            vec![position(3.0), position(4.0)],
+        ]
+    }
+
+    #[test]
+    fn bind_pose_keeps_stored_vertices() {
+        let actor = rigged_actor();
+        let posed = actor.skin_bind_pose();
+        assert_eq!(posed.len(), 1);
+        assert_eq!(posed[0].node_id, 2);
+        assert!(posed[0].collision_sub_meshes.is_empty());
+        let expected = bind_positions([0.0, 0.0, 0.0]);
+        for (sub_mesh, expected) in posed[0].visual_sub_meshes.iter().zip(expected.iter()) {
+            assert_points(&sub_mesh.vertex_positions, expected);
+            assert!(components(&sub_mesh.vertex_normals)
+                .iter()
+                .all(|normal| (normal[1] - 1.0).abs() < 1e-5));
+        }
+    }
+
+    #[test]
+    fn blends_bone_matrices_by_weight() {
+        let actor = rigged_actor();
+        let mut pose = actor.bind_pose();
+        // One more unit along the child's y axis, which is -x in the world.
+        pose[1].position.y = 3.0;
+        let posed = actor.skin(&pose);
+        let sub_meshes = &posed[0].visual_sub_meshes;
+        assert_points(
+            &sub_meshes[0].vertex_positions,
+            &[[0.0, 0.0, 0.0], [0.5, 2.0, 3.0], [1.0, 4.0, 6.0]],
+        );
+        assert_points(
+            &sub_meshes[1].vertex_positions,
+            &[[2.0, 6.0, 9.0], [3.0, 8.0, 12.0]],
+        );
+    }
+
+    #[test]
+    fn rotates_positions_and_normals_with_the_bones() {
+        let actor = rigged_actor();
+        let mut pose = actor.bind_pose();
+        // Root turned 180 degrees about z instead of 90.
+        pose[0].rotation.z = 1.0;
+        pose[0].rotation.w = 0.0;
+        let posed = actor.skin(&pose);
+        let sub_mesh = &posed[0].visual_sub_meshes[0];
+        assert_points(&sub_mesh.vertex_positions[..1], &[[1.0, -1.0, 0.0]]);
+        assert_points(&sub_mesh.vertex_normals, &[[-1.0, 0.0, 0.0]; 3]);
+        let tangent = &sub_mesh.vertex_tangents[0];
+        assert_points(
+            &[XacVec3d {
+                x: tangent.x,
+                y: tangent.y,
+                z: tangent.z,
+            }],
+            &[[0.0, 1.0, 0.0]],
+        );
+        assert_eq!(tangent.w, 1.0);
+    }
+
+    #[test]
+    fn attaches_vertices_without_weight_to_their_node() {
+        let mut actor = rigged_actor();
+        let sub_mesh = &mut actor.nodes[2].visual_mesh.sub_meshes[1];
+        sub_mesh.vertex_influences[0].clear();
+        for influence in sub_mesh.vertex_influences[1].iter_mut() {
+            influence.weight = 0.0;
+        }
+        let mut pose = actor.bind_pose();
+        let bind_posed = actor.skin(&pose);
+        // Node local (v, 2v, 3v) scaled by 2, turned 90 degrees about z and
+        // moved to grand's world position (-1, 0, 3).
+        assert_points(
+            &bind_posed[0].visual_sub_meshes[1].vertex_positions,
+            &[[-13.0, 6.0, 21.0], [-17.0, 8.0, 27.0]],
+        );
+
+        pose[1].position.y = 3.0;
+        let posed = actor.skin(&pose);
+        assert_points(
+            &posed[0].visual_sub_meshes[1].vertex_positions,
+            &[[-14.0, 6.0, 21.0], [-18.0, 8.0, 27.0]],
+        );
+    }
+
+    #[test]
+    fn skins_motion_with_prebuilt_sampler_and_binding() {
+        let actor = rigged_actor();
+        let xsm = Xsm::from_bytes(&motion_file::<LittleEndian>(false)).unwrap();
+        let sampler = xsm.sampler();
+        let binding = xsm.bind(&actor);
+        for time in [0.0, 0.5, 1.0] {
+            let pose = actor.motion_pose(&sampler, &binding, time);
+            let expected = actor.skin(&pose);
+            let posed = actor.skin_motion(&sampler, &binding, time);
+            for (sub_mesh, expected) in posed[0]
+                .visual_sub_meshes
+                .iter()
+                .zip(expected[0].visual_sub_meshes.iter())
+            {
+                assert_eq!(
+                    components(&sub_mesh.vertex_positions),
+                    components(&expected.vertex_positions)
+                );
+            }
+        }
+    }
+}
@@ -23,6 +23,20 @@ pub(crate) struct XacVec4d {
     pub(crate) w: f32,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct XacVec3d16 {
+    pub(crate) x: u16,
+    pub(crate) y: u16,
+    pub(crate) z: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct XacVec3d8 {
+    pub(crate) x: u8,
+    pub(crate) y: u8,
+    pub(crate) z: u8,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct XacColor {
     pub(crate) x: f32,
@@ -35,6 +49,7 @@ pub(crate) struct XacColor8 {
     pub(crate) x: u8,
     pub(crate) y: u8,
     pub(crate) z: u8,
+    pub(crate) w: u8,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,6 +73,7 @@ pub struct XacActorFile {
     pub(crate) root_nodes: Vec<XacActorNode>,
     pub(crate) materials: Vec<XacActorMaterial>,
     pub(crate) morph_targets: Vec<XacActorMorphTarget>,
+    pub(crate) morph_target_chunks: Vec<XacMorphTargetChunk>,
     pub(crate) header: XacHeader,
     pub(crate) metadata: XacMetaDataChunkv2Header,
     pub(crate) actor_name: String,
@@ -72,12 +88,26 @@ pub struct XacActorFile {
     pub(crate) mesh_num_influence_range: Vec<XacInfluenceRange>,
     pub(crate) sub_mesh_influence_range_indices: Vec<XacSubMeshInfluenceRange>,
     pub(crate) num_nodes_index: usize,
+    pub(crate) unknown_chunks: Vec<XacUnknownChunk>,
+}
+
+// A chunk the parser does not read, kept byte for byte. `offset` is where
+// its chunk header started in the parsed file and is not used when writing:
+// the writer puts unknown chunks after all known chunks, in the order they
+// were read.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct XacUnknownChunk {
+    pub(crate) type_id: i32,
+    pub(crate) version: i32,
+    pub(crate) offset: u64,
+    pub(crate) data: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct XacInfluenceRange {
     pub(crate) node_id: usize,
     pub(crate) num: i32,
+    pub(crate) is_collision_mesh: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -117,9 +147,13 @@ pub(crate) struct XacNodeHierarchyChunkv1NodeHeader {
     pub(crate) scale_rotation: XacQuaternion,
     pub(crate) position: XacVec3d,
     pub(crate) scale: XacVec3d,
+    pub(crate) shear: XacVec3d,
+    pub(crate) skeletal_lods: u32,
+    pub(crate) motion_lods: u32,
     pub(crate) parent_node_id: i32,
     pub(crate) num_children: i32,
-    pub(crate) include_inbounds_calc: i32,
+    pub(crate) include_inbounds_calc: u8,
+    pub(crate) node_flags: u8,
     pub(crate) transform: XacMatrix44,
     pub(crate) importance_factor: f32,
 }
@@ -143,6 +177,7 @@ pub(crate) struct XacMaterialDefinitionChunkv2Header {
     pub(crate) ior: f32,
     pub(crate) double_sided: bool,
     pub(crate) wireframe: bool,
+    pub(crate) transparency_type: u8,
     pub(crate) num_layers: u8,
 }
 
@@ -194,7 +229,13 @@ pub(crate) struct XacActorNode {
     pub(crate) rotation: XacQuaternion,
     pub(crate) scale: XacVec3d,
     pub(crate) scale_rotation: XacQuaternion,
+    pub(crate) shear: XacVec3d,
+    pub(crate) skeletal_lods: u32,
+    pub(crate) motion_lods: u32,
+    pub(crate) include_inbounds_calc: u8,
+    pub(crate) node_flags: u8,
     pub(crate) transform: XacMatrix44,
+    pub(crate) importance_factor: f32,
     pub(crate) parent: Option<Box<XacActorNode>>,
     pub(crate) num_children: i32,
     pub(crate) children: Vec<XacActorNode>,
@@ -202,9 +243,55 @@ pub(crate) struct XacActorNode {
     pub(crate) collision_mesh: XacActorMesh,
 }
 
+// Local transform of a node, either its bind pose or a sampled motion pose.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct XacNodeTransform {
+    pub(crate) position: XacVec3d,
+    pub(crate) rotation: XacQuaternion,
+    pub(crate) scale: XacVec3d,
+    pub(crate) scale_rotation: XacQuaternion,
+}
+
+// Matrices of every node for one pose, indexed like `XacActorFile::nodes`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct XacPoseMatrices {
+    pub(crate) local: Vec<XacMatrix44>,
+    pub(crate) world: Vec<XacMatrix44>,
+}
+
+// Deformed vertex buffers of one node's meshes, indexed like the submeshes
+// of `visual_mesh` and `collision_mesh`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct XacPosedMesh {
+    pub(crate) node_id: usize,
+    pub(crate) visual_sub_meshes: Vec<XacPosedSubMesh>,
+    pub(crate) collision_sub_meshes: Vec<XacPosedSubMesh>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct XacPosedSubMesh {
+    pub(crate) vertex_positions: Vec<XacVec3d>,
+    pub(crate) vertex_normals: Vec<XacVec3d>,
+    pub(crate) vertex_tangents: Vec<XacVec4d>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct XacActorMesh {
     pub(crate) sub_meshes: Vec<XacActorSubMesh>,
+    pub(crate) vertex_attributes: Vec<XacActorVertexAttribute>,
+}
+
+// Vertex element layout of a mesh in file order. `data` holds the raw
+// `element_size * num_vertices` bytes of usages the parser does not decode
+// into submesh arrays, and of decoded usages whose elements are wider than
+// the decoded value. It is empty otherwise.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct XacActorVertexAttribute {
+    pub(crate) usage: u32,
+    pub(crate) element_size: i32,
+    pub(crate) keep_originals: bool,
+    pub(crate) is_scale_factor: bool,
+    pub(crate) data: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -215,11 +302,12 @@ pub(crate) struct XacActorSubMesh {
     pub(crate) vertex_normals: Vec<XacVec3d>,
     pub(crate) vertex_tangents: Vec<XacVec4d>,
     pub(crate) vertex_bi_tangents: Vec<XacVec4d>,
-    pub(crate) vertex_uvs: Vec<XacVec2d>,
-    pub(crate) vertex_colors_32: Vec<XacColor8>,
-    pub(crate) vertex_colors_128: Vec<XacVec3d>,
-    pub(crate) vertex_influences: Vec<XacBoneInfluence>,
-    pub(crate) indices: Vec<XacVec3d>,
+    pub(crate) vertex_uvs: Vec<Vec<XacVec2d>>,
+    pub(crate) vertex_colors_32: Vec<Vec<XacColor8>>,
+    pub(crate) vertex_colors_128: Vec<Vec<XacVec4d>>,
+    pub(crate) vertex_influences: Vec<Vec<XacBoneInfluence>>,
+    pub(crate) bones: Vec<usize>,
+    pub(crate) indices: Vec<u32>,
     pub(crate) influence_range_indices: Vec<i32>,
 }
 
@@ -254,17 +342,33 @@ pub(crate) struct XacActorMorphTarget {
     pub(crate) name: String,
     pub(crate) range_min: f32,
     pub(crate) range_max: f32,
+    pub(crate) lod_level: i32,
     pub(crate) phoneme_set_bitmask: i32,
     pub(crate) deformation: Vec<XacActorNodeDeformation>,
+    pub(crate) transformations: Vec<XacMorphTargetsChunkv1Transformation>,
+}
+
+// The morph targets read from one morph target chunk. The chunk LOD is the
+// geometry LOD the targets belong to and can differ from each target's own
+// `lod_level`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct XacMorphTargetChunk {
+    pub(crate) lod_level: i32,
+    pub(crate) morph_target_ids: Vec<usize>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct XacActorNodeDeformation {
-    pub(crate) node: XacActorNode,
+    pub(crate) node_id: usize,
+    pub(crate) min_value: f32,
+    pub(crate) max_value: f32,
     pub(crate) vertex_indices: Vec<i32>,
     pub(crate) position_offsets: Vec<XacVec3d>,
     pub(crate) normal_offsets: Vec<XacVec3d>,
     pub(crate) tangent_offsets: Vec<XacVec3d>,
+    pub(crate) compressed_position_offsets: Vec<XacVec3d16>,
+    pub(crate) compressed_normal_offsets: Vec<XacVec3d8>,
+    pub(crate) compressed_tangent_offsets: Vec<XacVec3d8>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -307,6 +411,7 @@ pub(crate) struct XacActorMaterial {
     pub(crate) ior: f32,
     pub(crate) double_sided: bool,
     pub(crate) wireframe: bool,
+    pub(crate) transparency_type: u8,
     pub(crate) layers: Vec<XacActorMaterialLayer>,
 }
 
@@ -328,6 +433,7 @@ pub(crate) struct XacShaderMaterial {
     pub(crate) num_float: i32,
     pub(crate) num_bool: i32,
     pub(crate) num_string: i32,
+    pub(crate) padding: i32,
     pub(crate) flag: i32,
     pub(crate) name_material: String,
     pub(crate) name_shader: String,
@@ -335,6 +441,7 @@ pub(crate) struct XacShaderMaterial {
     pub(crate) float_property: Vec<XacFloatProperties>,
     pub(crate) bool_property: Vec<XacBoolProperties>,
     pub(crate) string_property: Vec<XacStringProperties>,
+    pub(crate) skipped_data: Vec<u8>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -0,0 +1,260 @@
+#![allow(dead_code)]
+use crate::xac::xac_struct::{XacActorFile, XacQuaternion, XacVec3d};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Cursor, Write};
+
+// The string, float and chunk helpers are shared with the XSM tests, both
+// formats lay them out the same way.
+pub(crate) fn string<E: ByteOrder>(data: &mut Vec<u8>, text: &str) {
+    data.write_i32::<E>(text.len() as i32).unwrap();
+    data.write_all(text.as_bytes()).unwrap();
+}
+
+pub(crate) fn floats<E: ByteOrder>(data: &mut Vec<u8>, values: &[f32]) {
+    for value in values {
+        data.write_f32::<E>(*value).unwrap();
+    }
+}
+
+pub(crate) fn chunk<E: ByteOrder>(file: &mut Vec<u8>, type_id: i32, version: i32, data: &[u8]) {
+    file.write_i32::<E>(type_id).unwrap();
+    file.write_i32::<E>(data.len() as i32).unwrap();
+    file.write_i32::<E>(version).unwrap();
+    file.write_all(data).unwrap();
+}
+
+// An actor using every chunk the writer produces, in the writer's order:
+// three nested nodes, a skinned two-submesh mesh on the last one with an
+// unknown vertex attribute and normals padded to 16 bytes, two materials
+// used by one submesh each, a shader material, a morph target and an
+// unknown chunk.
+pub(crate) fn actor_file<E: ByteOrder>(big_endian: bool) -> Vec<u8> {
+    let mut file = b"XAC ".to_vec();
+    file.extend_from_slice(&[1, 0, big_endian as u8, 0]);
+
+    let mut metadata = Vec::new();
+    metadata.write_u32::<E>(0).unwrap();
+    metadata.write_i32::<E>(-1).unwrap();
+    metadata.extend_from_slice(&[2, 5, 0, 0]);
+    floats::<E>(&mut metadata, &[0.5]);
+    for text in ["app", "orig.max", "date", "actor"] {
+        string::<E>(&mut metadata, text);
+    }
+    chunk::<E>(&mut file, 7, 2, &metadata);
+
+    let mut nodes = Vec::new();
+    nodes.write_i32::<E>(3).unwrap();
+    nodes.write_i32::<E>(1).unwrap();
+    for (name, parent, num_children) in [("root", -1, 1), ("child", 0, 1), ("grand", 1, 0)] {
+        floats::<E>(&mut nodes, &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+        floats::<E>(&mut nodes, &[1.0, 2.0, 3.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0]);
+        nodes.write_u32::<E>(u32::MAX).unwrap();
+        nodes.write_u32::<E>(u32::MAX).unwrap();
+        nodes.write_i32::<E>(parent).unwrap();
+        nodes.write_i32::<E>(num_children).unwrap();
+        nodes.extend_from_slice(&[1, 0, 0, 0]);
+        floats::<E>(&mut nodes, &[1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+        floats::<E>(&mut nodes, &[0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+        floats::<E>(&mut nodes, &[1.0]);
+        string::<E>(&mut nodes, name);
+    }
+    chunk::<E>(&mut file, 11, 1, &nodes);
+
+    let mut totals = Vec::new();
+    for count in [3, 2, 1] {
+        totals.write_i32::<E>(count).unwrap();
+    }
+    chunk::<E>(&mut file, 13, 1, &totals);
+
+    let materials = [
+        ("body", vec![(2, "body_diffuse.dds")]),
+        (
+            "head",
+            vec![(2, "head_diffuse.dds"), (5, "head_normal.dds")],
+        ),
+    ];
+    for (material_id, (name, layers)) in materials.iter().enumerate() {
+        let mut material = Vec::new();
+        floats::<E>(&mut material, &[0.1; 16]);
+        floats::<E>(&mut material, &[25.0, 1.0, 1.0, 1.5]);
+        material.extend_from_slice(&[1, 0, 2, layers.len() as u8]);
+        string::<E>(&mut material, name);
+        for (map_type, texture) in layers.iter() {
+            floats::<E>(&mut material, &[1.0, 0.0, 0.0, 1.0, 1.0, 0.0]);
+            material.write_i16::<E>(material_id as i16).unwrap();
+            material.extend_from_slice(&[*map_type, 0]);
+            string::<E>(&mut material, texture);
+        }
+        chunk::<E>(&mut file, 3, 2, &material);
+    }
+
+    let mut shader_material = Vec::new();
+    for count in [1, 1, 7, 1, 3, 1] {
+        shader_material.write_i32::<E>(count).unwrap();
+    }
+    string::<E>(&mut shader_material, "body_fx");
+    string::<E>(&mut shader_material, "skin.fx");
+    string::<E>(&mut shader_material, "passes");
+    shader_material.write_i32::<E>(2).unwrap();
+    string::<E>(&mut shader_material, "gloss");
+    floats::<E>(&mut shader_material, &[0.25]);
+    string::<E>(&mut shader_material, "alpha_test");
+    shader_material.write_u8(1).unwrap();
+    shader_material.write_i32::<E>(3).unwrap();
+    shader_material.extend_from_slice(&[9, 8, 7]);
+    string::<E>(&mut shader_material, "normal_map");
+    string::<E>(&mut shader_material, "body_normal.dds");
+    chunk::<E>(&mut file, 5, 1, &shader_material);
+
+    let num_vertices = 5;
+    let mut mesh = Vec::new();
+    for value in [2, 4, num_vertices, 6, 2, 10] {
+        mesh.write_i32::<E>(value).unwrap();
+    }
+    mesh.extend_from_slice(&[0, 0, 0, 0]);
+    let mut attribute = |usage: u32, element_size: i32, flags: [u8; 2]| {
+        mesh.write_u32::<E>(usage).unwrap();
+        mesh.write_i32::<E>(element_size).unwrap();
+        mesh.extend_from_slice(&[flags[0], flags[1], 0, 0]);
+        let mut data = Vec::new();
+        for vertex in 0..num_vertices {
+            let start = data.len();
+            let v = vertex as f32;
+            match usage {
+                0 => floats::<E>(&mut data, &[v, v * 2.0, v * 3.0]),
+                1 => floats::<E>(&mut data, &[0.0, 1.0, 0.0]),
+                2 => floats::<E>(&mut data, &[1.0, 0.0, v, 1.0]),
+                3 => floats::<E>(&mut data, &[v * 0.25, 1.0 - v * 0.25]),
+                4 => data.extend_from_slice(&[vertex as u8, 2, 3, 200]),
+                5 => data
+                    .write_i32::<E>([0, 1, 2, 3, 3][vertex as usize])
+                    .unwrap(),
+                6 => floats::<E>(&mut data, &[v, 0.25, 0.5, 0.75]),
+                _ => data.extend_from_slice(&[vertex as u8; 3]),
+            }
+            data.resize(start + element_size as usize, 0xa0 + vertex as u8);
+        }
+        mesh.extend_from_slice(&data);
+    };
+    attribute(0, 12, [1, 1]);
+    attribute(1, 16, [1, 0]);
+    attribute(2, 16, [0, 0]);
+    attribute(2, 16, [0, 0]);
+    attribute(3, 8, [0, 0]);
+    attribute(3, 8, [0, 0]);
+    attribute(4, 4, [0, 0]);
+    attribute(6, 16, [0, 0]);
+    attribute(42, 3, [1, 0]);
+    attribute(5, 4, [0, 0]);
+    let sub_meshes = [
+        (3, 0, vec![0, 1, 2], vec![0, 1]),
+        (2, 1, vec![0, 1, 1], vec![2]),
+    ];
+    for (sub_mesh_vertices, material_id, indices, bones) in sub_meshes {
+        for value in [
+            indices.len() as i32,
+            sub_mesh_vertices,
+            material_id,
+            bones.len() as i32,
+        ] {
+            mesh.write_i32::<E>(value).unwrap();
+        }
+        for index in indices.iter().chain(bones.iter()) {
+            mesh.write_u32::<E>(*index).unwrap();
+        }
+    }
+    chunk::<E>(&mut file, 1, 1, &mesh);
+
+    let mut skinning = Vec::new();
+    for value in [2, 3, 5] {
+        skinning.write_i32::<E>(value).unwrap();
+    }
+    skinning.extend_from_slice(&[0, 0, 0, 0]);
+    for (weight, node_id) in [(1.0, 0), (0.5, 0), (0.5, 1), (1.0, 1), (1.0, 2)] {
+        skinning.write_f32::<E>(weight).unwrap();
+        skinning.write_u16::<E>(node_id).unwrap();
+        skinning.extend_from_slice(&[0, 0]);
+    }
+    for (first, count) in [(0, 1), (1, 2), (3, 1), (4, 1)] {
+        skinning.write_i32::<E>(first).unwrap();
+        skinning.write_i32::<E>(count).unwrap();
+    }
+    chunk::<E>(&mut file, 2, 3, &skinning);
+
+    let mut morph_targets = Vec::new();
+    morph_targets.write_i32::<E>(1).unwrap();
+    morph_targets.write_i32::<E>(0).unwrap();
+    floats::<E>(&mut morph_targets, &[0.0, 1.0]);
+    for value in [0, 1, 1, 3] {
+        morph_targets.write_i32::<E>(value).unwrap();
+    }
+    string::<E>(&mut morph_targets, "smile");
+    morph_targets.write_i32::<E>(2).unwrap();
+    floats::<E>(&mut morph_targets, &[-1.0, 1.0]);
+    morph_targets.write_i32::<E>(2).unwrap();
+    for value in [0, 65535, 32768, 65535, 0, 0] {
+        morph_targets.write_u16::<E>(value).unwrap();
+    }
+    morph_targets.extend_from_slice(&[0, 255, 128, 255, 0, 0, 128, 128, 128, 0, 0, 0]);
+    morph_targets.write_i32::<E>(1).unwrap();
+    morph_targets.write_i32::<E>(3).unwrap();
+    morph_targets.write_i32::<E>(1).unwrap();
+    floats::<E>(
+        &mut morph_targets,
+        &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+    );
+    floats::<E>(&mut morph_targets, &[1.0, 1.0, 1.0, 2.0, 2.0, 2.0]);
+    chunk::<E>(&mut file, 12, 1, &morph_targets);
+
+    chunk::<E>(&mut file, 99, 4, b"unknown");
+    file
+}
+
+// Start offsets of every chunk header in a file, with their chunk type.
+pub(crate) fn chunk_offsets<E: ByteOrder>(file: &[u8]) -> Vec<(i32, usize)> {
+    let mut offsets = Vec::new();
+    let mut cursor = Cursor::new(file);
+    cursor.set_position(8);
+    while (cursor.position() as usize) < file.len() {
+        let start = cursor.position() as usize;
+        let type_id = cursor.read_i32::<E>().unwrap();
+        let length = cursor.read_i32::<E>().unwrap();
+        offsets.push((type_id, start));
+        cursor.set_position((start + 12 + length as usize) as u64);
+    }
+    offsets
+}
+
+// The actor of `actor_file` with a pose that is easy to follow by hand:
+// "root" at (1, 0, 0) turned 90 degrees about z, "child" 2 up its parent's
+// y axis and "grand" 3 along z, scaled by 2. The mesh on "grand" has one
+// vertex on root, one shared by root and child, one on child and two on
+// grand.
+pub(crate) fn rigged_actor() -> XacActorFile {
+    let mut actor = XacActorFile::from_bytes(&actor_file::<LittleEndian>(false)).unwrap();
+    let half = std::f32::consts::FRAC_1_SQRT_2;
+    let transforms = [
+        ([1.0, 0.0, 0.0], [0.0, 0.0, half, half], 1.0),
+        ([0.0, 2.0, 0.0], [0.0, 0.0, 0.0, 1.0], 1.0),
+        ([0.0, 0.0, 3.0], [0.0, 0.0, 0.0, 1.0], 2.0),
+    ];
+    for (node, (position, rotation, scale)) in actor.nodes.iter_mut().zip(transforms) {
+        node.position = XacVec3d {
+            x: position[0],
+            y: position[1],
+            z: position[2],
+        };
+        node.rotation = XacQuaternion {
+            x: rotation[0],
+            y: rotation[1],
+            z: rotation[2],
+            w: rotation[3],
+        };
+        node.scale = XacVec3d {
+            x: scale,
+            y: scale,
+            z: scale,
+        };
+    }
+    actor
+}
@@ -1,84 +1,448 @@
 #![allow(dead_code)]
 use crate::xac::xac_struct::{
-    XacColor, XacColor8, XacMatrix44, XacQuaternion, XacVec2d, XacVec3d, XacVec4d,
+    XacColor, XacColor8, XacMatrix44, XacQuaternion, XacVec2d, XacVec3d, XacVec3d16, XacVec3d8,
+    XacVec4d,
 };
-use byteorder::{LittleEndian, ReadBytesExt};
-use std::fs::File;
+use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt};
+use std::io;
+use std::io::{Read, Write};
 
-pub(crate) fn xac_read_string(file: &mut File) -> String {
+pub(crate) fn xac_read_string<R: Read, E: ByteOrder>(file: &mut R) -> io::Result<String> {
     let mut text = String::new();
-    let length = file.read_i32::<LittleEndian>().unwrap();
+    let length = file.read_i32::<E>()?;
     for _ in 0..length {
-        let character = file.read_u8().unwrap();
+        let character = file.read_u8()?;
         text.push(character as char);
     }
-    text
+    Ok(text)
 }
 
-pub(crate) fn xac_read_boolean(file: &mut File) -> bool {
-    let number = file.read_u8().unwrap();
-    let boolean = if number != 0 { true } else { false };
-    boolean
+pub(crate) fn xac_read_boolean<R: Read>(file: &mut R) -> io::Result<bool> {
+    let number = file.read_u8()?;
+    Ok(number != 0)
 }
-pub(crate) fn xac_read_color8(file: &mut File) -> XacColor8 {
-    let color8 = XacColor8 {
-        x: file.read_u8().unwrap(),
-        y: file.read_u8().unwrap(),
-        z: file.read_u8().unwrap(),
-    };
-    color8
+pub(crate) fn xac_read_color8<R: Read>(file: &mut R) -> io::Result<XacColor8> {
+    Ok(XacColor8 {
+        x: file.read_u8()?,
+        y: file.read_u8()?,
+        z: file.read_u8()?,
+        w: file.read_u8()?,
+    })
 }
 
-pub(crate) fn xac_read_color(file: &mut File) -> XacColor {
-    let color = XacColor {
-        x: file.read_f32::<LittleEndian>().unwrap(),
-        y: file.read_f32::<LittleEndian>().unwrap(),
-        z: file.read_f32::<LittleEndian>().unwrap(),
-    };
-    color
+pub(crate) fn xac_read_vec3d16<R: Read, E: ByteOrder>(file: &mut R) -> io::Result<XacVec3d16> {
+    Ok(XacVec3d16 {
+        x: file.read_u16::<E>()?,
+        y: file.read_u16::<E>()?,
+        z: file.read_u16::<E>()?,
+    })
+}
+
+pub(crate) fn xac_read_vec3d8<R: Read>(file: &mut R) -> io::Result<XacVec3d8> {
+    Ok(XacVec3d8 {
+        x: file.read_u8()?,
+        y: file.read_u8()?,
+        z: file.read_u8()?,
+    })
+}
+
+pub(crate) fn xac_read_color<R: Read, E: ByteOrder>(file: &mut R) -> io::Result<XacColor> {
+    Ok(XacColor {
+        x: file.read_f32::<E>()?,
+        y: file.read_f32::<E>()?,
+        z: file.read_f32::<E>()?,
+    })
+}
+
+pub(crate) fn xac_read_vec2d<R: Read, E: ByteOrder>(file: &mut R) -> io::Result<XacVec2d> {
+    Ok(XacVec2d {
+        x: file.read_f32::<E>()?,
+        y: file.read_f32::<E>()?,
+    })
+}
+pub(crate) fn xac_read_vec3d<R: Read, E: ByteOrder>(file: &mut R) -> io::Result<XacVec3d> {
+    Ok(XacVec3d {
+        x: file.read_f32::<E>()?,
+        y: file.read_f32::<E>()?,
+        z: file.read_f32::<E>()?,
+    })
+}
+pub(crate) fn xac_read_vec4d<R: Read, E: ByteOrder>(file: &mut R) -> io::Result<XacVec4d> {
+    Ok(XacVec4d {
+        x: file.read_f32::<E>()?,
+        y: file.read_f32::<E>()?,
+        z: file.read_f32::<E>()?,
+        w: file.read_f32::<E>()?,
+    })
+}
+
+pub(crate) fn xac_read_quaternion<R: Read, E: ByteOrder>(
+    file: &mut R,
+) -> io::Result<XacQuaternion> {
+    Ok(XacQuaternion {
+        x: file.read_f32::<E>()?,
+        y: file.read_f32::<E>()?,
+        z: file.read_f32::<E>()?,
+        w: file.read_f32::<E>()?,
+    })
+}
+
+pub(crate) fn xac_read_matrix44<R: Read, E: ByteOrder>(file: &mut R) -> io::Result<XacMatrix44> {
+    Ok(XacMatrix44 {
+        axis_1: xac_read_vec4d::<_, E>(file)?,
+        axis_2: xac_read_vec4d::<_, E>(file)?,
+        axis_3: xac_read_vec4d::<_, E>(file)?,
+        pos: xac_read_vec4d::<_, E>(file)?,
+    })
+}
+
+// Strings are read one byte per character, so only characters up to U+00FF
+// can be written back.
+pub(crate) fn xac_write_string<W: Write, E: ByteOrder>(file: &mut W, text: &str) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for character in text.chars() {
+        if character as u32 > 0xff {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Character {:?} cannot be stored in an XAC string",
+                    character
+                ),
+            ));
+        }
+        bytes.push(character as u8);
+    }
+    file.write_i32::<E>(bytes.len() as i32)?;
+    file.write_all(&bytes)
+}
+
+pub(crate) fn xac_write_boolean<W: Write>(file: &mut W, value: bool) -> io::Result<()> {
+    file.write_u8(value as u8)
+}
+
+pub(crate) fn xac_write_color8<W: Write>(file: &mut W, value: &XacColor8) -> io::Result<()> {
+    file.write_u8(value.x)?;
+    file.write_u8(value.y)?;
+    file.write_u8(value.z)?;
+    file.write_u8(value.w)
+}
+
+pub(crate) fn xac_write_vec3d16<W: Write, E: ByteOrder>(
+    file: &mut W,
+    value: &XacVec3d16,
+) -> io::Result<()> {
+    file.write_u16::<E>(value.x)?;
+    file.write_u16::<E>(value.y)?;
+    file.write_u16::<E>(value.z)
+}
+
+pub(crate) fn xac_write_vec3d8<W: Write>(file: &mut W, value: &XacVec3d8) -> io::Result<()> {
+    file.write_u8(value.x)?;
+    file.write_u8(value.y)?;
+    file.write_u8(value.z)
+}
+
+pub(crate) fn xac_write_vec2d<W: Write, E: ByteOrder>(
+    file: &mut W,
+    value: &XacVec2d,
+) -> io::Result<()> {
+    file.write_f32::<E>(value.x)?;
+    file.write_f32::<E>(value.y)
+}
+
+pub(crate) fn xac_write_vec3d<W: Write, E: ByteOrder>(
+    file: &mut W,
+    value: &XacVec3d,
+) -> io::Result<()> {
+    file.write_f32::<E>(value.x)?;
+    file.write_f32::<E>(value.y)?;
+    file.write_f32::<E>(value.z)
+}
+
+pub(crate) fn xac_write_vec4d<W: Write, E: ByteOrder>(
+    file: &mut W,
+    value: &XacVec4d,
+) -> io::Result<()> {
+    file.write_f32::<E>(value.x)?;
+    file.write_f32::<E>(value.y)?;
+    file.write_f32::<E>(value.z)?;
+    file.write_f32::<E>(value.w)
+}
+
+pub(crate) fn xac_write_quaternion<W: Write, E: ByteOrder>(
+    file: &mut W,
+    value: &XacQuaternion,
+) -> io::Result<()> {
+    file.write_f32::<E>(value.x)?;
+    file.write_f32::<E>(value.y)?;
+    file.write_f32::<E>(value.z)?;
+    file.write_f32::<E>(value.w)
+}
+
+pub(crate) fn xac_write_matrix44<W: Write, E: ByteOrder>(
+    file: &mut W,
+    value: &XacMatrix44,
+) -> io::Result<()> {
+    xac_write_vec4d::<_, E>(file, &value.axis_1)?;
+    xac_write_vec4d::<_, E>(file, &value.axis_2)?;
+    xac_write_vec4d::<_, E>(file, &value.axis_3)?;
+    xac_write_vec4d::<_, E>(file, &value.pos)
+}
+
+// Morph target deltas are quantized into the [min, max] range of their deformation.
+pub(crate) fn xac_decompress_vec3d16(value: &XacVec3d16, min: f32, max: f32) -> XacVec3d {
+    let range = max - min;
+    XacVec3d {
+        x: min + (value.x as f32 / 65535.0) * range,
+        y: min + (value.y as f32 / 65535.0) * range,
+        z: min + (value.z as f32 / 65535.0) * range,
+    }
 }
 
-pub(crate) fn xac_read_vec2d(file: &mut File) -> XacVec2d {
-    let vec2d = XacVec2d {
-        x: file.read_f32::<LittleEndian>().unwrap(),
-        y: file.read_f32::<LittleEndian>().unwrap(),
+pub(crate) fn xac_decompress_vec3d8(value: &XacVec3d8, min: f32, max: f32) -> XacVec3d {
+    let range = max - min;
+    XacVec3d {
+        x: min + (value.x as f32 / 255.0) * range,
+        y: min + (value.y as f32 / 255.0) * range,
+        z: min + (value.z as f32 / 255.0) * range,
+    }
+}
+
+pub(crate) fn xac_compress_vec3d16(value: &XacVec3d, min: f32, max: f32) -> XacVec3d16 {
+    let range = max - min;
+    let compress = |component: f32| {
+        if range > 0.0 {
+            ((component - min) / range * 65535.0)
+                .round()
+                .clamp(0.0, 65535.0) as u16
+        } else {
+            0
+        }
     };
-    vec2d
+    XacVec3d16 {
+        x: compress(value.x),
+        y: compress(value.y),
+        z: compress(value.z),
+    }
 }
-pub(crate) fn xac_read_vec3d(file: &mut File) -> XacVec3d {
-    let vec3d = XacVec3d {
-        x: file.read_f32::<LittleEndian>().unwrap(),
-        y: file.read_f32::<LittleEndian>().unwrap(),
-        z: file.read_f32::<LittleEndian>().unwrap(),
+
+pub(crate) fn xac_compress_vec3d8(value: &XacVec3d, min: f32, max: f32) -> XacVec3d8 {
+    let range = max - min;
+    let compress = |component: f32| {
+        if range > 0.0 {
+            ((component - min) / range * 255.0)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        } else {
+            0
+        }
     };
-    vec3d
-}
-pub(crate) fn xac_read_vec4d(file: &mut File) -> XacVec4d {
-    let vec4d = XacVec4d {
-        x: file.read_f32::<LittleEndian>().unwrap(),
-        y: file.read_f32::<LittleEndian>().unwrap(),
-        z: file.read_f32::<LittleEndian>().unwrap(),
-        w: file.read_f32::<LittleEndian>().unwrap(),
+    XacVec3d8 {
+        x: compress(value.x),
+        y: compress(value.y),
+        z: compress(value.z),
+    }
+}
+
+// Matrices use row vectors like EMotion FX: `axis_1..3` are the transformed
+// basis axes, `pos` is the translation and `a * b` applies `a` first.
+pub(crate) fn xac_matrix44_to_array(value: &XacMatrix44) -> [[f32; 4]; 4] {
+    [
+        [
+            value.axis_1.x,
+            value.axis_1.y,
+            value.axis_1.z,
+            value.axis_1.w,
+        ],
+        [
+            value.axis_2.x,
+            value.axis_2.y,
+            value.axis_2.z,
+            value.axis_2.w,
+        ],
+        [
+            value.axis_3.x,
+            value.axis_3.y,
+            value.axis_3.z,
+            value.axis_3.w,
+        ],
+        [value.pos.x, value.pos.y, value.pos.z, value.pos.w],
+    ]
+}
+
+pub(crate) fn xac_matrix44_from_array(rows: &[[f32; 4]; 4]) -> XacMatrix44 {
+    let row = |index: usize| XacVec4d {
+        x: rows[index][0],
+        y: rows[index][1],
+        z: rows[index][2],
+        w: rows[index][3],
     };
-    vec4d
+    XacMatrix44 {
+        axis_1: row(0),
+        axis_2: row(1),
+        axis_3: row(2),
+        pos: row(3),
+    }
+}
+
+pub(crate) fn xac_matrix44_identity() -> XacMatrix44 {
+    xac_matrix44_from_array(&[
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
 }
 
-pub(crate) fn xac_read_quaternion(file: &mut File) -> XacQuaternion {
-    let quaternion = XacQuaternion {
-        x: file.read_f32::<LittleEndian>().unwrap(),
-        y: file.read_f32::<LittleEndian>().unwrap(),
-        z: file.read_f32::<LittleEndian>().unwrap(),
-        w: file.read_f32::<LittleEndian>().unwrap(),
+pub(crate) fn xac_matrix44_multiply(a: &XacMatrix44, b: &XacMatrix44) -> XacMatrix44 {
+    let a = xac_matrix44_to_array(a);
+    let b = xac_matrix44_to_array(b);
+    let mut rows = [[0.0f32; 4]; 4];
+    for (row, a_row) in rows.iter_mut().zip(a.iter()) {
+        for (column, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a_row[k] * b[k][column]).sum();
+        }
+    }
+    xac_matrix44_from_array(&rows)
+}
+
+pub(crate) fn xac_matrix44_from_translation(value: &XacVec3d) -> XacMatrix44 {
+    xac_matrix44_from_array(&[
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [value.x, value.y, value.z, 1.0],
+    ])
+}
+
+pub(crate) fn xac_matrix44_from_scale(value: &XacVec3d) -> XacMatrix44 {
+    xac_matrix44_from_array(&[
+        [value.x, 0.0, 0.0, 0.0],
+        [0.0, value.y, 0.0, 0.0],
+        [0.0, 0.0, value.z, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+// The quaternion is normalized first, a zero quaternion gives the identity.
+pub(crate) fn xac_matrix44_from_quaternion(value: &XacQuaternion) -> XacMatrix44 {
+    let length =
+        (value.x * value.x + value.y * value.y + value.z * value.z + value.w * value.w).sqrt();
+    if length <= f32::EPSILON {
+        return xac_matrix44_identity();
+    }
+    let (x, y, z, w) = (
+        value.x / length,
+        value.y / length,
+        value.z / length,
+        value.w / length,
+    );
+    xac_matrix44_from_array(&[
+        [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y + z * w),
+            2.0 * (x * z - y * w),
+            0.0,
+        ],
+        [
+            2.0 * (x * y - z * w),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z + x * w),
+            0.0,
+        ],
+        [
+            2.0 * (x * z + y * w),
+            2.0 * (y * z - x * w),
+            1.0 - 2.0 * (x * x + y * y),
+            0.0,
+        ],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+// Inverse by cofactor expansion, `None` for a singular matrix.
+pub(crate) fn xac_matrix44_inverse(value: &XacMatrix44) -> Option<XacMatrix44> {
+    let m = xac_matrix44_to_array(value);
+    let minor = |row: usize, column: usize| -> f32 {
+        let mut values = [0.0f32; 9];
+        let mut index = 0;
+        for (r, m_row) in m.iter().enumerate() {
+            for (c, m_value) in m_row.iter().enumerate() {
+                if r != row && c != column {
+                    values[index] = *m_value;
+                    index += 1;
+                }
+            }
+        }
+        values[0] * (values[4] * values[8] - values[5] * values[7])
+            - values[1] * (values[3] * values[8] - values[5] * values[6])
+            + values[2] * (values[3] * values[7] - values[4] * values[6])
     };
-    quaternion
+    let mut cofactors = [[0.0f32; 4]; 4];
+    for (row, cofactor_row) in cofactors.iter_mut().enumerate() {
+        for (column, cofactor) in cofactor_row.iter_mut().enumerate() {
+            let sign = if (row + column) % 2 == 0 { 1.0 } else { -1.0 };
+            *cofactor = sign * minor(row, column);
+        }
+    }
+    let determinant: f32 = (0..4)
+        .map(|column| m[0][column] * cofactors[0][column])
+        .sum();
+    if determinant.abs() <= f32::EPSILON * f32::EPSILON {
+        return None;
+    }
+    let mut rows = [[0.0f32; 4]; 4];
+    for (row, inverse_row) in rows.iter_mut().enumerate() {
+        for (column, value) in inverse_row.iter_mut().enumerate() {
+            *value = cofactors[column][row] / determinant;
+        }
+    }
+    Some(xac_matrix44_from_array(&rows))
 }
 
-pub(crate) fn xac_read_matrix44(file: &mut File) -> XacMatrix44 {
-    let matrix44 = XacMatrix44 {
-        axis_1: xac_read_vec4d(file),
-        axis_2: xac_read_vec4d(file),
-        axis_3: xac_read_vec4d(file),
-        pos: xac_read_vec4d(file),
+// Normals use the inverse transpose so non-uniform scale keeps them
+// perpendicular to the surface.
+pub(crate) fn xac_matrix44_normal(matrix: &XacMatrix44) -> XacMatrix44 {
+    let inverse = match xac_matrix44_inverse(matrix) {
+        Some(inverse) => xac_matrix44_to_array(&inverse),
+        None => return matrix.clone(),
     };
-    matrix44
+    let mut rows = xac_matrix44_to_array(&xac_matrix44_identity());
+    for (row, rows_row) in rows.iter_mut().enumerate().take(3) {
+        for (column, value) in rows_row.iter_mut().enumerate().take(3) {
+            *value = inverse[column][row];
+        }
+    }
+    xac_matrix44_from_array(&rows)
+}
+
+pub(crate) fn xac_matrix44_transform_point(matrix: &XacMatrix44, value: &XacVec3d) -> XacVec3d {
+    let m = xac_matrix44_to_array(matrix);
+    XacVec3d {
+        x: value.x * m[0][0] + value.y * m[1][0] + value.z * m[2][0] + m[3][0],
+        y: value.x * m[0][1] + value.y * m[1][1] + value.z * m[2][1] + m[3][1],
+        z: value.x * m[0][2] + value.y * m[1][2] + value.z * m[2][2] + m[3][2],
+    }
+}
+
+// Transforms a direction, ignoring the translation.
+pub(crate) fn xac_matrix44_transform_vector(matrix: &XacMatrix44, value: &XacVec3d) -> XacVec3d {
+    let m = xac_matrix44_to_array(matrix);
+    XacVec3d {
+        x: value.x * m[0][0] + value.y * m[1][0] + value.z * m[2][0],
+        y: value.x * m[0][1] + value.y * m[1][1] + value.z * m[2][1],
+        z: value.x * m[0][2] + value.y * m[1][2] + value.z * m[2][2],
+    }
+}
+
+pub(crate) fn xac_normalize_vec3d(value: &XacVec3d) -> XacVec3d {
+    let length = (value.x * value.x + value.y * value.y + value.z * value.z).sqrt();
+    if length <= f32::EPSILON {
+        return value.clone();
+    }
+    XacVec3d {
+        x: value.x / length,
+        y: value.y / length,
+        z: value.z / length,
+    }
 }
@@ -0,0 +1,974 @@
+#![allow(dead_code)]
+use crate::xac::xac_enum::XacChunkType::{
+    XacMaterialDefinitionId, XacMaterialTotalId, XacMeshId, XacMetadataId, XacMorphTargetId,
+    XacNodeHierarchyId, XacShaderMaterialId, XacSkinningId,
+};
+use crate::xac::xac_enum::XacVerticesAttributeType::{
+    XacColor128Id, XacColor32Id, XacInfluenceRangeId, XacNormalId, XacPositionId, XacTangentId,
+    XacUVCoordId,
+};
+use crate::xac::xac_error::XacError;
+use crate::xac::xac_struct::{
+    XacActorFile, XacActorMaterial, XacActorMaterialLayer, XacActorMesh, XacActorVertexAttribute,
+    XacBoneInfluence, XacMorphTargetChunk, XacShaderMaterial, XacVec3d,
+};
+use crate::xac::xac_util::{
+    xac_compress_vec3d16, xac_compress_vec3d8, xac_decompress_vec3d16, xac_decompress_vec3d8,
+    xac_write_boolean, xac_write_color8, xac_write_matrix44, xac_write_quaternion,
+    xac_write_string, xac_write_vec2d, xac_write_vec3d, xac_write_vec3d16, xac_write_vec3d8,
+    xac_write_vec4d,
+};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+
+pub fn xacwrite(path: &str, xac: &XacActorFile) -> Result<(), XacError> {
+    let mut xac_file = BufWriter::new(File::create(path)?);
+    xac.to_writer(&mut xac_file)?;
+    xac_file.flush()?;
+    Ok(())
+}
+
+impl XacActorFile {
+    pub fn to_writer<W: Write>(&self, writer: &mut W) -> Result<(), XacError> {
+        writer.write_all(&self.to_bytes()?)?;
+        Ok(())
+    }
+
+    // Chunks are written with the latest layout the parser reads, in the
+    // order the parser needs them. Unknown chunks are appended at the end in
+    // the order they were read, so one that sat between known chunks moves.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, XacError> {
+        let mut bytes = Vec::new();
+        write_header(&mut bytes, self)?;
+        if self.header.big_endian {
+            write_chunks::<BigEndian>(&mut bytes, self)?;
+        } else {
+            write_chunks::<LittleEndian>(&mut bytes, self)?;
+        }
+        Ok(bytes)
+    }
+}
+
+fn write_header<W: Write>(file: &mut W, xac: &XacActorFile) -> Result<(), XacError> {
+    file.write_all(b"XAC ")?;
+    file.write_u8(xac.header.major_version)?;
+    file.write_u8(xac.header.minor_version)?;
+    xac_write_boolean(file, xac.header.big_endian)?;
+    file.write_u8(xac.header.multiply_order)?;
+    Ok(())
+}
+
+fn write_chunks<E: ByteOrder>(file: &mut Vec<u8>, xac: &XacActorFile) -> Result<(), XacError> {
+    write_chunk::<E>(file, XacMetadataId as i32, 2, |chunk| {
+        write_metadata::<_, E>(chunk, xac)
+    })?;
+    if !xac.nodes.is_empty() {
+        write_chunk::<E>(file, XacNodeHierarchyId as i32, 1, |chunk| {
+            write_node_hierarchy::<_, E>(chunk, xac)
+        })?;
+    }
+    if !xac.materials.is_empty() {
+        write_chunk::<E>(file, XacMaterialTotalId as i32, 1, |chunk| {
+            write_material_total::<_, E>(chunk, xac)
+        })?;
+    }
+    for (material_id, material) in xac.materials.iter().enumerate() {
+        write_chunk::<E>(file, XacMaterialDefinitionId as i32, 2, |chunk| {
+            write_material_definition::<_, E>(chunk, material, material_id)
+        })?;
+    }
+    for shader_material in xac.shader_materials.iter() {
+        write_chunk::<E>(file, XacShaderMaterialId as i32, 1, |chunk| {
+            write_shader_material::<_, E>(chunk, shader_material)
+        })?;
+    }
+
+    // Skinning chunks look up the mesh they belong to, so every mesh is
+    // written first.
+    let mut skinned_meshes: Vec<(usize, &XacActorMesh, bool, usize)> = Vec::new();
+    for (node_id, node) in xac.nodes.iter().enumerate() {
+        for (mesh, is_collision_mesh) in [(&node.visual_mesh, false), (&node.collision_mesh, true)]
+        {
+            if mesh.sub_meshes.is_empty() {
+                continue;
+            }
+            let num_influence_ranges =
+                mesh_num_influence_ranges(xac, node_id, mesh, is_collision_mesh);
+            write_chunk::<E>(file, XacMeshId as i32, 1, |chunk| {
+                write_mesh::<_, E>(
+                    chunk,
+                    node_id,
+                    mesh,
+                    is_collision_mesh,
+                    num_influence_ranges,
+                )
+            })?;
+            let is_skinned = mesh
+                .sub_meshes
+                .iter()
+                .any(|sub_mesh| !sub_mesh.vertex_influences.is_empty());
+            if is_skinned {
+                skinned_meshes.push((node_id, mesh, is_collision_mesh, num_influence_ranges));
+            }
+        }
+    }
+    for (node_id, mesh, is_collision_mesh, num_influence_ranges) in skinned_meshes {
+        write_chunk::<E>(file, XacSkinningId as i32, 3, |chunk| {
+            write_skinning::<_, E>(
+                chunk,
+                node_id,
+                mesh,
+                is_collision_mesh,
+                num_influence_ranges,
+            )
+        })?;
+    }
+
+    for morph_target_chunk in morph_target_chunks(xac)?.iter() {
+        write_chunk::<E>(file, XacMorphTargetId as i32, 1, |chunk| {
+            write_morph_target::<_, E>(chunk, xac, morph_target_chunk)
+        })?;
+    }
+    for unknown_chunk in xac.unknown_chunks.iter() {
+        write_chunk::<E>(
+            file,
+            unknown_chunk.type_id,
+            unknown_chunk.version,
+            |chunk| {
+                chunk.write_all(&unknown_chunk.data)?;
+                Ok(())
+            },
+        )?;
+    }
+    Ok(())
+}
+
+// Chunk data is written to a buffer first since the header stores its length.
+// Errors are located like the parser does, at the start of the chunk.
+fn write_chunk<E: ByteOrder>(
+    file: &mut Vec<u8>,
+    type_id: i32,
+    version: i32,
+    write: impl FnOnce(&mut Vec<u8>) -> Result<(), XacError>,
+) -> Result<(), XacError> {
+    let chunk_start = file.len() as u64;
+    let mut data = Vec::new();
+    write(&mut data).map_err(|err| err.locate(Some(type_id), chunk_start))?;
+    if data.len() > i32::MAX as usize {
+        return Err(XacError::inconsistent("Chunk is too large").locate(Some(type_id), chunk_start));
+    }
+    file.write_i32::<E>(type_id)?;
+    file.write_i32::<E>(data.len() as i32)?;
+    file.write_i32::<E>(version)?;
+    file.write_all(&data)?;
+    Ok(())
+}
+
+fn write_metadata<W: Write, E: ByteOrder>(
+    file: &mut W,
+    xac: &XacActorFile,
+) -> Result<(), XacError> {
+    file.write_u32::<E>(xac.metadata.reposition_mask)?;
+    file.write_i32::<E>(xac.metadata.repositioning_node)?;
+    file.write_u8(xac.metadata.exporter_major_version)?;
+    file.write_u8(xac.metadata.exporter_minor_version)?;
+    file.write_u8(0)?; //Padding
+    file.write_u8(0)?; //Padding
+    file.write_f32::<E>(xac.metadata.retarget_root_offset)?;
+    xac_write_string::<_, E>(file, &xac.source_app)?;
+    xac_write_string::<_, E>(file, &xac.original_filename)?;
+    xac_write_string::<_, E>(file, &xac.export_date)?;
+    xac_write_string::<_, E>(file, &xac.actor_name)?;
+    Ok(())
+}
+
+// The flat node list is authoritative, root and child counts are derived
+// from the parent ids so an edited hierarchy stays consistent.
+fn write_node_hierarchy<W: Write, E: ByteOrder>(
+    file: &mut W,
+    xac: &XacActorFile,
+) -> Result<(), XacError> {
+    let num_root_nodes = xac
+        .nodes
+        .iter()
+        .filter(|node| node.parent_node_id == -1)
+        .count();
+    file.write_i32::<E>(xac.nodes.len() as i32)?;
+    file.write_i32::<E>(num_root_nodes as i32)?;
+
+    let mut num_children: Vec<u32> = vec![0; xac.nodes.len()];
+    for node in xac.nodes.iter() {
+        if let Some(count) = usize::try_from(node.parent_node_id)
+            .ok()
+            .and_then(|parent_id| num_children.get_mut(parent_id))
+        {
+            *count += 1;
+        }
+    }
+
+    for (node_id, node) in xac.nodes.iter().enumerate() {
+        xac_write_quaternion::<_, E>(file, &node.rotation)?;
+        xac_write_quaternion::<_, E>(file, &node.scale_rotation)?;
+        xac_write_vec3d::<_, E>(file, &node.position)?;
+        xac_write_vec3d::<_, E>(file, &node.scale)?;
+        xac_write_vec3d::<_, E>(file, &node.shear)?;
+        file.write_u32::<E>(node.skeletal_lods)?;
+        file.write_u32::<E>(node.motion_lods)?;
+        file.write_i32::<E>(node.parent_node_id)?;
+        file.write_i32::<E>(num_children[node_id] as i32)?;
+        file.write_u8(node.include_inbounds_calc)?;
+        file.write_u8(node.node_flags)?;
+        file.write_u8(0)?; //Padding
+        file.write_u8(0)?; //Padding
+        xac_write_matrix44::<_, E>(file, &node.transform)?;
+        file.write_f32::<E>(node.importance_factor)?;
+        xac_write_string::<_, E>(file, &node.name)?;
+    }
+    Ok(())
+}
+
+fn write_material_total<W: Write, E: ByteOrder>(
+    file: &mut W,
+    xac: &XacActorFile,
+) -> Result<(), XacError> {
+    let num_standard_materials = xac.materials.len() as i32;
+    let num_fx_materials = xac.shader_materials.len() as i32;
+    file.write_i32::<E>(num_standard_materials + num_fx_materials)?;
+    file.write_i32::<E>(num_standard_materials)?;
+    file.write_i32::<E>(num_fx_materials)?;
+    Ok(())
+}
+
+fn write_material_definition<W: Write, E: ByteOrder>(
+    file: &mut W,
+    material: &XacActorMaterial,
+    material_id: usize,
+) -> Result<(), XacError> {
+    if material.layers.len() > u8::MAX as usize {
+        return Err(XacError::inconsistent(format!(
+            "Material {} has more than {} layers",
+            material.name,
+            u8::MAX
+        )));
+    }
+
+    xac_write_vec4d::<_, E>(file, &material.ambient_color)?;
+    xac_write_vec4d::<_, E>(file, &material.diffuse_color)?;
+    xac_write_vec4d::<_, E>(file, &material.specular_color)?;
+    xac_write_vec4d::<_, E>(file, &material.emissive_color)?;
+    file.write_f32::<E>(material.shine)?;
+    file.write_f32::<E>(material.shine_strength)?;
+    file.write_f32::<E>(material.opacity)?;
+    file.write_f32::<E>(material.ior)?;
+    xac_write_boolean(file, material.double_sided)?;
+    xac_write_boolean(file, material.wireframe)?;
+    file.write_u8(material.transparency_type)?;
+    file.write_u8(material.layers.len() as u8)?;
+    xac_write_string::<_, E>(file, &material.name)?;
+
+    for layer in material.layers.iter() {
+        write_material_layer::<_, E>(file, layer, material_id)?;
+    }
+    Ok(())
+}
+
+fn write_material_layer<W: Write, E: ByteOrder>(
+    file: &mut W,
+    layer: &XacActorMaterialLayer,
+    material_id: usize,
+) -> Result<(), XacError> {
+    file.write_f32::<E>(layer.amount)?;
+    file.write_f32::<E>(layer.u_offset)?;
+    file.write_f32::<E>(layer.v_offset)?;
+    file.write_f32::<E>(layer.u_tiling)?;
+    file.write_f32::<E>(layer.v_tiling)?;
+    file.write_f32::<E>(layer.rotation)?;
+    file.write_i16::<E>(material_id as i16)?;
+    file.write_u8(layer.map_type)?;
+    file.write_u8(0)?; //Padding
+    xac_write_string::<_, E>(file, &layer.name)?;
+    Ok(())
+}
+
+fn write_shader_material<W: Write, E: ByteOrder>(
+    file: &mut W,
+    shader_material: &XacShaderMaterial,
+) -> Result<(), XacError> {
+    file.write_i32::<E>(shader_material.int_property.len() as i32)?;
+    file.write_i32::<E>(shader_material.float_property.len() as i32)?;
+    file.write_i32::<E>(shader_material.padding)?;
+    file.write_i32::<E>(shader_material.bool_property.len() as i32)?;
+    file.write_i32::<E>(shader_material.flag)?;
+    file.write_i32::<E>(shader_material.string_property.len() as i32)?;
+    xac_write_string::<_, E>(file, &shader_material.name_material)?;
+    xac_write_string::<_, E>(file, &shader_material.name_shader)?;
+
+    for property in shader_material.int_property.iter() {
+        xac_write_string::<_, E>(file, &property.name_properties)?;
+        file.write_i32::<E>(property.value)?;
+    }
+    for property in shader_material.float_property.iter() {
+        xac_write_string::<_, E>(file, &property.name_properties)?;
+        file.write_f32::<E>(property.value)?;
+    }
+    for property in shader_material.bool_property.iter() {
+        xac_write_string::<_, E>(file, &property.name_properties)?;
+        file.write_u8(property.value)?;
+    }
+    file.write_i32::<E>(shader_material.skipped_data.len() as i32)?;
+    file.write_all(&shader_material.skipped_data)?;
+    for property in shader_material.string_property.iter() {
+        xac_write_string::<_, E>(file, &property.name_properties)?;
+        xac_write_string::<_, E>(file, &property.value)?;
+    }
+    Ok(())
+}
+
+// The number of original vertices is known from the parsed mesh chunk. Meshes
+// built in memory fall back to the highest original vertex number, or treat
+// every vertex as its own original vertex.
+fn mesh_num_influence_ranges(
+    xac: &XacActorFile,
+    node_id: usize,
+    mesh: &XacActorMesh,
+    is_collision_mesh: bool,
+) -> usize {
+    let parsed = xac
+        .mesh_num_influence_range
+        .iter()
+        .find(|range| range.node_id == node_id && range.is_collision_mesh == is_collision_mesh)
+        .map(|range| range.num.max(0) as usize)
+        .unwrap_or(0);
+
+    let mut required: usize = 0;
+    let mut vertex_offset: usize = 0;
+    for sub_mesh in mesh.sub_meshes.iter() {
+        for vertex in 0..sub_mesh.vertex_positions.len() {
+            let original_vertex = match sub_mesh.influence_range_indices.get(vertex) {
+                Some(index) => (*index).max(0) as usize,
+                None => vertex_offset + vertex,
+            };
+            required = required.max(original_vertex + 1);
+        }
+        vertex_offset += sub_mesh.vertex_positions.len();
+    }
+    parsed.max(required)
+}
+
+// Vertex attributes as parsed, or a default layout for meshes built in
+// memory that only have their submesh arrays filled.
+fn mesh_vertex_attributes(mesh: &XacActorMesh) -> Vec<XacActorVertexAttribute> {
+    if !mesh.vertex_attributes.is_empty() {
+        return mesh.vertex_attributes.clone();
+    }
+
+    let attribute = |usage: u32, element_size: i32| XacActorVertexAttribute {
+        usage,
+        element_size,
+        keep_originals: false,
+        is_scale_factor: false,
+        data: vec![],
+    };
+    let mut attributes = vec![attribute(XacPositionId as u32, 12)];
+    let Some(sub_mesh) = mesh.sub_meshes.first() else {
+        return attributes;
+    };
+    if !sub_mesh.vertex_normals.is_empty() {
+        attributes.push(attribute(XacNormalId as u32, 12));
+    }
+    if !sub_mesh.vertex_tangents.is_empty() {
+        attributes.push(attribute(XacTangentId as u32, 16));
+    }
+    if !sub_mesh.vertex_bi_tangents.is_empty() {
+        attributes.push(attribute(XacTangentId as u32, 16));
+    }
+    for _ in sub_mesh.vertex_uvs.iter() {
+        attributes.push(attribute(XacUVCoordId as u32, 8));
+    }
+    for _ in sub_mesh.vertex_colors_32.iter() {
+        attributes.push(attribute(XacColor32Id as u32, 4));
+    }
+    for _ in sub_mesh.vertex_colors_128.iter() {
+        attributes.push(attribute(XacColor128Id as u32, 16));
+    }
+    if !sub_mesh.influence_range_indices.is_empty() {
+        attributes.push(attribute(XacInfluenceRangeId as u32, 4));
+    }
+    attributes
+}
+
+fn write_mesh<W: Write, E: ByteOrder>(
+    file: &mut W,
+    node_id: usize,
+    mesh: &XacActorMesh,
+    is_collision_mesh: bool,
+    num_influence_ranges: usize,
+) -> Result<(), XacError> {
+    let num_vertices: usize = mesh
+        .sub_meshes
+        .iter()
+        .map(|sub_mesh| sub_mesh.vertex_positions.len())
+        .sum();
+    let num_indices: usize = mesh
+        .sub_meshes
+        .iter()
+        .map(|sub_mesh| sub_mesh.indices.len())
+        .sum();
+    let vertex_attributes = mesh_vertex_attributes(mesh);
+
+    file.write_i32::<E>(node_id as i32)?;
+    file.write_i32::<E>(num_influence_ranges as i32)?;
+    file.write_i32::<E>(num_vertices as i32)?;
+    file.write_i32::<E>(num_indices as i32)?;
+    file.write_i32::<E>(mesh.sub_meshes.len() as i32)?;
+    file.write_i32::<E>(vertex_attributes.len() as i32)?;
+    xac_write_boolean(file, is_collision_mesh)?;
+    file.write_u8(0)?; //Padding
+    file.write_u8(0)?; //Padding
+    file.write_u8(0)?; //Padding
+
+    // Attributes sharing a usage are told apart by their order, the first
+    // tangent attribute holds tangents and the second one bitangents.
+    let mut usage_sets: Vec<(u32, usize)> = Vec::new();
+    for attribute in vertex_attributes.iter() {
+        let set = match usage_sets
+            .iter_mut()
+            .find(|(usage, _)| *usage == attribute.usage)
+        {
+            Some((_, count)) => {
+                *count += 1;
+                *count - 1
+            }
+            None => {
+                usage_sets.push((attribute.usage, 1));
+                0
+            }
+        };
+
+        file.write_u32::<E>(attribute.usage)?;
+        file.write_i32::<E>(attribute.element_size)?;
+        xac_write_boolean(file, attribute.keep_originals)?;
+        xac_write_boolean(file, attribute.is_scale_factor)?;
+        file.write_u8(0)?; //Padding
+        file.write_u8(0)?; //Padding
+        write_vertex_attribute::<_, E>(file, mesh, attribute, set, num_vertices)?;
+    }
+
+    for sub_mesh in mesh.sub_meshes.iter() {
+        let sub_mesh_vertices = sub_mesh.vertex_positions.len();
+        if sub_mesh.indices.len() % 3 != 0
+            || sub_mesh
+                .indices
+                .iter()
+                .any(|index| *index as usize >= sub_mesh_vertices)
+        {
+            return Err(XacError::inconsistent(format!(
+                "Submesh of node {} has an invalid triangle list",
+                node_id
+            )));
+        }
+
+        file.write_i32::<E>(sub_mesh.indices.len() as i32)?;
+        file.write_i32::<E>(sub_mesh_vertices as i32)?;
+        file.write_i32::<E>(sub_mesh.material_id)?;
+        file.write_i32::<E>(sub_mesh.bones.len() as i32)?;
+        for index in sub_mesh.indices.iter() {
+            file.write_u32::<E>(*index)?;
+        }
+        for bone in sub_mesh.bones.iter() {
+            file.write_u32::<E>(*bone as u32)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_vertex_attribute<W: Write, E: ByteOrder>(
+    file: &mut W,
+    mesh: &XacActorMesh,
+    attribute: &XacActorVertexAttribute,
+    set: usize,
+    num_vertices: usize,
+) -> Result<(), XacError> {
+    if attribute.element_size < 0 {
+        return Err(XacError::inconsistent(format!(
+            "Invalid element size {} for vertex attribute {}",
+            attribute.element_size, attribute.usage
+        )));
+    }
+
+    let usage = attribute.usage;
+    let sub_meshes = mesh.sub_meshes.iter();
+    if usage == XacPositionId as u32 {
+        let values = sub_meshes.flat_map(|sub_mesh| sub_mesh.vertex_positions.iter());
+        return write_vertex_elements(file, attribute, num_vertices, values, |element, value| {
+            xac_write_vec3d::<_, E>(element, value)
+        });
+    } else if usage == XacNormalId as u32 {
+        let values = sub_meshes.flat_map(|sub_mesh| sub_mesh.vertex_normals.iter());
+        return write_vertex_elements(file, attribute, num_vertices, values, |element, value| {
+            xac_write_vec3d::<_, E>(element, value)
+        });
+    } else if usage == XacTangentId as u32 && set == 0 {
+        let values = sub_meshes.flat_map(|sub_mesh| sub_mesh.vertex_tangents.iter());
+        return write_vertex_elements(file, attribute, num_vertices, values, |element, value| {
+            xac_write_vec4d::<_, E>(element, value)
+        });
+    } else if usage == XacTangentId as u32 && set == 1 {
+        let values = sub_meshes.flat_map(|sub_mesh| sub_mesh.vertex_bi_tangents.iter());
+        return write_vertex_elements(file, attribute, num_vertices, values, |element, value| {
+            xac_write_vec4d::<_, E>(element, value)
+        });
+    } else if usage == XacUVCoordId as u32 {
+        let values =
+            sub_meshes.flat_map(|sub_mesh| sub_mesh.vertex_uvs.get(set).into_iter().flatten());
+        return write_vertex_elements(file, attribute, num_vertices, values, |element, value| {
+            xac_write_vec2d::<_, E>(element, value)
+        });
+    } else if usage == XacColor32Id as u32 {
+        let values = sub_meshes
+            .flat_map(|sub_mesh| sub_mesh.vertex_colors_32.get(set).into_iter().flatten());
+        return write_vertex_elements(file, attribute, num_vertices, values, xac_write_color8);
+    } else if usage == XacInfluenceRangeId as u32 {
+        let values = sub_meshes.flat_map(|sub_mesh| sub_mesh.influence_range_indices.iter());
+        return write_vertex_elements(file, attribute, num_vertices, values, |element, value| {
+            element.write_i32::<E>(*value)
+        });
+    } else if usage == XacColor128Id as u32 {
+        let values = sub_meshes
+            .flat_map(|sub_mesh| sub_mesh.vertex_colors_128.get(set).into_iter().flatten());
+        return write_vertex_elements(file, attribute, num_vertices, values, |element, value| {
+            xac_write_vec4d::<_, E>(element, value)
+        });
+    }
+
+    if attribute.data.len() != attribute.element_size as usize * num_vertices {
+        return Err(XacError::inconsistent(format!(
+            "Vertex attribute {} has {} bytes for {} vertices",
+            usage,
+            attribute.data.len(),
+            num_vertices
+        )));
+    }
+    file.write_all(&attribute.data)?;
+    Ok(())
+}
+
+// Each value is padded up to the element size of the attribute, with the
+// bytes kept from the parsed element when there are any and zeros otherwise.
+fn write_vertex_elements<'a, W: Write, T: 'a>(
+    file: &mut W,
+    attribute: &XacActorVertexAttribute,
+    num_vertices: usize,
+    values: impl Iterator<Item = &'a T>,
+    write: impl Fn(&mut Vec<u8>, &T) -> io::Result<()>,
+) -> Result<(), XacError> {
+    let element_size = attribute.element_size as usize;
+    let mut data: Vec<u8> = Vec::with_capacity(element_size * num_vertices);
+    let mut count: usize = 0;
+    for value in values {
+        let start = data.len();
+        write(&mut data, value)?;
+        if data.len() - start > element_size {
+            return Err(XacError::inconsistent(format!(
+                "Vertex attribute {} does not fit in {} bytes",
+                attribute.usage, element_size
+            )));
+        }
+        match attribute.data.get(data.len()..start + element_size) {
+            Some(padding) if attribute.data.len() == element_size * num_vertices => {
+                data.extend_from_slice(padding)
+            }
+            _ => data.resize(start + element_size, 0),
+        }
+        count += 1;
+    }
+    if count != num_vertices {
+        return Err(XacError::inconsistent(format!(
+            "Vertex attribute {} has {} values for {} vertices",
+            attribute.usage, count, num_vertices
+        )));
+    }
+    file.write_all(&data)?;
+    Ok(())
+}
+
+// Influences are stored per original vertex, submesh vertices that were split
+// from the same original vertex share one influence range.
+fn write_skinning<W: Write, E: ByteOrder>(
+    file: &mut W,
+    node_id: usize,
+    mesh: &XacActorMesh,
+    is_collision_mesh: bool,
+    num_influence_ranges: usize,
+) -> Result<(), XacError> {
+    let mut original_influences: Vec<Option<&Vec<XacBoneInfluence>>> =
+        vec![None; num_influence_ranges];
+    let mut vertex_offset: usize = 0;
+    for sub_mesh in mesh.sub_meshes.iter() {
+        for (vertex, influences) in sub_mesh.vertex_influences.iter().enumerate() {
+            let original_vertex = match sub_mesh.influence_range_indices.get(vertex) {
+                Some(index) => *index as usize,
+                None => vertex_offset + vertex,
+            };
+            match original_influences.get_mut(original_vertex) {
+                Some(slot) => {
+                    slot.get_or_insert(influences);
+                }
+                None => {
+                    return Err(XacError::inconsistent(
+                        "Vertex refers to a missing influence range",
+                    ))
+                }
+            }
+        }
+        vertex_offset += sub_mesh.vertex_positions.len();
+    }
+
+    let mut influences: Vec<&XacBoneInfluence> = Vec::new();
+    let mut influence_ranges: Vec<(usize, usize)> = Vec::new();
+    for vertex_influences in original_influences.iter() {
+        let vertex_influences = vertex_influences.map(|list| list.as_slice()).unwrap_or(&[]);
+        influence_ranges.push((influences.len(), vertex_influences.len()));
+        influences.extend(vertex_influences.iter());
+    }
+    let local_bones: BTreeSet<usize> = influences
+        .iter()
+        .map(|influence| influence.node_id)
+        .collect();
+
+    file.write_i32::<E>(node_id as i32)?;
+    file.write_i32::<E>(local_bones.len() as i32)?;
+    file.write_i32::<E>(influences.len() as i32)?;
+    xac_write_boolean(file, is_collision_mesh)?;
+    file.write_u8(0)?; //Padding
+    file.write_u8(0)?; //Padding
+    file.write_u8(0)?; //Padding
+
+    for influence in influences.iter() {
+        if influence.node_id > u16::MAX as usize {
+            return Err(XacError::inconsistent(format!(
+                "Influence bone {} does not fit in 16 bits",
+                influence.node_id
+            )));
+        }
+        file.write_f32::<E>(influence.weight)?;
+        file.write_u16::<E>(influence.node_id as u16)?;
+        file.write_u8(0)?; //Padding
+        file.write_u8(0)?; //Padding
+    }
+    for (first_influence_index, num_influences) in influence_ranges.iter() {
+        file.write_i32::<E>(*first_influence_index as i32)?;
+        file.write_i32::<E>(*num_influences as i32)?;
+    }
+    Ok(())
+}
+
+// Morph targets grouped by the chunk they were read from. Targets that are
+// in no chunk, like ones added in memory, go into one more chunk for the
+// base LOD.
+fn morph_target_chunks(xac: &XacActorFile) -> Result<Vec<XacMorphTargetChunk>, XacError> {
+    let mut chunks = xac.morph_target_chunks.clone();
+    let mut in_chunk = vec![false; xac.morph_targets.len()];
+    for chunk in chunks.iter() {
+        for morph_target_id in chunk.morph_target_ids.iter() {
+            match in_chunk.get_mut(*morph_target_id) {
+                Some(seen) => *seen = true,
+                None => {
+                    return Err(XacError::inconsistent(format!(
+                        "Morph target chunk refers to unknown morph target {}",
+                        morph_target_id
+                    )))
+                }
+            }
+        }
+    }
+    let remaining: Vec<usize> = (0..xac.morph_targets.len())
+        .filter(|morph_target_id| !in_chunk[*morph_target_id])
+        .collect();
+    if !remaining.is_empty() {
+        chunks.push(XacMorphTargetChunk {
+            lod_level: 0,
+            morph_target_ids: remaining,
+        });
+    }
+    Ok(chunks)
+}
+
+// The decoded offsets are authoritative. Compressed offsets that still
+// decode to them are written as read, so unedited targets are written back
+// unchanged, and the others are compressed again from the decoded values.
+fn write_morph_target<W: Write, E: ByteOrder>(
+    file: &mut W,
+    xac: &XacActorFile,
+    morph_target_chunk: &XacMorphTargetChunk,
+) -> Result<(), XacError> {
+    file.write_i32::<E>(morph_target_chunk.morph_target_ids.len() as i32)?;
+    file.write_i32::<E>(morph_target_chunk.lod_level)?;
+
+    for morph_target_id in morph_target_chunk.morph_target_ids.iter() {
+        let morph_target = &xac.morph_targets[*morph_target_id];
+        file.write_f32::<E>(morph_target.range_min)?;
+        file.write_f32::<E>(morph_target.range_max)?;
+        file.write_i32::<E>(morph_target.lod_level)?;
+        file.write_i32::<E>(morph_target.deformation.len() as i32)?;
+        file.write_i32::<E>(morph_target.transformations.len() as i32)?;
+        file.write_i32::<E>(morph_target.phoneme_set_bitmask)?;
+        xac_write_string::<_, E>(file, &morph_target.name)?;
+
+        for deformation in morph_target.deformation.iter() {
+            let num_vertices = deformation.vertex_indices.len();
+            file.write_i32::<E>(deformation.node_id as i32)?;
+            file.write_f32::<E>(deformation.min_value)?;
+            file.write_f32::<E>(deformation.max_value)?;
+            file.write_i32::<E>(num_vertices as i32)?;
+
+            let (min_value, max_value) = (deformation.min_value, deformation.max_value);
+            let positions = match morph_offsets(
+                &deformation.compressed_position_offsets,
+                &deformation.position_offsets,
+                num_vertices,
+                |position| xac_decompress_vec3d16(position, min_value, max_value),
+                |position| xac_compress_vec3d16(position, min_value, max_value),
+            ) {
+                Some(positions) => positions,
+                None => {
+                    return Err(XacError::inconsistent(format!(
+                        "Morph target {} has position offsets that do not match its vertices",
+                        morph_target.name
+                    )))
+                }
+            };
+            for position in positions.iter() {
+                xac_write_vec3d16::<_, E>(file, position)?;
+            }
+
+            for (compressed, offsets, kind) in [
+                (
+                    &deformation.compressed_normal_offsets,
+                    &deformation.normal_offsets,
+                    "normal",
+                ),
+                (
+                    &deformation.compressed_tangent_offsets,
+                    &deformation.tangent_offsets,
+                    "tangent",
+                ),
+            ] {
+                let offsets = match morph_offsets(
+                    compressed,
+                    offsets,
+                    num_vertices,
+                    |offset| xac_decompress_vec3d8(offset, -2.0, 2.0),
+                    |offset| xac_compress_vec3d8(offset, -2.0, 2.0),
+                ) {
+                    Some(offsets) => offsets,
+                    None => {
+                        return Err(XacError::inconsistent(format!(
+                            "Morph target {} has {} offsets that do not match its vertices",
+                            morph_target.name, kind
+                        )))
+                    }
+                };
+                for offset in offsets.iter() {
+                    xac_write_vec3d8(file, offset)?;
+                }
+            }
+
+            for vertex_index in deformation.vertex_indices.iter() {
+                file.write_i32::<E>(*vertex_index)?;
+            }
+        }
+
+        for transformation in morph_target.transformations.iter() {
+            file.write_i32::<E>(transformation.node_id)?;
+            xac_write_quaternion::<_, E>(file, &transformation.rotation)?;
+            xac_write_quaternion::<_, E>(file, &transformation.scale_rotation)?;
+            xac_write_vec3d::<_, E>(file, &transformation.position)?;
+            xac_write_vec3d::<_, E>(file, &transformation.scale)?;
+        }
+    }
+    Ok(())
+}
+
+// Offsets to write for a deformation, or `None` when neither the decoded
+// nor the compressed offsets have one per vertex.
+fn morph_offsets<C: Clone>(
+    compressed: &[C],
+    offsets: &[XacVec3d],
+    num_vertices: usize,
+    decompress: impl Fn(&C) -> XacVec3d,
+    compress: impl Fn(&XacVec3d) -> C,
+) -> Option<Vec<C>> {
+    if offsets.len() != num_vertices {
+        return if compressed.len() == num_vertices {
+            Some(compressed.to_vec())
+        } else {
+            None
+        };
+    }
+    let compressed = if compressed.len() == num_vertices {
+        compressed
+    } else {
+        &[]
+    };
+    Some(
+        offsets
+            .iter()
+            .enumerate()
+            .map(|(vertex, offset)| match compressed.get(vertex) {
+                Some(value) if vec3d_equal(&decompress(value), offset) => value.clone(),
+                _ => compress(offset),
+            })
+            .collect(),
+    )
+}
+
+fn vec3d_equal(a: &XacVec3d, b: &XacVec3d) -> bool {
+    a.x == b.x && a.y == b.y && a.z == b.z
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::xac::xac_struct::XacActorFile;
+    use crate::xac::xac_test_util::{actor_file, chunk, chunk_offsets, string};
+    use byteorder::WriteBytesExt;
+    use byteorder::{BigEndian, LittleEndian};
+
+    fn assert_round_trip(bytes: &[u8]) {
+        let parsed = XacActorFile::from_bytes(bytes).unwrap();
+        let node = &parsed.nodes[2];
+        assert_eq!(node.visual_mesh.sub_meshes.len(), 2);
+        assert_eq!(node.visual_mesh.sub_meshes[0].vertex_influences[1].len(), 2);
+        assert_eq!(parsed.materials[1].layers.len(), 2);
+        assert_eq!(node.visual_mesh.sub_meshes[0].material_id, 0);
+        assert_eq!(node.visual_mesh.sub_meshes[1].material_id, 1);
+        assert_eq!(node.visual_mesh.vertex_attributes[8].data.len(), 15);
+        assert!(node.visual_mesh.vertex_attributes[0].data.is_empty());
+        assert_eq!(node.visual_mesh.vertex_attributes[1].data.len(), 80);
+        assert_eq!(node.visual_mesh.vertex_attributes[1].data[76..], [0xa4; 4]);
+        assert_eq!(parsed.shader_materials[0].skipped_data, vec![9, 8, 7]);
+        assert_eq!(parsed.morph_targets.len(), 1);
+        assert_eq!(parsed.unknown_chunks.len(), 1);
+
+        let written = parsed.to_bytes().unwrap();
+        assert_eq!(written, bytes);
+        let reparsed = XacActorFile::from_bytes(&written).unwrap();
+        assert_eq!(
+            serde_json::to_value(&parsed).unwrap(),
+            serde_json::to_value(&reparsed).unwrap()
+        );
+    }
+
+    #[test]
+    fn round_trip_little_endian() {
+        assert_round_trip(&actor_file::<LittleEndian>(false));
+    }
+
+    #[test]
+    fn round_trip_big_endian() {
+        assert_round_trip(&actor_file::<BigEndian>(true));
+    }
+
+    #[test]
+    fn keeps_padding_of_edited_wide_elements() {
+        let mut parsed = XacActorFile::from_bytes(&actor_file::<LittleEndian>(false)).unwrap();
+        let mesh = &mut parsed.nodes[2].visual_mesh;
+        mesh.sub_meshes[1].vertex_normals[0].y = -1.0;
+        let written = parsed.to_bytes().unwrap();
+        let reparsed = XacActorFile::from_bytes(&written).unwrap();
+        let mesh = &reparsed.nodes[2].visual_mesh;
+        assert_eq!(mesh.sub_meshes[1].vertex_normals[0].y, -1.0);
+        assert_eq!(mesh.sub_meshes[0].vertex_normals[0].y, 1.0);
+        let padding: Vec<u8> = mesh.vertex_attributes[1]
+            .data
+            .chunks(16)
+            .map(|element| element[12])
+            .collect();
+        assert_eq!(padding, vec![0xa0, 0xa1, 0xa2, 0xa3, 0xa4]);
+    }
+
+    #[test]
+    fn moves_unknown_chunks_to_the_end() {
+        let file = actor_file::<LittleEndian>(false);
+        let offsets = chunk_offsets::<LittleEndian>(&file);
+        let mut reordered = file[..8].to_vec();
+        chunk::<LittleEndian>(&mut reordered, 98, 1, b"first");
+        reordered.extend_from_slice(&file[8..]);
+
+        let parsed = XacActorFile::from_bytes(&reordered).unwrap();
+        assert_eq!(parsed.unknown_chunks[0].offset, 8);
+        let written = parsed.to_bytes().unwrap();
+        let written_types: Vec<i32> = chunk_offsets::<LittleEndian>(&written)
+            .iter()
+            .map(|(type_id, _)| *type_id)
+            .collect();
+        let mut expected_types: Vec<i32> = offsets.iter().map(|(type_id, _)| *type_id).collect();
+        expected_types.retain(|type_id| *type_id != 99);
+        expected_types.extend([98, 99]);
+        assert_eq!(written_types, expected_types);
+    }
+
+    #[test]
+    fn round_trip_morph_target_chunks_per_lod() {
+        let file = actor_file::<LittleEndian>(false);
+        let (_, unknown_start) = *chunk_offsets::<LittleEndian>(&file).last().unwrap();
+        let mut morph_targets = Vec::new();
+        for value in [1, 1] {
+            morph_targets.write_i32::<LittleEndian>(value).unwrap();
+        }
+        morph_targets.write_f32::<LittleEndian>(0.0).unwrap();
+        morph_targets.write_f32::<LittleEndian>(1.0).unwrap();
+        for value in [2, 0, 0, 0] {
+            morph_targets.write_i32::<LittleEndian>(value).unwrap();
+        }
+        string::<LittleEndian>(&mut morph_targets, "smile_lod1");
+        let mut two_lods = file[..unknown_start].to_vec();
+        chunk::<LittleEndian>(&mut two_lods, 12, 1, &morph_targets);
+        two_lods.extend_from_slice(&file[unknown_start..]);
+
+        let parsed = XacActorFile::from_bytes(&two_lods).unwrap();
+        let chunks: Vec<(i32, Vec<usize>)> = parsed
+            .morph_target_chunks
+            .iter()
+            .map(|chunk| (chunk.lod_level, chunk.morph_target_ids.clone()))
+            .collect();
+        assert_eq!(chunks, vec![(0, vec![0]), (1, vec![1])]);
+        assert_eq!(parsed.morph_targets[1].lod_level, 2);
+
+        let written = parsed.to_bytes().unwrap();
+        assert_eq!(written, two_lods);
+    }
+
+    #[test]
+    fn writes_edited_morph_target_offsets() {
+        let mut parsed = XacActorFile::from_bytes(&actor_file::<LittleEndian>(false)).unwrap();
+        let deformation = &mut parsed.morph_targets[0].deformation[0];
+        deformation.position_offsets[0].x = 0.5;
+        deformation.normal_offsets[1].y = 1.0;
+        let unedited_tangents = deformation.tangent_offsets.clone();
+
+        let reparsed = XacActorFile::from_bytes(&parsed.to_bytes().unwrap()).unwrap();
+        let deformation = &reparsed.morph_targets[0].deformation[0];
+        assert!((deformation.position_offsets[0].x - 0.5).abs() < 1e-4);
+        assert_eq!(deformation.position_offsets[1].x, 1.0);
+        assert!((deformation.normal_offsets[1].y - 1.0).abs() < 0.01);
+        assert_eq!(
+            serde_json::to_value(&deformation.tangent_offsets).unwrap(),
+            serde_json::to_value(&unedited_tangents).unwrap()
+        );
+    }
+
+    #[test]
+    fn writes_compressed_offsets_without_decoded_ones() {
+        let file = actor_file::<LittleEndian>(false);
+        let mut parsed = XacActorFile::from_bytes(&file).unwrap();
+        let deformation = &mut parsed.morph_targets[0].deformation[0];
+        deformation.position_offsets.clear();
+        deformation.normal_offsets.clear();
+        deformation.tangent_offsets.clear();
+        assert_eq!(parsed.to_bytes().unwrap(), file);
+    }
+}
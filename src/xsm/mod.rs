@@ -1,3 +1,11 @@
+pub(crate) mod xsm_bind;
+pub(crate) mod xsm_bvh;
 pub(crate) mod xsm_enums;
+pub(crate) mod xsm_error;
 pub(crate) mod xsm_parser;
+pub(crate) mod xsm_sampler;
 pub(crate) mod xsm_structs;
+#[cfg(test)]
+pub(crate) mod xsm_test_util;
+pub(crate) mod xsm_util;
+pub(crate) mod xsm_writer;
@@ -0,0 +1,110 @@
+#![allow(dead_code)]
+use std::collections::HashMap;
+
+use crate::xac::xac_struct::XacActorFile;
+use crate::xsm::xsm_structs::{Xsm, XsmBinding};
+
+impl Xsm {
+    // Names are compared exactly. When an actor has several nodes with the
+    // same name, the first one is used, the same as for duplicate submotions.
+    pub fn bind(&self, actor: &XacActorFile) -> XsmBinding {
+        let mut node_ids: HashMap<&str, usize> = HashMap::new();
+        for (node_id, node) in actor.nodes.iter().enumerate() {
+            node_ids.entry(node.name.as_str()).or_insert(node_id);
+        }
+
+        let mut node_submotions: Vec<Option<usize>> = vec![None; actor.nodes.len()];
+        let mut submotion_nodes: Vec<Option<usize>> = Vec::new();
+        let mut unmatched_submotions: Vec<String> = Vec::new();
+        for (submotion_id, submotion) in self.bone_animation.skeletal_submotion.iter().enumerate() {
+            let node_id = node_ids.get(submotion.node_name.as_str()).copied();
+            match node_id {
+                Some(node_id) => {
+                    if node_submotions[node_id].is_none() {
+                        node_submotions[node_id] = Some(submotion_id);
+                    }
+                }
+                None => unmatched_submotions.push(submotion.node_name.clone()),
+            }
+            submotion_nodes.push(node_id);
+        }
+
+        let unmatched_nodes = actor
+            .nodes
+            .iter()
+            .zip(node_submotions.iter())
+            .filter(|(_, submotion_id)| submotion_id.is_none())
+            .map(|(node, _)| node.name.clone())
+            .collect();
+
+        XsmBinding {
+            node_submotions,
+            submotion_nodes,
+            unmatched_submotions,
+            unmatched_nodes,
+        }
+    }
+}
+
+impl XsmBinding {
+    // A motion fits an actor when every animated bone exists on the actor.
+    // Actor nodes without a submotion simply keep their bind pose.
+    pub fn fits(&self) -> bool {
+        self.unmatched_submotions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::xac::xac_test_util::rigged_actor;
+    use crate::xsm::xsm_structs::Xsm;
+    use crate::xsm::xsm_test_util::motion_file;
+    use byteorder::LittleEndian;
+
+    fn motion() -> Xsm {
+        Xsm::from_bytes(&motion_file::<LittleEndian>(false)).unwrap()
+    }
+
+    #[test]
+    fn binds_submotions_to_nodes_by_name() {
+        let binding = motion().bind(&rigged_actor());
+        assert_eq!(binding.node_submotions, vec![Some(0), Some(1), None]);
+        assert_eq!(binding.submotion_nodes, vec![Some(0), Some(1)]);
+        assert!(binding.unmatched_submotions.is_empty());
+        assert_eq!(binding.unmatched_nodes, vec!["grand".to_string()]);
+        assert!(binding.fits());
+    }
+
+    #[test]
+    fn uses_the_first_of_duplicate_names() {
+        let mut actor = rigged_actor();
+        actor.nodes[2].name = "child".to_string();
+        let mut xsm = motion();
+        xsm.bone_animation.skeletal_submotion[0].node_name = "child".to_string();
+
+        let binding = xsm.bind(&actor);
+        assert_eq!(binding.node_submotions, vec![None, Some(0), None]);
+        assert_eq!(binding.submotion_nodes, vec![Some(1), Some(1)]);
+        assert_eq!(
+            binding.unmatched_nodes,
+            vec!["root".to_string(), "child".to_string()]
+        );
+        assert!(binding.fits());
+    }
+
+    #[test]
+    fn does_not_fit_when_a_submotion_has_no_node() {
+        let mut xsm = motion();
+        xsm.bone_animation.skeletal_submotion[1].node_name = "tail".to_string();
+
+        let binding = xsm.bind(&rigged_actor());
+        assert_eq!(binding.node_submotions, vec![Some(0), None, None]);
+        assert_eq!(binding.submotion_nodes, vec![Some(0), None]);
+        assert_eq!(binding.unmatched_submotions, vec!["tail".to_string()]);
+        assert_eq!(
+            binding.unmatched_nodes,
+            vec!["child".to_string(), "grand".to_string()]
+        );
+        assert!(!binding.fits());
+    }
+}
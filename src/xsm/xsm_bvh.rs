@@ -0,0 +1,447 @@
+#![allow(dead_code)]
+use crate::xac::xac_struct::{XacActorFile, XacQuaternion};
+use crate::xsm::xsm_structs::Xsm;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+
+impl Xsm {
+    pub fn export_bvh(&self, path: &str, actor: &XacActorFile) -> io::Result<()> {
+        let mut bvh_file = BufWriter::new(File::create(path)?);
+        self.write_bvh(&mut bvh_file, actor)?;
+        bvh_file.flush()
+    }
+
+    // The hierarchy is the actor's node tree with bind pose translations as
+    // joint offsets. Root joints have a zero offset and absolute position
+    // channels, the other joints only rotate, as most BVH tools expect.
+    // Rotations are written as ZXY Euler angles in degrees. BVH has no
+    // scale, so scale tracks are not exported. The motion is resampled at
+    // the metadata frame rate, and longer motions than BVH_MAX_FRAMES are
+    // rejected. A BVH file has a single root, so actors with several root
+    // nodes get an extra root joint named after the actor that stays at the
+    // origin, with the root nodes as its children.
+    pub fn write_bvh<W: Write>(&self, bvh: &mut W, actor: &XacActorFile) -> io::Result<()> {
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); actor.nodes.len()];
+        let mut is_root: Vec<bool> = vec![false; actor.nodes.len()];
+        let mut roots: Vec<usize> = Vec::new();
+        for (node_id, node) in actor.nodes.iter().enumerate() {
+            match usize::try_from(node.parent_node_id) {
+                Ok(parent_id) if parent_id < actor.nodes.len() => children[parent_id].push(node_id),
+                _ => {
+                    is_root[node_id] = true;
+                    roots.push(node_id);
+                }
+            }
+        }
+
+        let sampler = self.sampler();
+        let binding = self.bind(actor);
+        let num_frames = sampler.num_frames();
+        if num_frames > BVH_MAX_FRAMES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Motion needs {} frames, more than the {} a BVH export allows",
+                    num_frames, BVH_MAX_FRAMES
+                ),
+            ));
+        }
+
+        writeln!(bvh, "HIERARCHY")?;
+        let mut joints: Vec<usize> = Vec::new();
+        let extra_root = roots.len() > 1;
+        if extra_root {
+            writeln!(bvh, "ROOT {}", bvh_name(&actor.actor_name))?;
+            writeln!(bvh, "{{")?;
+            writeln!(bvh, "\tOFFSET 0.000000 0.000000 0.000000")?;
+            writeln!(bvh, "\t{}", BVH_ROOT_CHANNELS)?;
+        }
+        let depth = extra_root as usize;
+        for root in roots.iter() {
+            write_bvh_joints(bvh, actor, &children, &is_root, *root, depth, &mut joints)?;
+        }
+        if extra_root {
+            writeln!(bvh, "}}")?;
+        }
+
+        writeln!(bvh, "MOTION")?;
+        writeln!(bvh, "Frames: {}", num_frames)?;
+        writeln!(bvh, "Frame Time: {:.6}", 1.0 / sampler.fps as f32)?;
+
+        let mut previous_angles: Vec<Option<[f32; 3]>> = vec![None; actor.nodes.len()];
+        for frame in 0..num_frames {
+            let pose = actor.motion_pose(&sampler, &binding, sampler.frame_time(frame));
+            let mut values: Vec<String> = Vec::new();
+            if extra_root {
+                values.extend((0..6).map(|_| bvh_value(0.0)));
+            }
+            for node_id in joints.iter() {
+                let transform = &pose[*node_id];
+                if is_root[*node_id] {
+                    values.push(bvh_value(transform.position.x));
+                    values.push(bvh_value(transform.position.y));
+                    values.push(bvh_value(transform.position.z));
+                }
+                let angles = bvh_euler_zxy(&transform.rotation, previous_angles[*node_id]);
+                previous_angles[*node_id] = Some(angles);
+                for angle in angles.iter() {
+                    values.push(bvh_value(*angle));
+                }
+            }
+            writeln!(bvh, "{}", values.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+// About an hour of motion at 240 frames per second.
+const BVH_MAX_FRAMES: usize = 1_000_000;
+
+const BVH_ROOT_CHANNELS: &str =
+    "CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation";
+
+// Writes the joint tree below `root` depth first. An explicit stack keeps
+// deep hierarchies from overflowing the call stack, with a closing entry
+// per joint that writes its `}` after all of its children. `joints`
+// collects node ids in the order their channels appear in frames. Root
+// nodes keep their position channels when they are written as joints of
+// the extra root.
+fn write_bvh_joints<W: Write>(
+    bvh: &mut W,
+    actor: &XacActorFile,
+    children: &[Vec<usize>],
+    is_root: &[bool],
+    root: usize,
+    root_depth: usize,
+    joints: &mut Vec<usize>,
+) -> io::Result<()> {
+    // (node id, depth, whether the joint is already open)
+    let mut stack: Vec<(usize, usize, bool)> = vec![(root, root_depth, false)];
+    while let Some((node_id, depth, open)) = stack.pop() {
+        let indent = "\t".repeat(depth);
+        if open {
+            writeln!(bvh, "{}}}", indent)?;
+            continue;
+        }
+
+        let node = &actor.nodes[node_id];
+        joints.push(node_id);
+        let keyword = if depth == 0 { "ROOT" } else { "JOINT" };
+        writeln!(bvh, "{}{} {}", indent, keyword, bvh_name(&node.name))?;
+        writeln!(bvh, "{}{{", indent)?;
+        if is_root[node_id] {
+            writeln!(bvh, "{}\tOFFSET 0.000000 0.000000 0.000000", indent)?;
+            writeln!(bvh, "{}\t{}", indent, BVH_ROOT_CHANNELS)?;
+        } else {
+            writeln!(
+                bvh,
+                "{}\tOFFSET {:.6} {:.6} {:.6}",
+                indent, node.position.x, node.position.y, node.position.z
+            )?;
+            writeln!(bvh, "{}\tCHANNELS 3 Zrotation Xrotation Yrotation", indent)?;
+        }
+
+        if children[node_id].is_empty() {
+            writeln!(bvh, "{}\tEnd Site", indent)?;
+            writeln!(bvh, "{}\t{{", indent)?;
+            writeln!(bvh, "{}\t\tOFFSET 0.000000 0.000000 0.000000", indent)?;
+            writeln!(bvh, "{}\t}}", indent)?;
+        }
+        stack.push((node_id, depth, true));
+        // Pushed in reverse so the children are written in order.
+        for child_id in children[node_id].iter().rev() {
+            stack.push((*child_id, depth + 1, false));
+        }
+    }
+    Ok(())
+}
+
+// Decomposes the rotation into R = Rz * Rx * Ry for column vectors and
+// returns [z, x, y] in degrees. Angles are moved by whole turns to stay
+// close to the previous frame, so tools do not see 360 degree jumps.
+fn bvh_euler_zxy(rotation: &XacQuaternion, previous: Option<[f32; 3]>) -> [f32; 3] {
+    let length = (rotation.x * rotation.x
+        + rotation.y * rotation.y
+        + rotation.z * rotation.z
+        + rotation.w * rotation.w)
+        .sqrt();
+    let (x, y, z, w) = if length <= f32::EPSILON {
+        (0.0, 0.0, 0.0, 1.0)
+    } else {
+        (
+            rotation.x / length,
+            rotation.y / length,
+            rotation.z / length,
+            rotation.w / length,
+        )
+    };
+    let m00 = 1.0 - 2.0 * (y * y + z * z);
+    let m01 = 2.0 * (x * y - z * w);
+    let m10 = 2.0 * (x * y + z * w);
+    let m11 = 1.0 - 2.0 * (x * x + z * z);
+    let m20 = 2.0 * (x * z - y * w);
+    let m21 = 2.0 * (y * z + x * w);
+    let m22 = 1.0 - 2.0 * (x * x + y * y);
+
+    let angle_x = m21.clamp(-1.0, 1.0).asin();
+    let (angle_z, angle_y) = if m21.abs() < 0.9999 {
+        (f32::atan2(-m01, m11), f32::atan2(-m20, m22))
+    } else {
+        // Gimbal lock, only the sum of z and y is defined.
+        (f32::atan2(m10, m00), 0.0)
+    };
+
+    let mut angles = [
+        angle_z.to_degrees(),
+        angle_x.to_degrees(),
+        angle_y.to_degrees(),
+    ];
+    if let Some(previous) = previous {
+        for (angle, previous) in angles.iter_mut().zip(previous.iter()) {
+            *angle += ((previous - *angle) / 360.0).round() * 360.0;
+        }
+    }
+    angles
+}
+
+// Values that round to zero are written without a minus sign.
+fn bvh_value(value: f32) -> String {
+    let text = format!("{:.6}", value);
+    if text == "-0.000000" {
+        "0.000000".to_string()
+    } else {
+        text
+    }
+}
+
+// BVH names are whitespace separated, so names must not contain any.
+fn bvh_name(name: &str) -> String {
+    let name = name.split_whitespace().collect::<Vec<&str>>().join("_");
+    if name.is_empty() {
+        "joint".to_string()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xac::xac_test_util::rigged_actor;
+    use crate::xsm::xsm_test_util::motion_file;
+    use byteorder::LittleEndian;
+
+    fn write(actor: &XacActorFile) -> String {
+        let xsm = Xsm::from_bytes(&motion_file::<LittleEndian>(false)).unwrap();
+        let mut bvh = Vec::new();
+        xsm.write_bvh(&mut bvh, actor).unwrap();
+        String::from_utf8(bvh).unwrap()
+    }
+
+    fn frame_values(bvh: &str) -> Vec<Vec<f32>> {
+        let motion = bvh.split("Frame Time: ").nth(1).unwrap();
+        motion
+            .lines()
+            .skip(1)
+            .map(|line| {
+                line.split(' ')
+                    .map(|value| value.parse::<f32>().unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        for (actual_value, expected_value) in actual.iter().zip(expected.iter()) {
+            assert!(
+                (actual_value - expected_value).abs() < 1e-3,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    // Rotation about a single axis, `axis` 0, 1 and 2 being x, y and z.
+    fn axis_rotation(axis: usize, degrees: f32) -> XacQuaternion {
+        let (sin, cos) = (degrees.to_radians() / 2.0).sin_cos();
+        let mut xyz = [0.0; 3];
+        xyz[axis] = sin;
+        XacQuaternion {
+            x: xyz[0],
+            y: xyz[1],
+            z: xyz[2],
+            w: cos,
+        }
+    }
+
+    fn multiply(a: &XacQuaternion, b: &XacQuaternion) -> XacQuaternion {
+        XacQuaternion {
+            x: a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            y: a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            z: a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+            w: a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+        }
+    }
+
+    fn zxy_rotation(z: f32, x: f32, y: f32) -> XacQuaternion {
+        multiply(
+            &multiply(&axis_rotation(2, z), &axis_rotation(0, x)),
+            &axis_rotation(1, y),
+        )
+    }
+
+    #[test]
+    fn writes_hierarchy_and_frames() {
+        let bvh = write(&rigged_actor());
+        let hierarchy = "HIERARCHY\n\
+            ROOT root\n\
+            {\n\
+            \tOFFSET 0.000000 0.000000 0.000000\n\
+            \tCHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation\n\
+            \tJOINT child\n\
+            \t{\n\
+            \t\tOFFSET 0.000000 2.000000 0.000000\n\
+            \t\tCHANNELS 3 Zrotation Xrotation Yrotation\n\
+            \t\tJOINT grand\n\
+            \t\t{\n\
+            \t\t\tOFFSET 0.000000 0.000000 3.000000\n\
+            \t\t\tCHANNELS 3 Zrotation Xrotation Yrotation\n\
+            \t\t\tEnd Site\n\
+            \t\t\t{\n\
+            \t\t\t\tOFFSET 0.000000 0.000000 0.000000\n\
+            \t\t\t}\n\
+            \t\t}\n\
+            \t}\n\
+            }\n\
+            MOTION\n\
+            Frames: 31\n\
+            Frame Time: 0.033333\n";
+        assert!(bvh.starts_with(hierarchy), "{}", bvh);
+
+        let frames = frame_values(&bvh);
+        assert_eq!(frames.len(), 31);
+        assert!(frames.iter().all(|frame| frame.len() == 12));
+        // Root position and ZXY angles, then the child's and grand's angles.
+        // The child is turned 90 degrees about x by its pose, grand is not
+        // animated.
+        assert_close(
+            &frames[0],
+            &[0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 90.0, 0.0, 0.0, 0.0, 0.0],
+        );
+        assert_close(&frames[30][0..6], &[2.0, 0.0, 0.0, 90.0, 0.0, 0.0]);
+        assert_close(&frames[15][0..4], &[1.0, 0.0, 0.0, 45.0]);
+    }
+
+    #[test]
+    fn wraps_several_roots_in_one_root() {
+        let mut actor = rigged_actor();
+        actor.nodes[2].parent_node_id = -1;
+        let bvh = write(&actor);
+        assert_eq!(bvh.matches("ROOT").count(), 1);
+        assert!(bvh.contains(
+            "ROOT actor\n\
+            {\n\
+            \tOFFSET 0.000000 0.000000 0.000000\n\
+            \tCHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation\n\
+            \tJOINT root\n\
+            \t{\n\
+            \t\tOFFSET 0.000000 0.000000 0.000000\n\
+            \t\tCHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation\n"
+        ));
+        // The second root "grand" also keeps its position channels.
+        assert!(bvh.contains(
+            "\tJOINT grand\n\
+            \t{\n\
+            \t\tOFFSET 0.000000 0.000000 0.000000\n\
+            \t\tCHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation\n\
+            \t\tEnd Site\n"
+        ));
+        assert!(bvh.contains("\t}\n}\nMOTION\n"));
+
+        let frames = frame_values(&bvh);
+        assert!(frames.iter().all(|frame| frame.len() == 21));
+        // The extra root stays at the origin and grand at its bind pose.
+        assert_close(&frames[30][0..6], &[0.0; 6]);
+        assert_close(&frames[30][6..12], &[2.0, 0.0, 0.0, 90.0, 0.0, 0.0]);
+        assert_close(&frames[30][15..21], &[0.0, 0.0, 3.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn decomposes_rotations_in_zxy_order() {
+        for angles in [
+            [30.0, 20.0, -40.0],
+            [-120.0, -60.0, 170.0],
+            [0.0, 45.0, 0.0],
+            [90.0, 0.0, 0.0],
+        ] {
+            let rotation = zxy_rotation(angles[0], angles[1], angles[2]);
+            assert_close(&bvh_euler_zxy(&rotation, None), &angles);
+        }
+        // Unnormalized quaternions are normalized first.
+        let mut rotation = zxy_rotation(30.0, 20.0, -40.0);
+        rotation.x *= 3.0;
+        rotation.y *= 3.0;
+        rotation.z *= 3.0;
+        rotation.w *= 3.0;
+        assert_close(&bvh_euler_zxy(&rotation, None), &[30.0, 20.0, -40.0]);
+    }
+
+    #[test]
+    fn handles_gimbal_lock() {
+        // With x at 90 degrees only z + y is defined and all of it goes to z.
+        let angles = bvh_euler_zxy(&zxy_rotation(30.0, 90.0, 20.0), None);
+        assert_close(&angles, &[50.0, 90.0, 0.0]);
+    }
+
+    #[test]
+    fn keeps_angles_close_to_the_previous_frame() {
+        let rotation = zxy_rotation(-10.0, 0.0, 0.0);
+        assert_close(&bvh_euler_zxy(&rotation, None), &[-10.0, 0.0, 0.0]);
+        assert_close(
+            &bvh_euler_zxy(&rotation, Some([350.0, 0.0, 0.0])),
+            &[350.0, 0.0, 0.0],
+        );
+        assert_close(
+            &bvh_euler_zxy(&rotation, Some([-730.0, 720.0, 0.0])),
+            &[-730.0, 720.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn writes_deep_hierarchy_without_recursion() {
+        let mut actor = rigged_actor();
+        let num_nodes = 2_000;
+        let leaf = actor.nodes[2].clone();
+        for node_id in actor.nodes.len()..num_nodes {
+            let mut node = leaf.clone();
+            node.parent_node_id = node_id as i32 - 1;
+            actor.nodes.push(node);
+        }
+        // A small stack that a call per joint would overflow.
+        let bvh = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || write(&actor))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(bvh.matches("JOINT").count(), num_nodes - 1);
+        assert_eq!(bvh.matches("End Site").count(), 1);
+        let closing = "\t".repeat(num_nodes - 1) + "}\n";
+        assert!(bvh.contains(&closing));
+        assert!(bvh.contains("\t}\n}\nMOTION\n"));
+        let frames = frame_values(&bvh);
+        assert!(frames.iter().all(|frame| frame.len() == 3 + 3 * num_nodes));
+    }
+
+    #[test]
+    fn rejects_motions_with_too_many_frames() {
+        let mut xsm = Xsm::from_bytes(&motion_file::<LittleEndian>(false)).unwrap();
+        xsm.metadata.fps = i32::MAX;
+        let mut bvh = Vec::new();
+        let err = xsm.write_bvh(&mut bvh, &rigged_actor()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(bvh.is_empty());
+    }
+}
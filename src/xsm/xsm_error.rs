@@ -0,0 +1,140 @@
+#![allow(dead_code)]
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum XsmError {
+    Io {
+        chunk_type: Option<i32>,
+        offset: u64,
+        source: io::Error,
+    },
+    InvalidMagic {
+        chunk_type: Option<i32>,
+        offset: u64,
+        magic: [u8; 4],
+    },
+    UnsupportedVersion {
+        chunk_type: Option<i32>,
+        offset: u64,
+        major_version: u8,
+        minor_version: u8,
+    },
+    TruncatedChunk {
+        chunk_type: Option<i32>,
+        offset: u64,
+    },
+    InconsistentCount {
+        chunk_type: Option<i32>,
+        offset: u64,
+        message: String,
+    },
+}
+
+impl XsmError {
+    pub(crate) fn inconsistent(message: impl Into<String>) -> XsmError {
+        XsmError::InconsistentCount {
+            chunk_type: None,
+            offset: 0,
+            message: message.into(),
+        }
+    }
+
+    pub fn chunk_type(&self) -> Option<i32> {
+        match self {
+            XsmError::Io { chunk_type, .. }
+            | XsmError::InvalidMagic { chunk_type, .. }
+            | XsmError::UnsupportedVersion { chunk_type, .. }
+            | XsmError::TruncatedChunk { chunk_type, .. }
+            | XsmError::InconsistentCount { chunk_type, .. } => *chunk_type,
+        }
+    }
+
+    pub fn offset(&self) -> u64 {
+        match self {
+            XsmError::Io { offset, .. }
+            | XsmError::InvalidMagic { offset, .. }
+            | XsmError::UnsupportedVersion { offset, .. }
+            | XsmError::TruncatedChunk { offset, .. }
+            | XsmError::InconsistentCount { offset, .. } => *offset,
+        }
+    }
+
+    // Errors raised inside a chunk reader do not know where they happened,
+    // the chunk loop fills in the chunk type and stream position afterwards.
+    pub(crate) fn locate(mut self, chunk: Option<i32>, position: u64) -> XsmError {
+        match &mut self {
+            XsmError::Io {
+                chunk_type, offset, ..
+            }
+            | XsmError::InvalidMagic {
+                chunk_type, offset, ..
+            }
+            | XsmError::UnsupportedVersion {
+                chunk_type, offset, ..
+            }
+            | XsmError::TruncatedChunk { chunk_type, offset }
+            | XsmError::InconsistentCount {
+                chunk_type, offset, ..
+            } => {
+                *chunk_type = chunk;
+                *offset = position;
+            }
+        }
+        self
+    }
+}
+
+impl From<io::Error> for XsmError {
+    fn from(source: io::Error) -> Self {
+        if source.kind() == io::ErrorKind::UnexpectedEof {
+            XsmError::TruncatedChunk {
+                chunk_type: None,
+                offset: 0,
+            }
+        } else {
+            XsmError::Io {
+                chunk_type: None,
+                offset: 0,
+                source,
+            }
+        }
+    }
+}
+
+impl fmt::Display for XsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XsmError::Io { source, .. } => write!(f, "I/O error: {}", source)?,
+            XsmError::InvalidMagic { magic, .. } => write!(
+                f,
+                "Not an XSM file: invalid header magic {:?}",
+                String::from_utf8_lossy(magic)
+            )?,
+            XsmError::UnsupportedVersion {
+                major_version,
+                minor_version,
+                ..
+            } => write!(
+                f,
+                "Unsupported .xsm version {}.{}",
+                major_version, minor_version
+            )?,
+            XsmError::TruncatedChunk { .. } => write!(f, "Unexpected end of data")?,
+            XsmError::InconsistentCount { message, .. } => write!(f, "{}", message)?,
+        }
+        match self.chunk_type() {
+            Some(chunk_type) => write!(f, " (chunk {} at byte {})", chunk_type, self.offset()),
+            None => write!(f, " (at byte {})", self.offset()),
+        }
+    }
+}
+
+impl std::error::Error for XsmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            XsmError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
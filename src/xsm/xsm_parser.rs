@@ -1,177 +1,411 @@
 #![allow(dead_code)]
 use std::fs::File;
-use std::io::{Read, Seek, SeekFrom};
+use std::io;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
 
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
 
 use crate::xsm::xsm_enums::XsmChunkType::{XsmBoneAnimationId, XsmMetadataId};
+use crate::xsm::xsm_error::XsmError;
 use crate::xsm::xsm_structs::{
     Xsm, XsmBoneAnimation, XsmChunk, XsmHeader, XsmMetadata, XsmPosKey, XsmQuaternion16, XsmRotKey,
-    XsmScaleKey, XsmScaleRotKey, XsmSubMotion, XsmVec3d,
+    XsmScaleKey, XsmScaleRotKey, XsmSubMotion, XsmUnknownChunk, XsmVec3d,
 };
 
-pub fn xsmparse(path: &str) -> Xsm {
-    let mut xsm_file = File::open(path).expect("Cannot open xsm file!");
-    let mut xsm_new = Xsm {
-        header: XsmHeader {
-            magic: "".to_string(),
-            major_version: 0,
-            minor_version: 0,
-            big_endian: false,
-        },
-        metadata: XsmMetadata {
-            unused: 0.0,
-            max_acceptable_error: 0.0,
-            fps: 0,
-            exporter_major_version: 0,
-            exporter_minor_version: 0,
-            source_app: "".to_string(),
-            original_filename: "".to_string(),
-            export_date: "".to_string(),
-            motion_name: "".to_string(),
-        },
-        bone_animation: XsmBoneAnimation {
-            num_submotion: 0,
-            skeletal_submotion: vec![],
-        },
-    };
-    read_header(&mut xsm_file, &mut xsm_new);
-    if xsm_new.header.magic == "XSM ".to_string() {
-        read_chunk(&mut xsm_file, &mut xsm_new);
-    } else {
-        panic!("Wrong file header!");
-    }
-    xsm_new
+// Latest key time the parser accepts, in seconds.
+const XSM_MAX_KEY_TIME: f32 = 86400.0;
+
+pub fn xsmparse(path: &str) -> Result<Xsm, XsmError> {
+    let xsm_file = File::open(path)?;
+    Xsm::from_reader(BufReader::new(xsm_file))
+}
+
+impl Xsm {
+    pub fn from_reader<R: Read + Seek>(mut reader: R) -> Result<Xsm, XsmError> {
+        let mut xsm_new = Xsm {
+            header: XsmHeader {
+                magic: "".to_string(),
+                major_version: 0,
+                minor_version: 0,
+                big_endian: false,
+            },
+            metadata: XsmMetadata {
+                version: 1,
+                unused: 0.0,
+                max_acceptable_error: 0.0,
+                fps: 0,
+                exporter_major_version: 0,
+                exporter_minor_version: 0,
+                source_app: "".to_string(),
+                original_filename: "".to_string(),
+                export_date: "".to_string(),
+                motion_name: "".to_string(),
+            },
+            bone_animation: XsmBoneAnimation {
+                version: 1,
+                num_submotion: 0,
+                skeletal_submotion: vec![],
+            },
+            unknown_chunks: vec![],
+        };
+        read_header(&mut reader, &mut xsm_new)?;
+        if xsm_new.header.big_endian {
+            read_chunk::<_, BigEndian>(&mut reader, &mut xsm_new)?;
+        } else {
+            read_chunk::<_, LittleEndian>(&mut reader, &mut xsm_new)?;
+        }
+        Ok(xsm_new)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Xsm, XsmError> {
+        Xsm::from_reader(Cursor::new(bytes))
+    }
 }
-fn xsm_read_string(file: &mut File) -> String {
+
+fn xsm_read_string<R: Read, E: ByteOrder>(file: &mut R) -> io::Result<String> {
     let mut text = String::new();
-    let length = file.read_i32::<LittleEndian>().unwrap();
+    let length = file.read_i32::<E>()?;
     for _ in 0..length {
-        let character = file.read_u8().unwrap();
+        let character = file.read_u8()?;
         text.push(character as char);
     }
-    text
+    Ok(text)
 }
 
-fn xsm_read_quaternion16(file: &mut File) -> XsmQuaternion16 {
-    let quat = XsmQuaternion16 {
-        x: file.read_i16::<LittleEndian>().unwrap(),
-        y: file.read_i16::<LittleEndian>().unwrap(),
-        z: file.read_i16::<LittleEndian>().unwrap(),
-        w: file.read_i16::<LittleEndian>().unwrap(),
-    };
-    quat
+fn xsm_read_quaternion16<R: Read, E: ByteOrder>(file: &mut R) -> io::Result<XsmQuaternion16> {
+    Ok(XsmQuaternion16 {
+        x: file.read_i16::<E>()?,
+        y: file.read_i16::<E>()?,
+        z: file.read_i16::<E>()?,
+        w: file.read_i16::<E>()?,
+    })
 }
 
-fn xsm_read_vec3d(file: &mut File) -> XsmVec3d {
-    let vec3d = XsmVec3d {
-        x: file.read_f32::<LittleEndian>().unwrap(),
-        y: file.read_f32::<LittleEndian>().unwrap(),
-        z: file.read_f32::<LittleEndian>().unwrap(),
-    };
-    vec3d
+fn xsm_read_vec3d<R: Read, E: ByteOrder>(file: &mut R) -> io::Result<XsmVec3d> {
+    Ok(XsmVec3d {
+        x: file.read_f32::<E>()?,
+        y: file.read_f32::<E>()?,
+        z: file.read_f32::<E>()?,
+    })
 }
 
-fn read_header<'a>(file: &'a mut File, xsm: &'a mut Xsm) -> &'a mut Xsm {
+fn read_header<R: Read>(file: &mut R, xsm: &mut Xsm) -> Result<(), XsmError> {
     let mut magic = [0; 4];
-    file.read_exact(&mut magic).unwrap();
-    xsm.header.magic = std::str::from_utf8(&magic).unwrap().to_string();
-    xsm.header.major_version = file.read_u8().unwrap();
-    xsm.header.minor_version = file.read_u8().unwrap();
-    xsm.header.big_endian = file.read_u8().unwrap() != 0;
-    file.read_u8().unwrap(); // Padding
-    xsm
+    file.read_exact(&mut magic)?;
+    if &magic != b"XSM " {
+        return Err(XsmError::InvalidMagic {
+            chunk_type: None,
+            offset: 0,
+            magic,
+        });
+    }
+    xsm.header.magic = String::from_utf8_lossy(&magic).to_string();
+    xsm.header.major_version = file.read_u8()?;
+    xsm.header.minor_version = file.read_u8()?;
+    if xsm.header.major_version != 1 {
+        return Err(XsmError::UnsupportedVersion {
+            chunk_type: None,
+            offset: 4,
+            major_version: xsm.header.major_version,
+            minor_version: xsm.header.minor_version,
+        });
+    }
+    xsm.header.big_endian = file.read_u8()? != 0;
+    file.read_u8()?; // Padding
+    Ok(())
 }
 
-fn read_chunk<'a>(file: &'a mut File, xsm: &'a mut Xsm) -> &'a mut Xsm {
-    while file.stream_position().unwrap() < file.metadata().unwrap().len() {
-        let chunk = XsmChunk {
-            chunk_type: file.read_i32::<LittleEndian>().unwrap(),
-            length: file.read_i32::<LittleEndian>().unwrap(),
-            version: file.read_i32::<LittleEndian>().unwrap(),
-        };
-        let position = file.stream_position().unwrap();
-        if chunk.chunk_type == XsmMetadataId as i32 {
-            read_metadata(file, xsm);
+fn read_chunk<R: Read + Seek, E: ByteOrder>(file: &mut R, xsm: &mut Xsm) -> Result<(), XsmError> {
+    let start = file.stream_position()?;
+    let file_length = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(start))?;
+    while file.stream_position()? < file_length {
+        let chunk_start = file.stream_position()?;
+        let chunk = read_chunk_header::<_, E>(file)
+            .map_err(|err| XsmError::from(err).locate(None, chunk_start))?;
+        let position = file.stream_position()?;
+        if chunk.length < 0 || position + chunk.length as u64 > file_length {
+            return Err(XsmError::TruncatedChunk {
+                chunk_type: Some(chunk.chunk_type),
+                offset: chunk_start,
+            });
         }
-        if chunk.chunk_type == XsmBoneAnimationId as i32 {
-            read_bone_animation(file, xsm);
+
+        let result = if chunk.chunk_type == XsmMetadataId as i32 {
+            xsm.metadata.version = chunk.version;
+            read_metadata::<_, E>(file, xsm)
+        } else if chunk.chunk_type == XsmBoneAnimationId as i32 {
+            xsm.bone_animation.version = chunk.version;
+            read_bone_animation::<_, E>(file, xsm)
+        } else {
+            read_unknown_chunk(file, xsm, &chunk, chunk_start)
+        };
+        if let Err(err) = result {
+            let failed_at = file.stream_position().unwrap_or(position);
+            return Err(err.locate(Some(chunk.chunk_type), failed_at));
         }
-        file.seek(SeekFrom::Start(position + chunk.length as u64))
-            .unwrap();
+
+        file.seek(SeekFrom::Start(position + chunk.length as u64))?;
     }
-    xsm
+    Ok(())
+}
+
+fn read_chunk_header<R: Read, E: ByteOrder>(file: &mut R) -> io::Result<XsmChunk> {
+    Ok(XsmChunk {
+        chunk_type: file.read_i32::<E>()?,
+        length: file.read_i32::<E>()?,
+        version: file.read_i32::<E>()?,
+    })
+}
+
+fn read_unknown_chunk<R: Read>(
+    file: &mut R,
+    xsm: &mut Xsm,
+    chunk: &XsmChunk,
+    chunk_start: u64,
+) -> Result<(), XsmError> {
+    let mut data = vec![0; chunk.length as usize];
+    file.read_exact(&mut data)?;
+    xsm.unknown_chunks.push(XsmUnknownChunk {
+        chunk_type: chunk.chunk_type,
+        version: chunk.version,
+        offset: chunk_start,
+        data,
+    });
+    Ok(())
 }
 
-fn read_metadata<'a>(file: &'a mut File, xsm: &'a mut Xsm) -> &'a mut Xsm {
-    xsm.metadata.unused = file.read_f32::<LittleEndian>().unwrap();
-    xsm.metadata.max_acceptable_error = file.read_f32::<LittleEndian>().unwrap();
-    xsm.metadata.fps = file.read_i32::<LittleEndian>().unwrap();
-    xsm.metadata.exporter_major_version = file.read_u8().unwrap();
-    xsm.metadata.exporter_minor_version = file.read_u8().unwrap();
-    file.read_u8().unwrap(); //Padding
-    file.read_u8().unwrap(); //Padding
-    xsm.metadata.source_app = xsm_read_string(file);
-    xsm.metadata.original_filename = xsm_read_string(file);
-    xsm.metadata.export_date = xsm_read_string(file);
-    xsm.metadata.motion_name = xsm_read_string(file);
-    xsm
+fn read_metadata<R: Read, E: ByteOrder>(file: &mut R, xsm: &mut Xsm) -> Result<(), XsmError> {
+    xsm.metadata.unused = file.read_f32::<E>()?;
+    xsm.metadata.max_acceptable_error = file.read_f32::<E>()?;
+    xsm.metadata.fps = file.read_i32::<E>()?;
+    xsm.metadata.exporter_major_version = file.read_u8()?;
+    xsm.metadata.exporter_minor_version = file.read_u8()?;
+    file.read_u8()?; //Padding
+    file.read_u8()?; //Padding
+    xsm.metadata.source_app = xsm_read_string::<_, E>(file)?;
+    xsm.metadata.original_filename = xsm_read_string::<_, E>(file)?;
+    xsm.metadata.export_date = xsm_read_string::<_, E>(file)?;
+    xsm.metadata.motion_name = xsm_read_string::<_, E>(file)?;
+    Ok(())
 }
 
-fn read_bone_animation<'a>(file: &'a mut File, xsm: &'a mut Xsm) -> &'a mut Xsm {
-    xsm.bone_animation.num_submotion = file.read_i32::<LittleEndian>().unwrap();
+fn read_bone_animation<R: Read, E: ByteOrder>(file: &mut R, xsm: &mut Xsm) -> Result<(), XsmError> {
+    xsm.bone_animation.num_submotion = file.read_i32::<E>()?;
+    if xsm.bone_animation.num_submotion < 0 {
+        return Err(XsmError::inconsistent("Invalid number of submotions"));
+    }
     for _ in 0..xsm.bone_animation.num_submotion {
-        xsm.bone_animation.skeletal_submotion.push({
-            let mut submotion = XsmSubMotion {
-                pose_rot: xsm_read_quaternion16(file),
-                bind_pose_rot: xsm_read_quaternion16(file),
-                pose_scale_rot: xsm_read_quaternion16(file),
-                bind_pose_scale_rot: xsm_read_quaternion16(file),
-                pose_pos: xsm_read_vec3d(file),
-                pose_scale: xsm_read_vec3d(file),
-                bind_pose_pos: xsm_read_vec3d(file),
-                bind_pose_scale_pos: xsm_read_vec3d(file),
-                num_pos_keys: file.read_i32::<LittleEndian>().unwrap(),
-                num_rot_keys: file.read_i32::<LittleEndian>().unwrap(),
-                num_scale_keys: file.read_i32::<LittleEndian>().unwrap(),
-                num_scale_rot_keys: file.read_i32::<LittleEndian>().unwrap(),
-                max_error: file.read_f32::<LittleEndian>().unwrap(),
-                node_name: xsm_read_string(file),
-                pos_key: vec![],
-                rot_key: vec![],
-                scale_key: vec![],
-                scale_rot_key: vec![],
-            };
-
-            for _ in 0..submotion.num_pos_keys {
-                submotion.pos_key.push(XsmPosKey {
-                    pos: xsm_read_vec3d(file),
-                    time: file.read_f32::<LittleEndian>().unwrap(),
-                })
-            }
+        let mut submotion = XsmSubMotion {
+            pose_rot: xsm_read_quaternion16::<_, E>(file)?,
+            bind_pose_rot: xsm_read_quaternion16::<_, E>(file)?,
+            pose_scale_rot: xsm_read_quaternion16::<_, E>(file)?,
+            bind_pose_scale_rot: xsm_read_quaternion16::<_, E>(file)?,
+            pose_pos: xsm_read_vec3d::<_, E>(file)?,
+            pose_scale: xsm_read_vec3d::<_, E>(file)?,
+            bind_pose_pos: xsm_read_vec3d::<_, E>(file)?,
+            bind_pose_scale_pos: xsm_read_vec3d::<_, E>(file)?,
+            num_pos_keys: file.read_i32::<E>()?,
+            num_rot_keys: file.read_i32::<E>()?,
+            num_scale_keys: file.read_i32::<E>()?,
+            num_scale_rot_keys: file.read_i32::<E>()?,
+            max_error: file.read_f32::<E>()?,
+            node_name: xsm_read_string::<_, E>(file)?,
+            pos_key: vec![],
+            rot_key: vec![],
+            scale_key: vec![],
+            scale_rot_key: vec![],
+        };
+        if submotion.num_pos_keys < 0
+            || submotion.num_rot_keys < 0
+            || submotion.num_scale_keys < 0
+            || submotion.num_scale_rot_keys < 0
+        {
+            return Err(XsmError::inconsistent(format!(
+                "Invalid number of keys for submotion {}",
+                submotion.node_name
+            )));
+        }
 
-            for _ in 0..submotion.num_rot_keys {
-                submotion.rot_key.push(XsmRotKey {
-                    rot: xsm_read_quaternion16(file),
-                    time: file.read_f32::<LittleEndian>().unwrap(),
-                })
-            }
-            for _ in 0..submotion.num_scale_keys {
-                submotion.scale_key.push(XsmScaleKey {
-                    scale: xsm_read_vec3d(file),
-                    time: file.read_f32::<LittleEndian>().unwrap(),
-                })
+        for _ in 0..submotion.num_pos_keys {
+            submotion.pos_key.push(XsmPosKey {
+                pos: xsm_read_vec3d::<_, E>(file)?,
+                time: read_key_time::<_, E>(file, &submotion.node_name)?,
+            })
+        }
+
+        for _ in 0..submotion.num_rot_keys {
+            submotion.rot_key.push(XsmRotKey {
+                rot: xsm_read_quaternion16::<_, E>(file)?,
+                time: read_key_time::<_, E>(file, &submotion.node_name)?,
+            })
+        }
+        for _ in 0..submotion.num_scale_keys {
+            submotion.scale_key.push(XsmScaleKey {
+                scale: xsm_read_vec3d::<_, E>(file)?,
+                time: read_key_time::<_, E>(file, &submotion.node_name)?,
+            })
+        }
+
+        for _ in 0..submotion.num_scale_rot_keys {
+            submotion.scale_rot_key.push(XsmScaleRotKey {
+                rot: xsm_read_quaternion16::<_, E>(file)?,
+                time: read_key_time::<_, E>(file, &submotion.node_name)?,
+            })
+        }
+        xsm.bone_animation.skeletal_submotion.push(submotion);
+    }
+
+    Ok(())
+}
+
+// Key times are seconds from the start of the motion. Times that are not
+// finite or lie beyond a day are rejected, since samplers and exporters
+// step through the whole motion.
+fn read_key_time<R: Read, E: ByteOrder>(file: &mut R, node_name: &str) -> Result<f32, XsmError> {
+    let time = file.read_f32::<E>()?;
+    if !(0.0..=XSM_MAX_KEY_TIME).contains(&time) {
+        return Err(XsmError::inconsistent(format!(
+            "Invalid key time {} for submotion {}",
+            time, node_name
+        )));
+    }
+    Ok(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xac::xac_test_util::chunk;
+    use crate::xsm::xsm_test_util::motion_file;
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut file = motion_file::<LittleEndian>(false);
+        file[..4].copy_from_slice(b"XAC ");
+        match Xsm::from_bytes(&file) {
+            Err(XsmError::InvalidMagic {
+                offset: 0, magic, ..
+            }) => assert_eq!(&magic, b"XAC "),
+            other => panic!("expected invalid magic, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_major_version() {
+        let mut file = motion_file::<LittleEndian>(false);
+        file[4] = 2;
+        file[5] = 3;
+        match Xsm::from_bytes(&file) {
+            Err(XsmError::UnsupportedVersion {
+                chunk_type: None,
+                offset: 4,
+                major_version: 2,
+                minor_version: 3,
+            }) => {}
+            other => panic!("expected unsupported version, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_truncated_chunk_at_its_start() {
+        let mut file = motion_file::<LittleEndian>(false);
+        let chunk_start = file.len();
+        chunk::<LittleEndian>(&mut file, 202, 1, &[0; 8]);
+        file.truncate(file.len() - 4);
+        match Xsm::from_bytes(&file) {
+            Err(XsmError::TruncatedChunk {
+                chunk_type: Some(202),
+                offset,
+            }) => assert_eq!(offset, chunk_start as u64),
+            other => panic!("expected truncated chunk, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_inconsistent_count_inside_its_chunk() {
+        let mut file = b"XSM ".to_vec();
+        file.extend_from_slice(&[1, 0, 0, 0]);
+        chunk::<LittleEndian>(&mut file, 202, 1, &(-1i32).to_le_bytes());
+        match Xsm::from_bytes(&file) {
+            Err(err @ XsmError::InconsistentCount { .. }) => {
+                assert_eq!(err.chunk_type(), Some(202));
+                assert_eq!(err.offset(), 24);
             }
+            other => panic!("expected inconsistent count, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_little_endian_motion() {
+        let xsm = Xsm::from_bytes(&motion_file::<LittleEndian>(false)).unwrap();
+        assert!(!xsm.header.big_endian);
+        assert_eq!(xsm.metadata.fps, 30);
+        assert_eq!(xsm.metadata.motion_name, "walk");
+        assert_eq!(xsm.bone_animation.num_submotion, 2);
+        let root = &xsm.bone_animation.skeletal_submotion[0];
+        assert_eq!(root.node_name, "root");
+        assert_eq!(root.pos_key[1].pos.x, 2.0);
+        assert_eq!(root.rot_key[1].rot.z, 23170);
+        assert_eq!(root.scale_key[1].time, 1.0);
+        assert_eq!(root.scale_rot_key.len(), 1);
+        let child = &xsm.bone_animation.skeletal_submotion[1];
+        assert_eq!(child.node_name, "child");
+        assert_eq!(child.pose_pos.z, 3.0);
+        assert!(child.pos_key.is_empty());
+        assert_eq!(xsm.unknown_chunks[0].chunk_type, 77);
+        assert_eq!(xsm.unknown_chunks[0].data, b"unknown");
+    }
+
+    #[test]
+    fn parses_big_endian_motion_like_little_endian() {
+        let little = Xsm::from_bytes(&motion_file::<LittleEndian>(false)).unwrap();
+        let mut big = Xsm::from_bytes(&motion_file::<BigEndian>(true)).unwrap();
+        assert!(big.header.big_endian);
+        big.header.big_endian = false;
+        assert_eq!(
+            serde_json::to_value(&big).unwrap(),
+            serde_json::to_value(&little).unwrap()
+        );
+    }
 
-            for _ in 0..submotion.num_scale_rot_keys {
-                submotion.scale_rot_key.push(XsmScaleRotKey {
-                    rot: xsm_read_quaternion16(file),
-                    time: file.read_f32::<LittleEndian>().unwrap(),
-                })
+    #[test]
+    fn reports_big_endian_errors_at_the_same_offsets() {
+        let mut file = b"XSM ".to_vec();
+        file.extend_from_slice(&[1, 0, 1, 0]);
+        chunk::<BigEndian>(&mut file, 202, 1, &(-1i32).to_be_bytes());
+        match Xsm::from_bytes(&file) {
+            Err(err @ XsmError::InconsistentCount { .. }) => {
+                assert_eq!(err.chunk_type(), Some(202));
+                assert_eq!(err.offset(), 24);
             }
-            submotion
-        });
+            other => panic!("expected inconsistent count, got {:?}", other),
+        }
+
+        let mut file = b"XSM ".to_vec();
+        file.extend_from_slice(&[1, 0, 1, 0]);
+        chunk::<BigEndian>(&mut file, 201, 1, &[0; 16]);
+        file.truncate(file.len() - 1);
+        match Xsm::from_bytes(&file) {
+            Err(XsmError::TruncatedChunk {
+                chunk_type: Some(201),
+                offset: 8,
+            }) => {}
+            other => panic!("expected truncated chunk, got {:?}", other),
+        }
     }
 
-    xsm
+    #[test]
+    fn rejects_key_times_out_of_range() {
+        for time in [f32::NAN, f32::INFINITY, -1.0, 1e9] {
+            let mut xsm = Xsm::from_bytes(&motion_file::<LittleEndian>(false)).unwrap();
+            xsm.bone_animation.skeletal_submotion[0].rot_key[1].time = time;
+            match Xsm::from_bytes(&xsm.to_bytes().unwrap()) {
+                Err(err @ XsmError::InconsistentCount { .. }) => {
+                    assert_eq!(err.chunk_type(), Some(202));
+                }
+                other => panic!("expected inconsistent count for {}, got {:?}", time, other),
+            }
+        }
+    }
 }
@@ -0,0 +1,374 @@
+#![allow(dead_code)]
+use crate::xsm::xsm_structs::{
+    Xsm, XsmDecodedRotKey, XsmDecodedSubMotion, XsmLocalTransform, XsmQuaternion,
+};
+use crate::xsm::xsm_util::{xsm_lerp_vec3d, xsm_slerp_quaternion};
+
+// Frame rate used when the metadata does not carry a usable one.
+const XSM_DEFAULT_FPS: i32 = 30;
+
+// Samples local node transforms of a motion at arbitrary times. Keys are
+// decoded once up front and are expected in ascending time order, as
+// exported. Times outside the keys are clamped to the first or last key.
+// Key times that are not finite, which the parser rejects but edited
+// motions may hold, do not count towards the duration.
+pub(crate) struct XsmSampler {
+    pub(crate) submotions: Vec<XsmDecodedSubMotion>,
+    pub(crate) fps: i32,
+    pub(crate) duration: f32,
+}
+
+impl XsmSampler {
+    pub fn new(xsm: &Xsm) -> XsmSampler {
+        let submotions = xsm.decoded_submotions();
+        let duration = submotions
+            .iter()
+            .flat_map(|submotion| {
+                submotion
+                    .pos_key
+                    .iter()
+                    .map(|key| key.time)
+                    .chain(submotion.rot_key.iter().map(|key| key.time))
+                    .chain(submotion.scale_key.iter().map(|key| key.time))
+                    .chain(submotion.scale_rot_key.iter().map(|key| key.time))
+            })
+            .filter(|time| time.is_finite())
+            .fold(0.0f32, f32::max);
+        let fps = if xsm.metadata.fps > 0 {
+            xsm.metadata.fps
+        } else {
+            XSM_DEFAULT_FPS
+        };
+        XsmSampler {
+            submotions,
+            fps,
+            duration,
+        }
+    }
+
+    // Number of frames needed to cover the clip at `fps`, both ends included.
+    pub fn num_frames(&self) -> usize {
+        ((self.duration * self.fps as f32).ceil() as usize).saturating_add(1)
+    }
+
+    pub fn frame_time(&self, frame: usize) -> f32 {
+        (frame as f32 / self.fps as f32).min(self.duration)
+    }
+
+    pub fn submotion_index(&self, node_name: &str) -> Option<usize> {
+        self.submotions
+            .iter()
+            .position(|submotion| submotion.node_name == node_name)
+    }
+
+    pub fn sample(&self, node_name: &str, time: f32) -> Option<XsmLocalTransform> {
+        self.submotion_index(node_name)
+            .map(|index| xsm_sample_submotion(&self.submotions[index], time))
+    }
+
+    // Samples every submotion, in submotion order.
+    pub fn sample_all(&self, time: f32) -> Vec<XsmLocalTransform> {
+        self.submotions
+            .iter()
+            .map(|submotion| xsm_sample_submotion(submotion, time))
+            .collect()
+    }
+}
+
+// Tracks without keys hold the submotion's pose value.
+pub(crate) fn xsm_sample_submotion(
+    submotion: &XsmDecodedSubMotion,
+    time: f32,
+) -> XsmLocalTransform {
+    let pos = sample_track(
+        &submotion.pos_key,
+        time,
+        |key| key.time,
+        |key| &key.pos,
+        xsm_lerp_vec3d,
+    );
+    let scale = sample_track(
+        &submotion.scale_key,
+        time,
+        |key| key.time,
+        |key| &key.scale,
+        xsm_lerp_vec3d,
+    );
+    XsmLocalTransform {
+        pos: pos.unwrap_or_else(|| submotion.pose_pos.clone()),
+        rot: sample_rotation(&submotion.rot_key, time)
+            .unwrap_or_else(|| submotion.pose_rot.clone()),
+        scale: scale.unwrap_or_else(|| submotion.pose_scale.clone()),
+        scale_rot: sample_rotation(&submotion.scale_rot_key, time)
+            .unwrap_or_else(|| submotion.pose_scale_rot.clone()),
+    }
+}
+
+fn sample_rotation(keys: &[XsmDecodedRotKey], time: f32) -> Option<XsmQuaternion> {
+    sample_track(
+        keys,
+        time,
+        |key| key.time,
+        |key| &key.rot,
+        xsm_slerp_quaternion,
+    )
+}
+
+fn sample_track<K, V: Clone>(
+    keys: &[K],
+    time: f32,
+    key_time: impl Fn(&K) -> f32,
+    key_value: impl Fn(&K) -> &V,
+    interpolate: impl Fn(&V, &V, f32) -> V,
+) -> Option<V> {
+    let first = keys.first()?;
+    let last = keys.last()?;
+    if time <= key_time(first) {
+        return Some(key_value(first).clone());
+    }
+    if time >= key_time(last) {
+        return Some(key_value(last).clone());
+    }
+
+    // A NaN `time` or key time can pass the checks above without a key on
+    // both sides of it.
+    let next = keys.partition_point(|key| key_time(key) <= time);
+    if next == 0 {
+        return Some(key_value(first).clone());
+    }
+    if next == keys.len() {
+        return Some(key_value(last).clone());
+    }
+    let from = &keys[next - 1];
+    let to = &keys[next];
+    let span = key_time(to) - key_time(from);
+    if span.is_nan() || span <= 0.0 {
+        return Some(key_value(to).clone());
+    }
+    let t = (time - key_time(from)) / span;
+    Some(interpolate(key_value(from), key_value(to), t))
+}
+
+impl Xsm {
+    pub fn sampler(&self) -> XsmSampler {
+        XsmSampler::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xsm::xsm_structs::{XsmPosKey, XsmScaleKey, XsmVec3d};
+    use crate::xsm::xsm_test_util::motion_file;
+    use byteorder::LittleEndian;
+
+    fn vec3d(x: f32, y: f32, z: f32) -> XsmVec3d {
+        XsmVec3d { x, y, z }
+    }
+
+    fn quaternion(x: f32, y: f32, z: f32, w: f32) -> XsmQuaternion {
+        XsmQuaternion { x, y, z, w }
+    }
+
+    fn submotion(node_name: &str) -> XsmDecodedSubMotion {
+        XsmDecodedSubMotion {
+            node_name: node_name.to_string(),
+            pose_rot: quaternion(1.0, 0.0, 0.0, 0.0),
+            bind_pose_rot: quaternion(0.0, 0.0, 0.0, 1.0),
+            pose_scale_rot: quaternion(0.0, 1.0, 0.0, 0.0),
+            bind_pose_scale_rot: quaternion(0.0, 0.0, 0.0, 1.0),
+            pose_pos: vec3d(1.0, 2.0, 3.0),
+            pose_scale: vec3d(4.0, 5.0, 6.0),
+            bind_pose_pos: vec3d(0.0, 0.0, 0.0),
+            bind_pose_scale_pos: vec3d(1.0, 1.0, 1.0),
+            max_error: 0.0,
+            pos_key: vec![],
+            rot_key: vec![],
+            scale_key: vec![],
+            scale_rot_key: vec![],
+        }
+    }
+
+    fn keyed_submotion() -> XsmDecodedSubMotion {
+        let mut keyed = submotion("keyed");
+        keyed.pos_key = vec![
+            XsmPosKey {
+                pos: vec3d(0.0, 0.0, 0.0),
+                time: 1.0,
+            },
+            XsmPosKey {
+                pos: vec3d(2.0, -4.0, 8.0),
+                time: 2.0,
+            },
+            XsmPosKey {
+                pos: vec3d(2.0, 0.0, 0.0),
+                time: 4.0,
+            },
+        ];
+        keyed.scale_key = vec![
+            XsmScaleKey {
+                scale: vec3d(1.0, 1.0, 1.0),
+                time: 1.0,
+            },
+            XsmScaleKey {
+                scale: vec3d(3.0, 3.0, 3.0),
+                time: 3.0,
+            },
+        ];
+        keyed
+    }
+
+    fn components(value: &XsmVec3d) -> [f32; 3] {
+        [value.x, value.y, value.z]
+    }
+
+    fn assert_quaternion(actual: &XsmQuaternion, expected: [f32; 4]) {
+        let actual = [actual.x, actual.y, actual.z, actual.w];
+        for (actual_value, expected_value) in actual.iter().zip(expected.iter()) {
+            assert!(
+                (actual_value - expected_value).abs() < 1e-5,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn interpolates_between_keys() {
+        let keyed = keyed_submotion();
+        let table = [
+            (1.0, [0.0, 0.0, 0.0], [1.0, 1.0, 1.0]),
+            (1.5, [1.0, -2.0, 4.0], [1.5, 1.5, 1.5]),
+            (2.0, [2.0, -4.0, 8.0], [2.0, 2.0, 2.0]),
+            (3.0, [2.0, -2.0, 4.0], [3.0, 3.0, 3.0]),
+        ];
+        for (time, pos, scale) in table {
+            let sampled = xsm_sample_submotion(&keyed, time);
+            assert_eq!(components(&sampled.pos), pos, "position at {}", time);
+            assert_eq!(components(&sampled.scale), scale, "scale at {}", time);
+        }
+    }
+
+    #[test]
+    fn clamps_outside_the_keys() {
+        let keyed = keyed_submotion();
+        for time in [-1.0, 0.0, 0.999] {
+            let sampled = xsm_sample_submotion(&keyed, time);
+            assert_eq!(components(&sampled.pos), [0.0, 0.0, 0.0]);
+            assert_eq!(components(&sampled.scale), [1.0, 1.0, 1.0]);
+        }
+        for time in [4.0, 4.5, 100.0] {
+            let sampled = xsm_sample_submotion(&keyed, time);
+            assert_eq!(components(&sampled.pos), [2.0, 0.0, 0.0]);
+            assert_eq!(components(&sampled.scale), [3.0, 3.0, 3.0]);
+        }
+    }
+
+    #[test]
+    fn falls_back_to_pose_without_keys() {
+        let keyed = keyed_submotion();
+        for time in [0.0, 2.5] {
+            let sampled = xsm_sample_submotion(&keyed, time);
+            assert_quaternion(&sampled.rot, [1.0, 0.0, 0.0, 0.0]);
+            assert_quaternion(&sampled.scale_rot, [0.0, 1.0, 0.0, 0.0]);
+        }
+        let sampled = xsm_sample_submotion(&submotion("still"), 1.0);
+        assert_eq!(components(&sampled.pos), [1.0, 2.0, 3.0]);
+        assert_eq!(components(&sampled.scale), [4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn slerps_along_the_shortest_path() {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let mut rotating = submotion("rotating");
+        // The second key is 90 degrees about z with every component negated,
+        // which is the same rotation reached the short way.
+        rotating.rot_key = vec![
+            XsmDecodedRotKey {
+                rot: quaternion(0.0, 0.0, 0.0, 1.0),
+                time: 0.0,
+            },
+            XsmDecodedRotKey {
+                rot: quaternion(0.0, 0.0, -half, -half),
+                time: 1.0,
+            },
+        ];
+        let eighth = std::f32::consts::FRAC_PI_8;
+        let sampled = xsm_sample_submotion(&rotating, 0.5);
+        assert_quaternion(&sampled.rot, [0.0, 0.0, eighth.sin(), eighth.cos()]);
+        let sampled = xsm_sample_submotion(&rotating, 0.25);
+        let sixteenth = eighth / 2.0;
+        assert_quaternion(&sampled.rot, [0.0, 0.0, sixteenth.sin(), sixteenth.cos()]);
+    }
+
+    #[test]
+    fn samples_by_node_name() {
+        let sampler = XsmSampler {
+            submotions: vec![submotion("still"), keyed_submotion()],
+            fps: 30,
+            duration: 4.0,
+        };
+        assert_eq!(sampler.submotion_index("keyed"), Some(1));
+        assert!(sampler.sample("missing", 0.0).is_none());
+        let sampled = sampler.sample("keyed", 1.5).unwrap();
+        assert_eq!(components(&sampled.pos), [1.0, -2.0, 4.0]);
+        let all = sampler.sample_all(1.5);
+        assert_eq!(all.len(), 2);
+        assert_eq!(components(&all[0].pos), [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn duration_covers_the_last_key_of_any_track() {
+        let mut xsm = Xsm::from_bytes(&motion_file::<LittleEndian>(false)).unwrap();
+        let sampler = xsm.sampler();
+        assert_eq!(sampler.duration, 1.0);
+        assert_eq!(sampler.fps, 30);
+        assert_eq!(sampler.num_frames(), 31);
+        assert_eq!(sampler.frame_time(15), 0.5);
+        assert_eq!(sampler.frame_time(31), 1.0);
+
+        xsm.bone_animation.skeletal_submotion[0].scale_key[1].time = 2.5;
+        xsm.metadata.fps = 0;
+        let sampler = xsm.sampler();
+        assert_eq!(sampler.duration, 2.5);
+        assert_eq!(sampler.fps, XSM_DEFAULT_FPS);
+        assert_eq!(sampler.num_frames(), 76);
+    }
+
+    #[test]
+    fn clamps_nan_times_and_key_times() {
+        let keyed = keyed_submotion();
+        let sampled = xsm_sample_submotion(&keyed, f32::NAN);
+        assert_eq!(components(&sampled.pos), [0.0, 0.0, 0.0]);
+        assert_eq!(components(&sampled.scale), [1.0, 1.0, 1.0]);
+
+        // Corrupt key times are stepped over instead of interpolated with.
+        for index in 0..keyed.pos_key.len() {
+            let mut corrupt = keyed_submotion();
+            corrupt.pos_key[index].time = f32::NAN;
+            for time in [0.0, 1.5, 3.0, 5.0] {
+                let pos = components(&xsm_sample_submotion(&corrupt, time).pos);
+                assert!(
+                    pos.iter().all(|value| value.is_finite()),
+                    "{:?} at {} with key {} corrupt",
+                    pos,
+                    time,
+                    index
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ignores_key_times_that_are_not_finite() {
+        let mut xsm = Xsm::from_bytes(&motion_file::<LittleEndian>(false)).unwrap();
+        let mut submotions = xsm.decoded_submotions();
+        submotions[0].pos_key[1].time = f32::INFINITY;
+        submotions[0].scale_key[1].time = f32::NAN;
+        xsm.set_decoded_submotions(&submotions);
+        let sampler = xsm.sampler();
+        assert_eq!(sampler.duration, 1.0);
+        assert_eq!(sampler.num_frames(), 31);
+    }
+}
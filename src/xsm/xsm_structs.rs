@@ -1,14 +1,14 @@
 #![allow(dead_code)]
 
 use serde::{Deserialize, Serialize};
-#[derive(Debug,Serialize,Deserialize)]
+#[derive(Debug,Clone,Serialize,Deserialize)]
 pub(crate) struct XsmVec3d {
     pub(crate) x: f32,
     pub(crate) y: f32,
     pub(crate) z: f32,
 }
 
-#[derive(Debug,Serialize,Deserialize)]
+#[derive(Debug,Clone,Serialize,Deserialize)]
 pub(crate) struct XsmQuaternion16 {
     pub(crate) x: i16,
     pub(crate) y: i16,
@@ -16,11 +16,20 @@ pub(crate) struct XsmQuaternion16 {
     pub(crate) w: i16,
 }
 
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub(crate) struct XsmQuaternion {
+    pub(crate) x: f32,
+    pub(crate) y: f32,
+    pub(crate) z: f32,
+    pub(crate) w: f32,
+}
+
 #[derive(Debug,Serialize,Deserialize)]
 pub struct Xsm {
     pub(crate) header: XsmHeader,
     pub(crate) metadata: XsmMetadata,
     pub(crate) bone_animation: XsmBoneAnimation,
+    pub(crate) unknown_chunks: Vec<XsmUnknownChunk>,
 }
 
 #[derive(Debug,Serialize,Deserialize)]
@@ -39,8 +48,23 @@ pub(crate) struct XsmChunk {
     pub(crate) version: i32,
 }
 
+// A chunk the parser does not read, kept byte for byte. `offset` is where
+// its chunk header started in the parsed file and is not used when writing:
+// the writer puts unknown chunks after all known chunks, in the order they
+// were read.
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub(crate) struct XsmUnknownChunk {
+    pub(crate) chunk_type: i32,
+    pub(crate) version: i32,
+    pub(crate) offset: u64,
+    pub(crate) data: Vec<u8>,
+}
+
+// `version` is the chunk version the metadata was read with, the writer
+// stores it back unchanged. The same goes for XsmBoneAnimation.
 #[derive(Debug,Serialize,Deserialize)]
 pub(crate) struct XsmMetadata {
+    pub(crate) version: i32,
     pub(crate) unused: f32,
     pub(crate) max_acceptable_error: f32,
     pub(crate) fps: i32,
@@ -54,6 +78,7 @@ pub(crate) struct XsmMetadata {
 
 #[derive(Debug,Serialize,Deserialize)]
 pub(crate) struct XsmBoneAnimation {
+    pub(crate) version: i32,
     pub(crate) num_submotion: i32,
     pub(crate) skeletal_submotion: Vec<XsmSubMotion>,
 }
@@ -82,7 +107,7 @@ pub(crate) struct XsmSubMotion {
 }
 
 #[allow(dead_code)]
-#[derive(Debug,Serialize,Deserialize)]
+#[derive(Debug,Clone,Serialize,Deserialize)]
 pub(crate) struct XsmPosKey {
     pub(crate) pos: XsmVec3d,
     pub(crate) time: f32,
@@ -96,7 +121,7 @@ pub(crate) struct XsmRotKey {
 }
 
 #[allow(dead_code)]
-#[derive(Debug,Serialize,Deserialize)]
+#[derive(Debug,Clone,Serialize,Deserialize)]
 pub(crate) struct XsmScaleKey {
     pub(crate) scale: XsmVec3d,
     pub(crate) time: f32,
@@ -108,3 +133,48 @@ pub(crate) struct XsmScaleRotKey {
     pub(crate) rot: XsmQuaternion16,
     pub(crate) time: f32,
 }
+
+// XsmSubMotion with every 16-bit quaternion decoded to a normalized float
+// quaternion. Rotation and scale rotation keys share the decoded key type.
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub(crate) struct XsmDecodedSubMotion {
+    pub(crate) node_name: String,
+    pub(crate) pose_rot: XsmQuaternion,
+    pub(crate) bind_pose_rot: XsmQuaternion,
+    pub(crate) pose_scale_rot: XsmQuaternion,
+    pub(crate) bind_pose_scale_rot: XsmQuaternion,
+    pub(crate) pose_pos: XsmVec3d,
+    pub(crate) pose_scale: XsmVec3d,
+    pub(crate) bind_pose_pos: XsmVec3d,
+    pub(crate) bind_pose_scale_pos: XsmVec3d,
+    pub(crate) max_error: f32,
+    pub(crate) pos_key: Vec<XsmPosKey>,
+    pub(crate) rot_key: Vec<XsmDecodedRotKey>,
+    pub(crate) scale_key: Vec<XsmScaleKey>,
+    pub(crate) scale_rot_key: Vec<XsmDecodedRotKey>,
+}
+
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub(crate) struct XsmDecodedRotKey {
+    pub(crate) rot: XsmQuaternion,
+    pub(crate) time: f32,
+}
+
+// Local transform of a node at a point in time, as returned by the sampler.
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub(crate) struct XsmLocalTransform {
+    pub(crate) pos: XsmVec3d,
+    pub(crate) rot: XsmQuaternion,
+    pub(crate) scale: XsmVec3d,
+    pub(crate) scale_rot: XsmQuaternion,
+}
+
+// Mapping between the submotions of a motion and the nodes of an actor,
+// matched by name. Indices refer to `skeletal_submotion` and `nodes`.
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub(crate) struct XsmBinding {
+    pub(crate) node_submotions: Vec<Option<usize>>,
+    pub(crate) submotion_nodes: Vec<Option<usize>>,
+    pub(crate) unmatched_submotions: Vec<String>,
+    pub(crate) unmatched_nodes: Vec<String>,
+}
@@ -0,0 +1,65 @@
+#![allow(dead_code)]
+use crate::xac::xac_test_util::{chunk, floats, string};
+use byteorder::{ByteOrder, WriteBytesExt};
+
+pub(crate) fn quaternion16<E: ByteOrder>(data: &mut Vec<u8>, value: [i16; 4]) {
+    for component in value {
+        data.write_i16::<E>(component).unwrap();
+    }
+}
+
+// A motion in the writer's chunk order: metadata (chunk version 2), a bone
+// animation with a "root" submotion keyed on every track and a "child"
+// submotion without keys, and an unknown chunk.
+pub(crate) fn motion_file<E: ByteOrder>(big_endian: bool) -> Vec<u8> {
+    let mut file = b"XSM ".to_vec();
+    file.extend_from_slice(&[1, 0, big_endian as u8, 0]);
+
+    let mut metadata = Vec::new();
+    floats::<E>(&mut metadata, &[0.0, 0.5]);
+    metadata.write_i32::<E>(30).unwrap();
+    metadata.extend_from_slice(&[1, 2, 0, 0]);
+    for text in ["app", "walk.max", "date", "walk"] {
+        string::<E>(&mut metadata, text);
+    }
+    chunk::<E>(&mut file, 201, 2, &metadata);
+
+    let identity = [0, 0, 0, 32767];
+    let mut animation = Vec::new();
+    animation.write_i32::<E>(2).unwrap();
+
+    for rotation in [identity, identity, identity, identity] {
+        quaternion16::<E>(&mut animation, rotation);
+    }
+    floats::<E>(&mut animation, &[0.0, 1.0, 0.0, 1.0, 1.0, 1.0]);
+    floats::<E>(&mut animation, &[0.0, 1.0, 0.0, 1.0, 1.0, 1.0]);
+    for count in [2, 2, 2, 1] {
+        animation.write_i32::<E>(count).unwrap();
+    }
+    floats::<E>(&mut animation, &[0.001]);
+    string::<E>(&mut animation, "root");
+    floats::<E>(&mut animation, &[0.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 1.0]);
+    quaternion16::<E>(&mut animation, identity);
+    floats::<E>(&mut animation, &[0.0]);
+    quaternion16::<E>(&mut animation, [0, 0, 23170, 23170]);
+    floats::<E>(&mut animation, &[1.0]);
+    floats::<E>(&mut animation, &[1.0, 1.0, 1.0, 0.0, 3.0, 3.0, 3.0, 1.0]);
+    quaternion16::<E>(&mut animation, identity);
+    floats::<E>(&mut animation, &[0.0]);
+
+    let child_rotation = [23170, 0, 0, 23170];
+    for rotation in [child_rotation, identity, identity, identity] {
+        quaternion16::<E>(&mut animation, rotation);
+    }
+    floats::<E>(&mut animation, &[1.0, 2.0, 3.0, 1.0, 1.0, 1.0]);
+    floats::<E>(&mut animation, &[1.0, 2.0, 3.0, 1.0, 1.0, 1.0]);
+    for count in [0, 0, 0, 0] {
+        animation.write_i32::<E>(count).unwrap();
+    }
+    floats::<E>(&mut animation, &[0.001]);
+    string::<E>(&mut animation, "child");
+    chunk::<E>(&mut file, 202, 1, &animation);
+
+    chunk::<E>(&mut file, 77, 3, b"unknown");
+    file
+}
@@ -0,0 +1,338 @@
+#![allow(dead_code)]
+use crate::xsm::xsm_structs::{
+    Xsm, XsmDecodedRotKey, XsmDecodedSubMotion, XsmQuaternion, XsmQuaternion16, XsmRotKey,
+    XsmScaleRotKey, XsmSubMotion, XsmVec3d,
+};
+
+// Components are stored as signed 16-bit fractions of 32767. Rounding can
+// leave the result slightly off unit length, so it is normalized again.
+pub(crate) fn xsm_decompress_quaternion16(value: &XsmQuaternion16) -> XsmQuaternion {
+    xsm_normalize_quaternion(&XsmQuaternion {
+        x: value.x as f32 / 32767.0,
+        y: value.y as f32 / 32767.0,
+        z: value.z as f32 / 32767.0,
+        w: value.w as f32 / 32767.0,
+    })
+}
+
+pub(crate) fn xsm_compress_quaternion16(value: &XsmQuaternion) -> XsmQuaternion16 {
+    let value = xsm_normalize_quaternion(value);
+    let compress = |component: f32| (component * 32767.0).round().clamp(-32767.0, 32767.0) as i16;
+    XsmQuaternion16 {
+        x: compress(value.x),
+        y: compress(value.y),
+        z: compress(value.z),
+        w: compress(value.w),
+    }
+}
+
+// A zero quaternion has no rotation to keep and becomes the identity.
+pub(crate) fn xsm_normalize_quaternion(value: &XsmQuaternion) -> XsmQuaternion {
+    let length =
+        (value.x * value.x + value.y * value.y + value.z * value.z + value.w * value.w).sqrt();
+    if length <= f32::EPSILON {
+        return XsmQuaternion {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        };
+    }
+    XsmQuaternion {
+        x: value.x / length,
+        y: value.y / length,
+        z: value.z / length,
+        w: value.w / length,
+    }
+}
+
+pub(crate) fn xsm_lerp_vec3d(from: &XsmVec3d, to: &XsmVec3d, t: f32) -> XsmVec3d {
+    XsmVec3d {
+        x: from.x + (to.x - from.x) * t,
+        y: from.y + (to.y - from.y) * t,
+        z: from.z + (to.z - from.z) * t,
+    }
+}
+
+// Interpolates along the shortest arc. Nearly parallel quaternions fall back
+// to a normalized lerp, where the slerp weights become unstable.
+pub(crate) fn xsm_slerp_quaternion(
+    from: &XsmQuaternion,
+    to: &XsmQuaternion,
+    t: f32,
+) -> XsmQuaternion {
+    let mut dot = from.x * to.x + from.y * to.y + from.z * to.z + from.w * to.w;
+    let sign = if dot < 0.0 { -1.0 } else { 1.0 };
+    dot *= sign;
+
+    let (from_weight, to_weight) = if dot > 0.9995 {
+        (1.0 - t, t)
+    } else {
+        let angle = dot.acos();
+        let sin_angle = angle.sin();
+        (
+            ((1.0 - t) * angle).sin() / sin_angle,
+            (t * angle).sin() / sin_angle,
+        )
+    };
+    let to_weight = to_weight * sign;
+    xsm_normalize_quaternion(&XsmQuaternion {
+        x: from.x * from_weight + to.x * to_weight,
+        y: from.y * from_weight + to.y * to_weight,
+        z: from.z * from_weight + to.z * to_weight,
+        w: from.w * from_weight + to.w * to_weight,
+    })
+}
+
+impl XsmSubMotion {
+    pub fn decode(&self) -> XsmDecodedSubMotion {
+        XsmDecodedSubMotion {
+            node_name: self.node_name.clone(),
+            pose_rot: xsm_decompress_quaternion16(&self.pose_rot),
+            bind_pose_rot: xsm_decompress_quaternion16(&self.bind_pose_rot),
+            pose_scale_rot: xsm_decompress_quaternion16(&self.pose_scale_rot),
+            bind_pose_scale_rot: xsm_decompress_quaternion16(&self.bind_pose_scale_rot),
+            pose_pos: self.pose_pos.clone(),
+            pose_scale: self.pose_scale.clone(),
+            bind_pose_pos: self.bind_pose_pos.clone(),
+            bind_pose_scale_pos: self.bind_pose_scale_pos.clone(),
+            max_error: self.max_error,
+            pos_key: self.pos_key.clone(),
+            rot_key: self
+                .rot_key
+                .iter()
+                .map(|key| XsmDecodedRotKey {
+                    rot: xsm_decompress_quaternion16(&key.rot),
+                    time: key.time,
+                })
+                .collect(),
+            scale_key: self.scale_key.clone(),
+            scale_rot_key: self
+                .scale_rot_key
+                .iter()
+                .map(|key| XsmDecodedRotKey {
+                    rot: xsm_decompress_quaternion16(&key.rot),
+                    time: key.time,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl XsmDecodedSubMotion {
+    // Inverse of XsmSubMotion::decode. Key counts follow the key lists.
+    pub fn encode(&self) -> XsmSubMotion {
+        XsmSubMotion {
+            pose_rot: xsm_compress_quaternion16(&self.pose_rot),
+            bind_pose_rot: xsm_compress_quaternion16(&self.bind_pose_rot),
+            pose_scale_rot: xsm_compress_quaternion16(&self.pose_scale_rot),
+            bind_pose_scale_rot: xsm_compress_quaternion16(&self.bind_pose_scale_rot),
+            pose_pos: self.pose_pos.clone(),
+            pose_scale: self.pose_scale.clone(),
+            bind_pose_pos: self.bind_pose_pos.clone(),
+            bind_pose_scale_pos: self.bind_pose_scale_pos.clone(),
+            num_pos_keys: self.pos_key.len() as i32,
+            num_rot_keys: self.rot_key.len() as i32,
+            num_scale_keys: self.scale_key.len() as i32,
+            num_scale_rot_keys: self.scale_rot_key.len() as i32,
+            max_error: self.max_error,
+            node_name: self.node_name.clone(),
+            pos_key: self.pos_key.clone(),
+            rot_key: self
+                .rot_key
+                .iter()
+                .map(|key| XsmRotKey {
+                    rot: xsm_compress_quaternion16(&key.rot),
+                    time: key.time,
+                })
+                .collect(),
+            scale_key: self.scale_key.clone(),
+            scale_rot_key: self
+                .scale_rot_key
+                .iter()
+                .map(|key| XsmScaleRotKey {
+                    rot: xsm_compress_quaternion16(&key.rot),
+                    time: key.time,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Xsm {
+    pub fn decoded_submotions(&self) -> Vec<XsmDecodedSubMotion> {
+        self.bone_animation
+            .skeletal_submotion
+            .iter()
+            .map(|submotion| submotion.decode())
+            .collect()
+    }
+
+    // Replaces the submotions with encoded copies of `submotions`, so edits
+    // made on decoded submotions can be written.
+    pub fn set_decoded_submotions(&mut self, submotions: &[XsmDecodedSubMotion]) {
+        self.bone_animation.skeletal_submotion = submotions
+            .iter()
+            .map(|submotion| submotion.encode())
+            .collect();
+        self.bone_animation.num_submotion = submotions.len() as i32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quaternion16(x: i16, y: i16, z: i16, w: i16) -> XsmQuaternion16 {
+        XsmQuaternion16 { x, y, z, w }
+    }
+
+    fn components(value: &XsmQuaternion) -> [f32; 4] {
+        [value.x, value.y, value.z, value.w]
+    }
+
+    fn assert_close(actual: [f32; 4], expected: [f32; 4]) {
+        for (actual, expected) in actual.iter().zip(expected.iter()) {
+            assert!(
+                (actual - expected).abs() < 1e-5,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn decompresses_quaternion16() {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let table = [
+            // Identity.
+            (quaternion16(0, 0, 0, 32767), [0.0, 0.0, 0.0, 1.0]),
+            // Extremes map to exactly one.
+            (quaternion16(32767, 0, 0, 0), [1.0, 0.0, 0.0, 0.0]),
+            (quaternion16(0, -32767, 0, 0), [0.0, -1.0, 0.0, 0.0]),
+            // 90 degrees about z, scaled by 1/32767.
+            (quaternion16(0, 0, 23170, 23170), [0.0, 0.0, half, half]),
+            // Off unit length, normalized after scaling.
+            (quaternion16(0, 0, 0, 16384), [0.0, 0.0, 0.0, 1.0]),
+            (quaternion16(16384, 0, 0, 16384), [half, 0.0, 0.0, half]),
+            // A zero quaternion has no rotation and becomes the identity.
+            (quaternion16(0, 0, 0, 0), [0.0, 0.0, 0.0, 1.0]),
+        ];
+        for (compressed, expected) in table {
+            assert_close(
+                components(&xsm_decompress_quaternion16(&compressed)),
+                expected,
+            );
+        }
+    }
+
+    #[test]
+    fn compresses_quaternion16() {
+        let table = [
+            ([0.0, 0.0, 0.0, 1.0], [0, 0, 0, 32767]),
+            ([0.0, 0.0, 0.0, -1.0], [0, 0, 0, -32767]),
+            ([0.5, 0.5, 0.5, 0.5], [16384, 16384, 16384, 16384]),
+            // Normalized before scaling.
+            ([0.0, 0.0, 0.0, 4.0], [0, 0, 0, 32767]),
+            ([0.0, 0.0, 0.0, 0.0], [0, 0, 0, 32767]),
+        ];
+        for (value, expected) in table {
+            let compressed = xsm_compress_quaternion16(&XsmQuaternion {
+                x: value[0],
+                y: value[1],
+                z: value[2],
+                w: value[3],
+            });
+            assert_eq!(
+                [compressed.x, compressed.y, compressed.z, compressed.w],
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn decompress_then_compress_is_symmetric() {
+        let table = [
+            quaternion16(0, 0, 0, 32767),
+            quaternion16(0, 0, 0, -32767),
+            quaternion16(32767, 0, 0, 0),
+            quaternion16(0, 0, -32767, 0),
+            quaternion16(0, 0, 23170, 23170),
+            quaternion16(-23170, 0, 0, 23170),
+            quaternion16(16384, -16384, 16384, 16383),
+            quaternion16(9459, 18918, -9459, 23170),
+        ];
+        for compressed in table {
+            let round_trip = xsm_compress_quaternion16(&xsm_decompress_quaternion16(&compressed));
+            assert_eq!(
+                [round_trip.x, round_trip.y, round_trip.z, round_trip.w],
+                [compressed.x, compressed.y, compressed.z, compressed.w]
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_submotion_quaternions() {
+        let submotion = XsmSubMotion {
+            pose_rot: quaternion16(0, 0, 23170, 23170),
+            bind_pose_rot: quaternion16(0, 0, 0, 32767),
+            pose_scale_rot: quaternion16(0, 0, 0, 16384),
+            bind_pose_scale_rot: quaternion16(0, 0, 0, 0),
+            pose_pos: XsmVec3d {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+            },
+            pose_scale: XsmVec3d {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            },
+            bind_pose_pos: XsmVec3d {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            bind_pose_scale_pos: XsmVec3d {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            },
+            num_pos_keys: 0,
+            num_rot_keys: 1,
+            num_scale_keys: 0,
+            num_scale_rot_keys: 1,
+            max_error: 0.001,
+            node_name: "root".to_string(),
+            pos_key: vec![],
+            rot_key: vec![XsmRotKey {
+                rot: quaternion16(32767, 0, 0, 0),
+                time: 0.5,
+            }],
+            scale_key: vec![],
+            scale_rot_key: vec![XsmScaleRotKey {
+                rot: quaternion16(0, -32767, 0, 0),
+                time: 0.25,
+            }],
+        };
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let decoded = submotion.decode();
+        assert_eq!(decoded.node_name, "root");
+        assert_close(components(&decoded.pose_rot), [0.0, 0.0, half, half]);
+        assert_close(components(&decoded.bind_pose_rot), [0.0, 0.0, 0.0, 1.0]);
+        assert_close(components(&decoded.pose_scale_rot), [0.0, 0.0, 0.0, 1.0]);
+        assert_close(
+            components(&decoded.bind_pose_scale_rot),
+            [0.0, 0.0, 0.0, 1.0],
+        );
+        assert_close(components(&decoded.rot_key[0].rot), [1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(decoded.rot_key[0].time, 0.5);
+        assert_close(
+            components(&decoded.scale_rot_key[0].rot),
+            [0.0, -1.0, 0.0, 0.0],
+        );
+        assert_eq!(decoded.scale_rot_key[0].time, 0.25);
+        assert_eq!(decoded.pose_pos.z, 3.0);
+    }
+}
@@ -0,0 +1,248 @@
+#![allow(dead_code)]
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+
+use crate::xsm::xsm_enums::XsmChunkType::{XsmBoneAnimationId, XsmMetadataId};
+use crate::xsm::xsm_error::XsmError;
+use crate::xsm::xsm_structs::{Xsm, XsmQuaternion16, XsmVec3d};
+
+pub fn xsmwrite(path: &str, xsm: &Xsm) -> Result<(), XsmError> {
+    let mut xsm_file = BufWriter::new(File::create(path)?);
+    xsm.to_writer(&mut xsm_file)?;
+    xsm_file.flush()?;
+    Ok(())
+}
+
+impl Xsm {
+    pub fn to_writer<W: Write>(&self, writer: &mut W) -> Result<(), XsmError> {
+        writer.write_all(&self.to_bytes()?)?;
+        Ok(())
+    }
+
+    // Unknown chunks are appended after the metadata and bone animation, in
+    // the order they were read.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, XsmError> {
+        let mut bytes = Vec::new();
+        write_header(&mut bytes, self)?;
+        if self.header.big_endian {
+            write_chunks::<BigEndian>(&mut bytes, self)?;
+        } else {
+            write_chunks::<LittleEndian>(&mut bytes, self)?;
+        }
+        Ok(bytes)
+    }
+}
+
+fn xsm_write_string<W: Write, E: ByteOrder>(file: &mut W, text: &str) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for character in text.chars() {
+        if character as u32 > 0xff {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Character {:?} cannot be stored in an XSM string",
+                    character
+                ),
+            ));
+        }
+        bytes.push(character as u8);
+    }
+    file.write_i32::<E>(bytes.len() as i32)?;
+    file.write_all(&bytes)
+}
+
+fn xsm_write_quaternion16<W: Write, E: ByteOrder>(
+    file: &mut W,
+    value: &XsmQuaternion16,
+) -> io::Result<()> {
+    file.write_i16::<E>(value.x)?;
+    file.write_i16::<E>(value.y)?;
+    file.write_i16::<E>(value.z)?;
+    file.write_i16::<E>(value.w)
+}
+
+fn xsm_write_vec3d<W: Write, E: ByteOrder>(file: &mut W, value: &XsmVec3d) -> io::Result<()> {
+    file.write_f32::<E>(value.x)?;
+    file.write_f32::<E>(value.y)?;
+    file.write_f32::<E>(value.z)
+}
+
+fn write_header<W: Write>(file: &mut W, xsm: &Xsm) -> Result<(), XsmError> {
+    file.write_all(b"XSM ")?;
+    file.write_u8(xsm.header.major_version)?;
+    file.write_u8(xsm.header.minor_version)?;
+    file.write_u8(xsm.header.big_endian as u8)?;
+    file.write_u8(0)?; // Padding
+    Ok(())
+}
+
+fn write_chunks<E: ByteOrder>(file: &mut Vec<u8>, xsm: &Xsm) -> Result<(), XsmError> {
+    write_chunk::<E>(file, XsmMetadataId as i32, xsm.metadata.version, |chunk| {
+        write_metadata::<_, E>(chunk, xsm)
+    })?;
+    write_chunk::<E>(
+        file,
+        XsmBoneAnimationId as i32,
+        xsm.bone_animation.version,
+        |chunk| write_bone_animation::<_, E>(chunk, xsm),
+    )?;
+    for unknown_chunk in xsm.unknown_chunks.iter() {
+        write_chunk::<E>(
+            file,
+            unknown_chunk.chunk_type,
+            unknown_chunk.version,
+            |chunk| {
+                chunk.write_all(&unknown_chunk.data)?;
+                Ok(())
+            },
+        )?;
+    }
+    Ok(())
+}
+
+// Chunk data is written to a buffer first since the header stores its length.
+fn write_chunk<E: ByteOrder>(
+    file: &mut Vec<u8>,
+    chunk_type: i32,
+    version: i32,
+    write: impl FnOnce(&mut Vec<u8>) -> Result<(), XsmError>,
+) -> Result<(), XsmError> {
+    let chunk_start = file.len() as u64;
+    let mut data = Vec::new();
+    write(&mut data).map_err(|err| err.locate(Some(chunk_type), chunk_start))?;
+    if data.len() > i32::MAX as usize {
+        return Err(
+            XsmError::inconsistent("Chunk is too large").locate(Some(chunk_type), chunk_start)
+        );
+    }
+    file.write_i32::<E>(chunk_type)?;
+    file.write_i32::<E>(data.len() as i32)?;
+    file.write_i32::<E>(version)?;
+    file.write_all(&data)?;
+    Ok(())
+}
+
+fn write_metadata<W: Write, E: ByteOrder>(file: &mut W, xsm: &Xsm) -> Result<(), XsmError> {
+    file.write_f32::<E>(xsm.metadata.unused)?;
+    file.write_f32::<E>(xsm.metadata.max_acceptable_error)?;
+    file.write_i32::<E>(xsm.metadata.fps)?;
+    file.write_u8(xsm.metadata.exporter_major_version)?;
+    file.write_u8(xsm.metadata.exporter_minor_version)?;
+    file.write_u8(0)?; //Padding
+    file.write_u8(0)?; //Padding
+    xsm_write_string::<_, E>(file, &xsm.metadata.source_app)?;
+    xsm_write_string::<_, E>(file, &xsm.metadata.original_filename)?;
+    xsm_write_string::<_, E>(file, &xsm.metadata.export_date)?;
+    xsm_write_string::<_, E>(file, &xsm.metadata.motion_name)?;
+    Ok(())
+}
+
+// Submotion and key counts are taken from the key lists, so edited tracks do
+// not need their counters updated by hand.
+fn write_bone_animation<W: Write, E: ByteOrder>(file: &mut W, xsm: &Xsm) -> Result<(), XsmError> {
+    file.write_i32::<E>(xsm.bone_animation.skeletal_submotion.len() as i32)?;
+    for submotion in xsm.bone_animation.skeletal_submotion.iter() {
+        xsm_write_quaternion16::<_, E>(file, &submotion.pose_rot)?;
+        xsm_write_quaternion16::<_, E>(file, &submotion.bind_pose_rot)?;
+        xsm_write_quaternion16::<_, E>(file, &submotion.pose_scale_rot)?;
+        xsm_write_quaternion16::<_, E>(file, &submotion.bind_pose_scale_rot)?;
+        xsm_write_vec3d::<_, E>(file, &submotion.pose_pos)?;
+        xsm_write_vec3d::<_, E>(file, &submotion.pose_scale)?;
+        xsm_write_vec3d::<_, E>(file, &submotion.bind_pose_pos)?;
+        xsm_write_vec3d::<_, E>(file, &submotion.bind_pose_scale_pos)?;
+        file.write_i32::<E>(submotion.pos_key.len() as i32)?;
+        file.write_i32::<E>(submotion.rot_key.len() as i32)?;
+        file.write_i32::<E>(submotion.scale_key.len() as i32)?;
+        file.write_i32::<E>(submotion.scale_rot_key.len() as i32)?;
+        file.write_f32::<E>(submotion.max_error)?;
+        xsm_write_string::<_, E>(file, &submotion.node_name)?;
+
+        for key in submotion.pos_key.iter() {
+            xsm_write_vec3d::<_, E>(file, &key.pos)?;
+            file.write_f32::<E>(key.time)?;
+        }
+        for key in submotion.rot_key.iter() {
+            xsm_write_quaternion16::<_, E>(file, &key.rot)?;
+            file.write_f32::<E>(key.time)?;
+        }
+        for key in submotion.scale_key.iter() {
+            xsm_write_vec3d::<_, E>(file, &key.scale)?;
+            file.write_f32::<E>(key.time)?;
+        }
+        for key in submotion.scale_rot_key.iter() {
+            xsm_write_quaternion16::<_, E>(file, &key.rot)?;
+            file.write_f32::<E>(key.time)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::xsm::xsm_structs::Xsm;
+    use crate::xsm::xsm_test_util::motion_file;
+    use byteorder::{BigEndian, LittleEndian};
+
+    fn assert_round_trip(bytes: &[u8]) {
+        let parsed = Xsm::from_bytes(bytes).unwrap();
+        assert_eq!(parsed.metadata.version, 2);
+        assert_eq!(parsed.bone_animation.version, 1);
+
+        let written = parsed.to_bytes().unwrap();
+        assert_eq!(written, bytes);
+        let reparsed = Xsm::from_bytes(&written).unwrap();
+        assert_eq!(
+            serde_json::to_value(&parsed).unwrap(),
+            serde_json::to_value(&reparsed).unwrap()
+        );
+    }
+
+    #[test]
+    fn round_trip_little_endian() {
+        assert_round_trip(&motion_file::<LittleEndian>(false));
+    }
+
+    #[test]
+    fn round_trip_big_endian() {
+        assert_round_trip(&motion_file::<BigEndian>(true));
+    }
+
+    #[test]
+    fn round_trip_through_decoded_submotions() {
+        for (bytes, big_endian) in [
+            (motion_file::<LittleEndian>(false), false),
+            (motion_file::<BigEndian>(true), true),
+        ] {
+            let mut parsed = Xsm::from_bytes(&bytes).unwrap();
+            assert_eq!(parsed.header.big_endian, big_endian);
+            let decoded = parsed.decoded_submotions();
+            parsed.set_decoded_submotions(&decoded);
+            assert_eq!(parsed.to_bytes().unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn writes_edited_decoded_submotions() {
+        let mut parsed = Xsm::from_bytes(&motion_file::<LittleEndian>(false)).unwrap();
+        let mut decoded = parsed.decoded_submotions();
+        let key = decoded[0].rot_key.pop().unwrap();
+        decoded[0].rot_key[0].rot = key.rot;
+        decoded[1].pose_pos.x = 5.0;
+        parsed.set_decoded_submotions(&decoded);
+
+        let reparsed = Xsm::from_bytes(&parsed.to_bytes().unwrap()).unwrap();
+        let root = &reparsed.bone_animation.skeletal_submotion[0];
+        assert_eq!(root.num_rot_keys, 1);
+        assert_eq!(
+            (root.rot_key[0].rot.z, root.rot_key[0].rot.w),
+            (23170, 23170)
+        );
+        assert_eq!(
+            reparsed.bone_animation.skeletal_submotion[1].pose_pos.x,
+            5.0
+        );
+    }
+}